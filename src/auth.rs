@@ -0,0 +1,98 @@
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// a room claim of "*" grants access to every room
+const ANY_ROOM: &str = "*";
+// overflow rooms are named `<room>#<index>`, a claim on a room grants its overflow rooms too
+const OVERFLOW_SEPARATOR: char = '#';
+
+pub fn base_room(room: &str) -> &str {
+    room.split(OVERFLOW_SEPARATOR).next().unwrap_or(room)
+}
+
+pub fn overflow_room(room: &str, index: usize) -> String {
+    format!("{}{}{}", base_room(room), OVERFLOW_SEPARATOR, index)
+}
+
+#[derive(Debug, Clone)]
+pub struct Claims {
+    pub player: String,
+    pub room: String,
+}
+
+impl Claims {
+    pub fn allows(&self, room: &str) -> bool {
+        self.room == ANY_ROOM || self.room == base_room(room)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    Malformed,
+    BadSignature,
+    Forbidden,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Malformed => write!(f, "malformed token"),
+            AuthError::BadSignature => write!(f, "bad token signature"),
+            AuthError::Forbidden => write!(f, "token does not grant access to this room"),
+        }
+    }
+}
+
+// hook called on every join, implement it to plug an existing identity provider
+pub trait Authenticator: Send {
+    fn authenticate(&self, token: &str) -> Result<Claims, AuthError>;
+}
+
+// tokens look like `<player>:<room>:<hex hmac-sha256 of "<player>:<room>">`
+pub struct HmacAuthenticator {
+    key: Vec<u8>,
+}
+
+impl HmacAuthenticator {
+    pub fn new(key: &[u8]) -> HmacAuthenticator {
+        HmacAuthenticator {
+            key: key.to_vec(),
+        }
+    }
+
+    pub fn sign(&self, player: &str, room: &str) -> String {
+        let payload = format!("{}:{}", player, room);
+        let signature = self.mac(&payload).result().code();
+
+        format!("{}:{}", payload, hex::encode(signature))
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_varkey(&self.key).expect("HMAC accepts keys of any size");
+        mac.input(payload.as_bytes());
+        mac
+    }
+}
+
+impl Authenticator for HmacAuthenticator {
+    fn authenticate(&self, token: &str) -> Result<Claims, AuthError> {
+        let split = token.rfind(':').ok_or(AuthError::Malformed)?;
+        let (payload, signature) = (&token[..split], &token[split + 1..]);
+
+        let mut parts = payload.splitn(2, ':');
+        let player = parts.next().filter(|player| !player.is_empty()).ok_or(AuthError::Malformed)?;
+        let room = parts.next().filter(|room| !room.is_empty()).ok_or(AuthError::Malformed)?;
+
+        let signature = hex::decode(signature).map_err(|_| AuthError::Malformed)?;
+        self.mac(payload).verify(&signature).map_err(|_| AuthError::BadSignature)?;
+
+        Ok(Claims {
+            player: player.to_string(),
+            room: room.to_string(),
+        })
+    }
+}
@@ -0,0 +1,58 @@
+use na::{Isometry2, Vector2};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::algebra::Velocity2;
+use nphysics2d::object::{BodyHandle, BodyStatus};
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+
+// balls are the bulk of what spawns and despawns, projectiles above all, so their bodies are parked
+// instead of removed and handed to the next ball rather than adding one to the world,
+// they all share the same shape so nothing has to be rebuilt
+pub struct BodyPool {
+    parked: Vec<PhysicsBody>,
+    max: usize,
+}
+
+// parked bodies touch nothing and are skipped by the solver
+fn park(world: &mut World<f32>, physics_body: PhysicsBody) {
+    if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+        rigid_body.set_status(BodyStatus::Disabled);
+        rigid_body.set_velocity(Velocity2::zero());
+    }
+    world.collision_world_mut().set_collision_groups(physics_body.collider, CollisionGroups::new().with_membership(&[]).with_whitelist(&[]));
+}
+
+// for whatever kept a handle to a body that was since despawned, like a grapple hooked to it
+pub fn is_parked(world: &World<f32>, body: BodyHandle) -> bool {
+    world.rigid_body(body).map_or(false, |rigid_body| rigid_body.status() == BodyStatus::Disabled)
+}
+
+impl BodyPool {
+    pub fn new(max: usize) -> BodyPool {
+        BodyPool { parked: Vec::with_capacity(max), max }
+    }
+
+    // false when the pool is full, the body is left as it was for the caller to remove
+    pub fn put(&mut self, world: &mut World<f32>, physics_body: PhysicsBody) -> bool {
+        if self.parked.len() >= self.max {
+            return false;
+        }
+
+        park(world, physics_body);
+        self.parked.push(physics_body);
+        true
+    }
+
+    // the caller sets the collision groups back, like for a new body
+    pub fn take(&mut self, world: &mut World<f32>, position: Vector2<f32>) -> Option<PhysicsBody> {
+        let physics_body = self.parked.pop()?;
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            rigid_body.set_status(BodyStatus::Dynamic);
+            rigid_body.set_position(Isometry2::new(position, 0.0));
+            rigid_body.set_velocity(Velocity2::zero());
+            rigid_body.activate();
+        }
+        Some(physics_body)
+    }
+}
@@ -0,0 +1,56 @@
+use na::Isometry2;
+use ncollide2d::bounding_volume::BoundingVolume;
+use ncollide2d::query;
+use nphysics2d::world::World;
+
+use crate::components::{Continuous, PhysicsBody};
+
+// nphysics only checks where bodies end up, so anything moving further than its own size in a
+// step can pass through thin walls, entities spawned `continuous` are swept along their motion
+
+// taken right before a step
+pub fn positions(world: &World<f32>, entities: &hecs::World) -> Vec<(PhysicsBody, Isometry2<f32>)> {
+    let mut query = entities.query::<(&PhysicsBody, &Continuous)>();
+    query.iter()
+        .filter_map(|(_, (physics_body, _))| world.rigid_body(physics_body.body).map(|rigid_body| (*physics_body, *rigid_body.position())))
+        .collect()
+}
+
+// runs after the step, a body that went through something is brought back to where it first
+// touched it, the next step resolves the contact
+pub fn sweep(world: &mut World<f32>, before: &[(PhysicsBody, Isometry2<f32>)]) {
+    for (physics_body, start) in before {
+        let end = match world.rigid_body(physics_body.body) {
+            Some(rigid_body) => *rigid_body.position(),
+            None => continue,
+        };
+        let motion = end.translation.vector - start.translation.vector;
+
+        let first = {
+            let collision_world = world.collision_world();
+            let own = match collision_world.collision_object(physics_body.collider) {
+                Some(own) => own,
+                None => continue,
+            };
+            let shape = own.shape().as_ref();
+            let swept = shape.aabb(start).merged(&shape.aabb(&end));
+
+            let mut first: Option<f32> = None;
+            for other in collision_world.interferences_with_aabb(&swept, own.collision_groups()) {
+                if other.handle() == physics_body.collider || other.data().body() == physics_body.body {
+                    continue;
+                }
+                // the others already moved, they're taken where they ended up
+                let toi = query::time_of_impact(start, &motion, shape, other.position(), &na::zero(), other.shape().as_ref());
+                if let Some(toi) = toi.filter(|toi| *toi > 0.0 && *toi < 1.0) {
+                    first = Some(first.map_or(toi, |first| first.min(toi)));
+                }
+            }
+            first
+        };
+
+        if let (Some(toi), Some(rigid_body)) = (first, world.rigid_body_mut(physics_body.body)) {
+            rigid_body.set_position(Isometry2::new(start.translation.vector + motion * toi, end.rotation.angle()));
+        }
+    }
+}
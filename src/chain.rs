@@ -0,0 +1,53 @@
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::shape::{Cuboid, ShapeHandle};
+use nphysics2d::joint::{ConstraintHandle, RevoluteConstraint};
+use nphysics2d::object::{BodyHandle, Material};
+use nphysics2d::volumetric::Volumetric;
+use nphysics2d::world::World;
+
+use crate::components::{Joints, PhysicsBody};
+
+const LINK_DENSITY: f32 = 1.0;
+
+// links are laid straight from `start` to `end`, each hinged to the next at their touching ends,
+// and the two ends are hinged to the world, it sags once gravity pulls on it
+pub fn build(world: &mut World<f32>, margin: f32, start: Point2<f32>, end: Point2<f32>, links: usize, thickness: f32) -> (Vector2<f32>, Vec<(PhysicsBody, Joints)>) {
+    let span = end - start;
+    let link_length = span.norm() / links as f32;
+    let direction = span.normalize();
+    let angle = direction.y.atan2(direction.x);
+    let half_extents = Vector2::new(link_length * 0.5, thickness);
+
+    let shape = ShapeHandle::new(Cuboid::new(half_extents - Vector2::repeat(margin)));
+    let inertia = shape.inertia(LINK_DENSITY);
+    let center_of_mass = shape.center_of_mass();
+    let back = Point2::new(-half_extents.x, 0.0);
+    let front = Point2::new(half_extents.x, 0.0);
+
+    let mut built: Vec<(PhysicsBody, Joints)> = Vec::with_capacity(links);
+    let mut previous: Option<BodyHandle> = None;
+    for i in 0..links {
+        let center = start + direction * (link_length * (i as f32 + 0.5));
+        let body = world.add_rigid_body(Isometry2::new(center.coords, angle), inertia, center_of_mass);
+        let collider = world.add_collider(margin, shape.clone(), body, Isometry2::identity(), Material::new(0.0, 0.5));
+
+        let joint = match previous {
+            Some(previous) => RevoluteConstraint::new(previous, body, front, back),
+            None => RevoluteConstraint::new(BodyHandle::ground(), body, start, back),
+        };
+        let joint = world.add_constraint(joint);
+        if let Some((_, Joints(joints))) = built.last_mut() {
+            joints.push(joint);
+        }
+
+        let mut joints: Vec<ConstraintHandle> = vec![joint];
+        if i == links - 1 {
+            joints.push(world.add_constraint(RevoluteConstraint::new(BodyHandle::ground(), body, end, front)));
+        }
+
+        built.push((PhysicsBody { collider, body }, Joints(joints)));
+        previous = Some(body);
+    }
+
+    (half_extents, built)
+}
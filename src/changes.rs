@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use na::{Isometry2, Point2};
+use nphysics2d::algebra::Velocity2;
+
+use crate::protocol::{CharacterState, ClientId, EntityState, NetId};
+
+const POSITION_EPSILON: f32 = 1.0e-3;
+const ANGLE_EPSILON: f32 = 1.0e-3;
+const VELOCITY_EPSILON: f32 = 1.0e-2;
+
+// remembers what was last broadcast for each entity, a sleeping body never passes the
+// epsilons so it stays out of delta snapshots until something wakes it up
+#[derive(Default)]
+pub struct ChangeTracker {
+    sent: HashMap<NetId, Sent>,
+}
+
+// the part of an entity state `has_changed` compares, refreshed in place every tick so the
+// buffers of the lists are reused
+struct Sent {
+    owner: Option<ClientId>,
+    position: Isometry2<f32>,
+    velocity: Velocity2<f32>,
+    character: Option<CharacterState>,
+    grapple: Option<Point2<f32>>,
+    wheels: Vec<f32>,
+    limbs: Vec<Isometry2<f32>>,
+    outline: Vec<Point2<f32>>,
+    rope: Vec<Point2<f32>>,
+    invulnerable: bool,
+    team: Option<u8>,
+}
+
+impl Sent {
+    fn new(entity: &EntityState) -> Sent {
+        Sent {
+            owner: entity.owner,
+            position: entity.position,
+            velocity: entity.velocity,
+            character: entity.character,
+            grapple: entity.grapple,
+            wheels: entity.wheels.clone(),
+            limbs: entity.limbs.clone(),
+            outline: entity.outline.clone(),
+            rope: entity.rope.clone(),
+            invulnerable: entity.invulnerable.is_some(),
+            team: entity.team,
+        }
+    }
+
+    fn update(&mut self, entity: &EntityState) {
+        self.owner = entity.owner;
+        self.position = entity.position;
+        self.velocity = entity.velocity;
+        self.character = entity.character;
+        self.grapple = entity.grapple;
+        self.wheels.clone_from(&entity.wheels);
+        self.limbs.clone_from(&entity.limbs);
+        self.outline.clone_from(&entity.outline);
+        self.rope.clone_from(&entity.rope);
+        self.invulnerable = entity.invulnerable.is_some();
+        self.team = entity.team;
+    }
+}
+
+impl ChangeTracker {
+    pub fn has_changed(&self, entity: &EntityState) -> bool {
+        let sent = match self.sent.get(&entity.id) {
+            Some(sent) => sent,
+            None => return true,
+        };
+
+        sent.owner != entity.owner
+            || sent.character != entity.character
+            || sent.grapple != entity.grapple
+            || sent.invulnerable != entity.invulnerable.is_some()
+            || sent.team != entity.team
+            || sent.wheels.len() != entity.wheels.len()
+            || sent.wheels.iter().zip(entity.wheels.iter()).any(|(sent, angle)| (sent - angle).abs() > ANGLE_EPSILON)
+            || sent.limbs.len() != entity.limbs.len()
+            || sent.limbs.iter().zip(entity.limbs.iter()).any(|(sent, limb)| {
+                (sent.translation.vector - limb.translation.vector).norm() > POSITION_EPSILON
+                    || sent.rotation.angle_to(&limb.rotation).abs() > ANGLE_EPSILON
+            })
+            || sent.outline.len() != entity.outline.len()
+            || sent.outline.iter().zip(entity.outline.iter()).any(|(sent, point)| (sent - point).norm() > POSITION_EPSILON)
+            || sent.rope.len() != entity.rope.len()
+            || sent.rope.iter().zip(entity.rope.iter()).any(|(sent, point)| (sent - point).norm() > POSITION_EPSILON)
+            || (sent.position.translation.vector - entity.position.translation.vector).norm() > POSITION_EPSILON
+            || sent.position.rotation.angle_to(&entity.position.rotation).abs() > ANGLE_EPSILON
+            || (sent.velocity.linear - entity.velocity.linear).norm() > VELOCITY_EPSILON
+            || (sent.velocity.angular - entity.velocity.angular).abs() > VELOCITY_EPSILON
+    }
+
+    pub fn mark_sent(&mut self, entity: &EntityState) {
+        match self.sent.get_mut(&entity.id) {
+            Some(sent) => sent.update(entity),
+            None => {
+                self.sent.insert(entity.id, Sent::new(entity));
+            },
+        }
+    }
+
+    pub fn forget(&mut self, id: NetId) {
+        self.sent.remove(&id);
+    }
+}
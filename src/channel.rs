@@ -0,0 +1,50 @@
+use std::collections::VecDeque;
+
+use crate::protocol::{Channel, ServerMessage};
+
+// per connection queues, one per channel, flushed once at the end of every tick
+#[derive(Default)]
+pub struct Outbox {
+    reliable_ordered: VecDeque<ServerMessage>,
+    reliable_unordered: VecDeque<ServerMessage>,
+    unreliable: VecDeque<ServerMessage>,
+}
+
+impl Outbox {
+    pub fn push(&mut self, message: ServerMessage) {
+        match message.channel() {
+            Channel::ReliableOrdered => self.reliable_ordered.push_back(message),
+            Channel::ReliableUnordered => self.reliable_unordered.push_back(message),
+            Channel::Unreliable => {
+                // a keyframe makes any older snapshot still waiting here useless,
+                // deltas have to stay queued behind the snapshot they apply to
+                if let ServerMessage::Snapshot(ref snapshot) = message {
+                    if snapshot.keyframe {
+                        self.unreliable.retain(|queued| match queued {
+                            ServerMessage::Snapshot(_) => false,
+                            _ => true,
+                        });
+                    }
+                }
+                self.unreliable.push_back(message);
+            },
+        }
+    }
+
+    // reliable traffic goes first so events are never stuck behind a snapshot
+    pub fn drain<'a>(&'a mut self) -> impl Iterator<Item = ServerMessage> + 'a {
+        self.reliable_ordered.drain(..)
+            .chain(self.reliable_unordered.drain(..))
+            .chain(self.unreliable.drain(..))
+    }
+
+    pub fn len(&self) -> usize {
+        self.reliable_ordered.len() + self.reliable_unordered.len() + self.unreliable.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.reliable_ordered.clear();
+        self.reliable_unordered.clear();
+        self.unreliable.clear();
+    }
+}
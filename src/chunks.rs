@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use na::{Isometry2, Vector2};
+use ncollide2d::shape::{Cuboid, ShapeHandle};
+use nphysics2d::object::{BodyHandle, ColliderHandle, Material};
+use nphysics2d::world::World;
+
+use crate::collision::CollisionLayers;
+use crate::components::PhysicsBody;
+use crate::level::{BlockDefinition, Level};
+use crate::protocol::{Block, ChunkEntry, ChunkId, Shape};
+
+struct Chunk {
+    blocks: Vec<BlockDefinition>,
+    // the colliders of the blocks while the chunk is loaded
+    colliders: Option<Vec<ColliderHandle>>,
+}
+
+// the static blocks of the level, grouped in square chunks that only have colliders while a
+// dynamic body is within `load_radius` chunks of them, a block belongs to the chunk of its center
+pub struct Chunks {
+    size: f32,
+    load_radius: i32,
+    margin: f32,
+    chunks: BTreeMap<ChunkId, Chunk>,
+    // the material of the loaded blocks that have one
+    surfaces: HashMap<ColliderHandle, u8>,
+}
+
+impl Chunks {
+    pub fn new(level: &Level, margin: f32) -> Chunks {
+        let size = level.chunks.size;
+        let mut chunks = BTreeMap::new();
+
+        for block in level.blocks.iter() {
+            let id = chunk_of(size, Vector2::new(block.position[0], block.position[1]));
+            chunks.entry(id)
+                .or_insert_with(|| Chunk { blocks: vec![], colliders: None })
+                .blocks.push(block.clone());
+        }
+
+        Chunks {
+            size,
+            load_radius: level.chunks.load_radius as i32,
+            margin,
+            chunks,
+            surfaces: HashMap::new(),
+        }
+    }
+
+    // loads the chunks dynamic bodies got close to and unloads the ones they left,
+    // returns whether anything changed
+    pub fn update(&mut self, world: &mut World<f32>, entities: &hecs::World, layers: &CollisionLayers) -> bool {
+        let mut wanted = HashSet::new();
+        for (_, physics_body) in entities.query::<&PhysicsBody>().iter() {
+            if physics_body.body.is_ground() {
+                continue;
+            }
+            let center = match world.rigid_body(physics_body.body) {
+                Some(rigid_body) => chunk_of(self.size, rigid_body.position().translation.vector),
+                None => continue,
+            };
+
+            for x in -self.load_radius..=self.load_radius {
+                for y in -self.load_radius..=self.load_radius {
+                    wanted.insert(ChunkId { x: center.x + x, y: center.y + y });
+                }
+            }
+        }
+
+        let margin = self.margin;
+        let surfaces = &mut self.surfaces;
+        let mut changed = false;
+        for (id, chunk) in self.chunks.iter_mut() {
+            match (wanted.contains(id), chunk.colliders.is_some()) {
+                (true, false) => {
+                    let colliders: Vec<ColliderHandle> = chunk.blocks.iter().map(|block| add_block(world, margin, block, layers)).collect();
+                    for (block, collider) in chunk.blocks.iter().zip(colliders.iter()) {
+                        if block.surface != 0 {
+                            surfaces.insert(*collider, block.surface);
+                        }
+                    }
+                    chunk.colliders = Some(colliders);
+                    changed = true;
+                },
+                (false, true) => {
+                    if let Some(colliders) = chunk.colliders.take() {
+                        for collider in colliders.iter() {
+                            surfaces.remove(collider);
+                        }
+                        world.remove_colliders(&colliders);
+                    }
+                    changed = true;
+                },
+                _ => {},
+            }
+        }
+
+        changed
+    }
+
+    // 0 for colliders that aren't blocks or have no material
+    pub fn surface(&self, collider: ColliderHandle) -> u8 {
+        self.surfaces.get(&collider).cloned().unwrap_or(0)
+    }
+
+    // every chunk of the level, clients fetch the blocks of the ones they need
+    pub fn manifest(&self) -> Vec<ChunkEntry> {
+        self.chunks.iter().map(|(id, chunk)| ChunkEntry {
+            id: *id,
+            blocks: chunk.blocks.len() as u16,
+            loaded: chunk.colliders.is_some(),
+        }).collect()
+    }
+
+    pub fn blocks(&self, id: ChunkId) -> Option<Vec<Block>> {
+        let chunk = self.chunks.get(&id)?;
+
+        Some(chunk.blocks.iter().map(|block| Block {
+            position: Vector2::new(block.position[0], block.position[1]),
+            shape: Shape::Cuboid { half_extents: Vector2::new(block.half_extents[0], block.half_extents[1]) },
+        }).collect())
+    }
+}
+
+fn chunk_of(size: f32, position: Vector2<f32>) -> ChunkId {
+    ChunkId {
+        x: (position.x / size).floor() as i32,
+        y: (position.y / size).floor() as i32,
+    }
+}
+
+fn add_block(world: &mut World<f32>, margin: f32, block: &BlockDefinition, layers: &CollisionLayers) -> ColliderHandle {
+    let shape = ShapeHandle::new(Cuboid::new(Vector2::new(
+        block.half_extents[0] - margin,
+        block.half_extents[1] - margin,
+    )));
+    let position = Isometry2::new(Vector2::new(block.position[0], block.position[1]), 0.0);
+    let collider = world.add_collider(margin, shape, BodyHandle::ground(), position, Material::new(1.0, 0.0));
+
+    // blocks on a layer the config doesn't know collide with everything
+    if let Some(groups) = layers.groups(&block.layer) {
+        world.collision_world_mut().set_collision_groups(collider, groups);
+    }
+    collider
+}
@@ -0,0 +1,31 @@
+// weight of a new sample in the smoothed values
+const SMOOTHING: f32 = 0.125;
+
+// round trip and clock offset estimates of one connection, all in milliseconds,
+// `offset` is what to add to the server clock to get the client clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClockSync {
+    pub rtt: Option<f32>,
+    pub jitter: f32,
+    pub offset: f32,
+}
+
+impl ClockSync {
+    // `sent_at` and `now` are server times, `client_time` is read by the client when it answered
+    pub fn sample(&mut self, sent_at: u64, client_time: u64, now: u64) {
+        let rtt = now.saturating_sub(sent_at) as f32;
+        let offset = client_time as f32 - (sent_at as f32 + rtt / 2.0);
+
+        match self.rtt {
+            Some(smoothed) => {
+                self.jitter += SMOOTHING * ((rtt - smoothed).abs() - self.jitter);
+                self.rtt = Some(smoothed + SMOOTHING * (rtt - smoothed));
+                self.offset += SMOOTHING * (offset - self.offset);
+            },
+            None => {
+                self.rtt = Some(rtt);
+                self.offset = offset;
+            },
+        }
+    }
+}
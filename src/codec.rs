@@ -0,0 +1,1031 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use na::{Isometry2, Point2, Vector2};
+use nphysics2d::algebra::Velocity2;
+
+use crate::clock::ClockSync;
+use crate::compression::Compression;
+use crate::levelgen::ArenaParams;
+use crate::protocol::{Block, CameraHint, CharacterState, ChunkEntry, ChunkId, Command, CommandKind, DespawnReason, EntityKind, EntityState, Event, MIN_PROTOCOL_VERSION, MatchPhase, MaterialEntry, Metadata, MotionKind, PROTOCOL_VERSION, RejectReason, ServerMessage, Shape, Snapshot, TimerState, Violation};
+use crate::session::SessionToken;
+
+// everything is little endian, optional values are prefixed with a 0/1 byte
+// and collections with their u16 length, schema.rs describes the layout for clients
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    UnknownTag(u8),
+    InvalidString,
+    Decompression(String),
+    UnsupportedVersion(u16),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of frame"),
+            DecodeError::UnknownTag(tag) => write!(f, "unknown tag {}", tag),
+            DecodeError::InvalidString => write!(f, "invalid utf-8 string"),
+            DecodeError::Decompression(error) => write!(f, "can't decompress frame: {}", error),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported protocol version {}", version),
+        }
+    }
+}
+
+pub struct Writer<'a> {
+    bytes: &'a mut Vec<u8>,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(bytes: &'a mut Vec<u8>) -> Writer<'a> {
+        Writer {
+            bytes,
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    pub fn id(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    pub fn option<T, F: FnOnce(&mut Writer<'a>, T)>(&mut self, value: Option<T>, write: F) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                write(self, value);
+            },
+            None => self.u8(0),
+        }
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.u16(value.len() as u16);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u16(value.len() as u16);
+        self.bytes.extend_from_slice(value);
+    }
+
+    // for whole files, everything sent to clients fits a u16 length
+    pub fn long_string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader {
+            bytes,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, DecodeError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, DecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(i32::from_le_bytes(bytes))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, DecodeError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn id(&mut self) -> Result<usize, DecodeError> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub fn option<T, F: FnOnce(&mut Reader<'a>) -> Result<T, DecodeError>>(&mut self, read: F) -> Result<Option<T>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(read(self)?)),
+            tag => Err(DecodeError::UnknownTag(tag)),
+        }
+    }
+
+    pub fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| DecodeError::InvalidString)
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let len = self.u16()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn long_string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| DecodeError::InvalidString)
+    }
+}
+
+pub fn encode(message: &ServerMessage) -> Vec<u8> {
+    let mut bytes = vec![];
+    encode_into(message, &mut bytes);
+    bytes
+}
+
+// reuses `bytes` so a long lived scratch buffer stops allocating once it's big enough
+pub fn encode_into(message: &ServerMessage, bytes: &mut Vec<u8>) {
+    bytes.clear();
+    let mut writer = Writer::new(bytes);
+
+    match message {
+        ServerMessage::Welcome { client, token, entity, compression } => {
+            writer.u8(0);
+            writer.id(*client);
+            writer.u64(token.0);
+            writer.option(*entity, Writer::id);
+            writer.u8(*compression as u8);
+        },
+        ServerMessage::Resumed { client, entity } => {
+            writer.u8(1);
+            writer.id(*client);
+            writer.option(*entity, Writer::id);
+        },
+        ServerMessage::Rejected(reason) => {
+            writer.u8(2);
+            writer.string(reason);
+        },
+        ServerMessage::Throttled { dropped } => {
+            writer.u8(3);
+            writer.u32(*dropped);
+        },
+        ServerMessage::CommandRejected { command, reason } => {
+            writer.u8(4);
+            writer.u8(*command as u8);
+            writer.u8(*reason as u8);
+        },
+        ServerMessage::Snapshot(snapshot) => {
+            write_snapshot_header(&mut writer, snapshot);
+            write_snapshot_body(&mut writer, snapshot);
+        },
+        ServerMessage::Events(events) => {
+            writer.u8(6);
+            writer.u16(events.len() as u16);
+            for event in events {
+                write_event(&mut writer, event);
+            }
+        },
+        ServerMessage::Ping { sent_at } => {
+            writer.u8(7);
+            writer.u64(*sent_at);
+        },
+        ServerMessage::Pong { client_time, tick, server_time } => {
+            writer.u8(8);
+            writer.u64(*client_time);
+            writer.u64(*tick);
+            writer.u64(*server_time);
+        },
+        ServerMessage::End => {
+            writer.u8(9);
+        },
+        ServerMessage::ChunkManifest(chunks) => {
+            writer.u8(10);
+            writer.u16(chunks.len() as u16);
+            for chunk in chunks {
+                writer.i32(chunk.id.x);
+                writer.i32(chunk.id.y);
+                writer.u16(chunk.blocks);
+                writer.u8(chunk.loaded as u8);
+            }
+        },
+        ServerMessage::Chunk { id, blocks } => {
+            writer.u8(11);
+            writer.i32(id.x);
+            writer.i32(id.y);
+            writer.u16(blocks.len() as u16);
+            for block in blocks {
+                writer.f32(block.position.x);
+                writer.f32(block.position.y);
+                write_shape(&mut writer, &block.shape);
+            }
+        },
+        ServerMessage::Arena { seed, params } => {
+            writer.u8(12);
+            writer.u64(*seed);
+            writer.f32(params.width);
+            writer.f32(params.height);
+            writer.u16(params.obstacles);
+            writer.f32(params.min_size);
+            writer.f32(params.max_size);
+            writer.u16(params.spawn_points);
+        },
+        ServerMessage::Crashed { restarting } => {
+            writer.u8(13);
+            writer.u8(*restarting as u8);
+        },
+        ServerMessage::Relayed { from, channel, payload } => {
+            writer.u8(14);
+            writer.id(*from);
+            writer.u8(*channel);
+            writer.bytes(payload);
+        },
+        ServerMessage::Inputs { tick, commands, checksum } => {
+            writer.u8(15);
+            writer.u64(*tick);
+            writer.u16(commands.len() as u16);
+            for (client, command) in commands.iter() {
+                writer.id(*client);
+                write_command(&mut writer, command);
+            }
+            writer.option(*checksum, Writer::u64);
+        },
+        ServerMessage::SnapshotDelta { tick, baseline, ack, len, bytes } => {
+            writer.u8(16);
+            writer.u64(*tick);
+            writer.u64(*baseline);
+            writer.option(*ack, Writer::u32);
+            writer.u32(*len);
+            writer.bytes(bytes);
+        },
+        ServerMessage::Materials(materials) => {
+            writer.u8(17);
+            writer.u16(materials.len() as u16);
+            for material in materials {
+                writer.string(&material.name);
+                writer.option(material.sound_set.as_ref().map(String::as_str), Writer::string);
+                writer.option(material.particle_set.as_ref().map(String::as_str), Writer::string);
+                writer.option(material.footstep.as_ref().map(String::as_str), Writer::string);
+            }
+        },
+        ServerMessage::Feedback { magnitude, duration_ms } => {
+            writer.u8(18);
+            writer.f32(*magnitude);
+            writer.u16(*duration_ms);
+        },
+        ServerMessage::Camera(hint) => {
+            writer.u8(19);
+            writer.option(hint.target, Writer::id);
+            writer.f32(hint.zoom);
+        },
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<ServerMessage, DecodeError> {
+    decode_version(PROTOCOL_VERSION, bytes)
+}
+
+// older versions only differ by the tags they know, anything added since is rejected like an unknown tag
+pub fn decode_version(version: u16, bytes: &[u8]) -> Result<ServerMessage, DecodeError> {
+    if version < MIN_PROTOCOL_VERSION || version > PROTOCOL_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let mut reader = Reader::new(bytes);
+
+    let message = match reader.u8()? {
+        0 => ServerMessage::Welcome {
+            client: reader.id()?,
+            token: SessionToken(reader.u64()?),
+            entity: reader.option(Reader::id)?,
+            compression: read_compression(&mut reader)?,
+        },
+        1 => ServerMessage::Resumed {
+            client: reader.id()?,
+            entity: reader.option(Reader::id)?,
+        },
+        2 => ServerMessage::Rejected(reader.string()?),
+        3 => ServerMessage::Throttled { dropped: reader.u32()? },
+        4 => ServerMessage::CommandRejected {
+            command: read_command_kind(&mut reader, version)?,
+            reason: read_reject_reason(&mut reader)?,
+        },
+        5 => ServerMessage::Snapshot(read_snapshot(&mut reader)?),
+        6 => {
+            let len = reader.u16()?;
+            let mut events = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                events.push(read_event(&mut reader, version)?);
+            }
+            ServerMessage::Events(events)
+        },
+        7 => ServerMessage::Ping { sent_at: reader.u64()? },
+        8 => ServerMessage::Pong {
+            client_time: reader.u64()?,
+            tick: reader.u64()?,
+            server_time: reader.u64()?,
+        },
+        9 => ServerMessage::End,
+        10 => {
+            let len = reader.u16()?;
+            let mut chunks = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                chunks.push(ChunkEntry {
+                    id: ChunkId { x: reader.i32()?, y: reader.i32()? },
+                    blocks: reader.u16()?,
+                    loaded: reader.u8()? != 0,
+                });
+            }
+            ServerMessage::ChunkManifest(chunks)
+        },
+        11 => {
+            let id = ChunkId { x: reader.i32()?, y: reader.i32()? };
+            let len = reader.u16()?;
+            let mut blocks = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                blocks.push(Block {
+                    position: Vector2::new(reader.f32()?, reader.f32()?),
+                    shape: read_shape(&mut reader)?,
+                });
+            }
+            ServerMessage::Chunk { id, blocks }
+        },
+        12 => ServerMessage::Arena {
+            seed: reader.u64()?,
+            params: ArenaParams {
+                width: reader.f32()?,
+                height: reader.f32()?,
+                obstacles: reader.u16()?,
+                min_size: reader.f32()?,
+                max_size: reader.f32()?,
+                spawn_points: reader.u16()?,
+            },
+        },
+        13 if version >= 2 => ServerMessage::Crashed { restarting: reader.u8()? != 0 },
+        14 if version >= 3 => ServerMessage::Relayed { from: reader.id()?, channel: reader.u8()?, payload: reader.bytes()? },
+        15 if version >= 5 => {
+            let tick = reader.u64()?;
+            let len = reader.u16()?;
+            let mut commands = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                commands.push((reader.id()?, read_command(&mut reader, version)?));
+            }
+            ServerMessage::Inputs { tick, commands: Arc::new(commands), checksum: reader.option(Reader::u64)? }
+        },
+        16 if version >= 7 => ServerMessage::SnapshotDelta {
+            tick: reader.u64()?,
+            baseline: reader.u64()?,
+            ack: reader.option(Reader::u32)?,
+            len: reader.u32()?,
+            bytes: reader.bytes()?,
+        },
+        17 if version >= 11 => {
+            let len = reader.u16()?;
+            let mut materials = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                materials.push(MaterialEntry {
+                    name: reader.string()?,
+                    sound_set: reader.option(Reader::string)?,
+                    particle_set: reader.option(Reader::string)?,
+                    footstep: reader.option(Reader::string)?,
+                });
+            }
+            ServerMessage::Materials(materials)
+        },
+        18 if version >= 12 => ServerMessage::Feedback { magnitude: reader.f32()?, duration_ms: reader.u16()? },
+        19 if version >= 13 => ServerMessage::Camera(CameraHint { target: reader.option(Reader::id)?, zoom: reader.f32()? }),
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(message)
+}
+
+// a snapshot is split in two: the header holds what differs between clients, the body
+// is the same for the whole room so it's encoded once and shared by all connections
+pub fn encode_snapshot_header(snapshot: &Snapshot, bytes: &mut Vec<u8>) {
+    bytes.clear();
+    write_snapshot_header(&mut Writer::new(bytes), snapshot);
+}
+
+pub fn encode_snapshot_body(snapshot: &Snapshot, bytes: &mut Vec<u8>) {
+    bytes.clear();
+    write_snapshot_body(&mut Writer::new(bytes), snapshot);
+}
+
+fn write_snapshot_header(writer: &mut Writer, snapshot: &Snapshot) {
+    writer.u8(5);
+    writer.option(snapshot.ack, Writer::u32);
+    writer.option(snapshot.clock.rtt, Writer::f32);
+    writer.f32(snapshot.clock.jitter);
+    writer.f32(snapshot.clock.offset);
+}
+
+fn write_snapshot_body(writer: &mut Writer, snapshot: &Snapshot) {
+    writer.u64(snapshot.tick);
+    writer.u8(snapshot.keyframe as u8);
+    writer.u16(snapshot.entities.len() as u16);
+    for entity in snapshot.entities.iter() {
+        writer.id(entity.id);
+        writer.option(entity.owner, Writer::id);
+        writer.f32(entity.position.translation.vector.x);
+        writer.f32(entity.position.translation.vector.y);
+        writer.f32(entity.position.rotation.angle());
+        writer.f32(entity.velocity.linear.x);
+        writer.f32(entity.velocity.linear.y);
+        writer.f32(entity.velocity.angular);
+        writer.option(entity.character, |writer, state| writer.u8(state.grounded as u8 | (state.jumping as u8) << 1));
+        writer.option(entity.grapple, |writer, end| {
+            writer.f32(end.x);
+            writer.f32(end.y);
+        });
+        writer.u8(entity.wheels.len() as u8);
+        for angle in entity.wheels.iter() {
+            writer.f32(*angle);
+        }
+        writer.u8(entity.limbs.len() as u8);
+        for limb in entity.limbs.iter() {
+            writer.f32(limb.translation.vector.x);
+            writer.f32(limb.translation.vector.y);
+            writer.f32(limb.rotation.angle());
+        }
+        writer.u8(entity.outline.len() as u8);
+        for point in entity.outline.iter() {
+            writer.f32(point.x);
+            writer.f32(point.y);
+        }
+        writer.option(entity.invulnerable, Writer::u32);
+        writer.option(entity.team, Writer::u8);
+        writer.u8(entity.rope.len() as u8);
+        for point in entity.rope.iter() {
+            writer.f32(point.x);
+            writer.f32(point.y);
+        }
+    }
+    writer.u8(snapshot.timers.len() as u8);
+    for timer in snapshot.timers.iter() {
+        writer.string(&timer.name);
+        writer.u32(timer.remaining_ticks);
+    }
+}
+
+fn read_snapshot(reader: &mut Reader) -> Result<Snapshot, DecodeError> {
+    let ack = reader.option(Reader::u32)?;
+    let clock = ClockSync {
+        rtt: reader.option(Reader::f32)?,
+        jitter: reader.f32()?,
+        offset: reader.f32()?,
+    };
+    let tick = reader.u64()?;
+    let keyframe = reader.u8()? != 0;
+
+    let len = reader.u16()?;
+    let mut entities = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        let id = reader.id()?;
+        let owner = reader.option(Reader::id)?;
+        let position = Isometry2::new(Vector2::new(reader.f32()?, reader.f32()?), reader.f32()?);
+        let velocity = Velocity2::new(Vector2::new(reader.f32()?, reader.f32()?), reader.f32()?);
+
+        let mut state = EntityState::new(id, owner, position, velocity);
+        state.character = reader.option(|reader| {
+            let flags = reader.u8()?;
+            Ok(CharacterState { grounded: flags & 1 != 0, jumping: flags & 2 != 0 })
+        })?;
+        state.grapple = reader.option(|reader| Ok(Point2::new(reader.f32()?, reader.f32()?)))?;
+        for _ in 0..reader.u8()? {
+            state.wheels.push(reader.f32()?);
+        }
+        for _ in 0..reader.u8()? {
+            state.limbs.push(Isometry2::new(Vector2::new(reader.f32()?, reader.f32()?), reader.f32()?));
+        }
+        for _ in 0..reader.u8()? {
+            state.outline.push(Point2::new(reader.f32()?, reader.f32()?));
+        }
+        state.invulnerable = reader.option(Reader::u32)?;
+        state.team = reader.option(Reader::u8)?;
+        for _ in 0..reader.u8()? {
+            state.rope.push(Point2::new(reader.f32()?, reader.f32()?));
+        }
+        entities.push(state);
+    }
+
+    let len = reader.u8()?;
+    let mut timers = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        timers.push(TimerState { name: reader.string()?, remaining_ticks: reader.u32()? });
+    }
+
+    Ok(Snapshot { tick, keyframe, ack, clock, entities: Arc::new(entities), timers: Arc::new(timers) })
+}
+
+fn write_event(writer: &mut Writer, event: &Event) {
+    match event {
+        Event::Joined { client, entity } => {
+            writer.u8(0);
+            writer.id(*client);
+            writer.option(*entity, Writer::id);
+        },
+        Event::Left { client, entity } => {
+            writer.u8(1);
+            writer.id(*client);
+            writer.option(*entity, Writer::id);
+        },
+        Event::ContactStarted { a, b, impulse, point, normal } => {
+            writer.u8(2);
+            writer.id(*a);
+            writer.id(*b);
+            writer.f32(*impulse);
+            writer.f32(point.x);
+            writer.f32(point.y);
+            writer.f32(normal.x);
+            writer.f32(normal.y);
+        },
+        Event::ContactStopped(a, b) => {
+            writer.u8(3);
+            writer.id(*a);
+            writer.id(*b);
+        },
+        Event::AuthorityChanged { entity, previous, owner } => {
+            writer.u8(4);
+            writer.id(*entity);
+            writer.option(*previous, Writer::id);
+            writer.option(*owner, Writer::id);
+        },
+        Event::Despawned { entity, reason } => {
+            writer.u8(5);
+            writer.id(*entity);
+            writer.u8(*reason as u8);
+        },
+        Event::Spawned { entity, kind, shape, position, metadata } => {
+            writer.u8(6);
+            writer.id(*entity);
+            writer.u8(*kind as u8);
+            write_shape(writer, shape);
+            writer.f32(position.translation.vector.x);
+            writer.f32(position.translation.vector.y);
+            writer.f32(position.rotation.angle());
+            write_metadata(writer, metadata);
+        },
+        Event::MetadataChanged { entity, metadata } => {
+            writer.u8(7);
+            writer.id(*entity);
+            write_metadata(writer, metadata);
+        },
+        Event::Approached { a, b } => {
+            writer.u8(8);
+            writer.id(*a);
+            writer.id(*b);
+        },
+        Event::Separated { a, b } => {
+            writer.u8(9);
+            writer.id(*a);
+            writer.id(*b);
+        },
+        Event::VelocityClamped { entity } => {
+            writer.u8(10);
+            writer.id(*entity);
+        },
+        Event::MotionStarted { entity, motion } => {
+            writer.u8(11);
+            writer.id(*entity);
+            writer.u8(*motion as u8);
+        },
+        Event::MotionEnded { entity, motion } => {
+            writer.u8(12);
+            writer.id(*entity);
+            writer.u8(*motion as u8);
+        },
+        Event::Respawned { client, entity } => {
+            writer.u8(13);
+            writer.id(*client);
+            writer.id(*entity);
+        },
+        Event::MatchPhaseChanged { phase } => {
+            writer.u8(14);
+            writer.u8(*phase as u8);
+        },
+        Event::Scored { client, points, score } => {
+            writer.u8(15);
+            writer.id(*client);
+            writer.u32(*points);
+            writer.u32(*score);
+        },
+        Event::TimerExpired { name } => {
+            writer.u8(16);
+            writer.string(name);
+        },
+        Event::InvariantViolated { entity, violation } => {
+            writer.u8(17);
+            writer.id(*entity);
+            writer.u8(*violation as u8);
+        },
+        Event::PayloadChanged { entity, payload } => {
+            writer.u8(18);
+            writer.id(*entity);
+            writer.bytes(payload);
+        },
+        Event::ContinuousCollision { entity } => {
+            writer.u8(19);
+            writer.id(*entity);
+        },
+        Event::Hit { shooter, target, point, damage } => {
+            writer.u8(20);
+            writer.id(*shooter);
+            writer.id(*target);
+            writer.f32(point.x);
+            writer.f32(point.y);
+            writer.f32(*damage);
+        },
+        Event::Swung { entity, targets } => {
+            writer.u8(21);
+            writer.id(*entity);
+            writer.u16(targets.len() as u16);
+            for target in targets {
+                writer.id(*target);
+            }
+        },
+        Event::ImpactFx { material_a, material_b, impulse, point } => {
+            writer.u8(22);
+            writer.u8(*material_a);
+            writer.u8(*material_b);
+            writer.f32(*impulse);
+            writer.f32(point.x);
+            writer.f32(point.y);
+        },
+        Event::ProtocolDeprecated { current, minimum } => {
+            writer.u8(23);
+            writer.u16(*current);
+            writer.u16(*minimum);
+        },
+    }
+}
+
+// tags follow `CommandKind`
+pub fn write_command(writer: &mut Writer, command: &Command) {
+    writer.u8(command.kind() as u8);
+
+    match *command {
+        Command::Impulse { entity, impulse } => {
+            writer.id(entity);
+            writer.f32(impulse.x);
+            writer.f32(impulse.y);
+        },
+        Command::Move { entity, direction } => {
+            writer.id(entity);
+            writer.f32(direction);
+        },
+        Command::Jump { entity, pressed } => {
+            writer.id(entity);
+            writer.u8(pressed as u8);
+        },
+        Command::Dash { entity, direction, distance, duration } => {
+            writer.id(entity);
+            writer.f32(direction.x);
+            writer.f32(direction.y);
+            writer.f32(distance);
+            writer.f32(duration);
+        },
+        Command::Grapple { entity, direction } => {
+            writer.id(entity);
+            writer.f32(direction.x);
+            writer.f32(direction.y);
+        },
+        Command::Reel { entity, speed } => {
+            writer.id(entity);
+            writer.f32(speed);
+        },
+        Command::Release { entity } => {
+            writer.id(entity);
+        },
+        Command::Drive { entity, throttle, steer } => {
+            writer.id(entity);
+            writer.f32(throttle);
+            writer.f32(steer);
+        },
+        Command::FireHitscan { shooter, dir, client_tick } => {
+            writer.id(shooter);
+            writer.f32(dir.x);
+            writer.f32(dir.y);
+            writer.u64(client_tick);
+        },
+        Command::Swing { entity, direction, radius, angle } => {
+            writer.id(entity);
+            writer.f32(direction.x);
+            writer.f32(direction.y);
+            writer.f32(radius);
+            writer.f32(angle);
+        },
+    }
+}
+
+// commands added since `version` are rejected like an unknown tag
+pub fn read_command(reader: &mut Reader, version: u16) -> Result<Command, DecodeError> {
+    let command = match reader.u8()? {
+        0 => Command::Impulse { entity: reader.id()?, impulse: Vector2::new(reader.f32()?, reader.f32()?) },
+        1 => Command::Move { entity: reader.id()?, direction: reader.f32()? },
+        2 => Command::Jump { entity: reader.id()?, pressed: reader.u8()? != 0 },
+        3 => Command::Dash {
+            entity: reader.id()?,
+            direction: Vector2::new(reader.f32()?, reader.f32()?),
+            distance: reader.f32()?,
+            duration: reader.f32()?,
+        },
+        4 => Command::Grapple { entity: reader.id()?, direction: Vector2::new(reader.f32()?, reader.f32()?) },
+        5 => Command::Reel { entity: reader.id()?, speed: reader.f32()? },
+        6 => Command::Release { entity: reader.id()? },
+        7 => Command::Drive { entity: reader.id()?, throttle: reader.f32()?, steer: reader.f32()? },
+        8 if version >= 8 => Command::FireHitscan { shooter: reader.id()?, dir: Vector2::new(reader.f32()?, reader.f32()?), client_tick: reader.u64()? },
+        9 if version >= 9 => Command::Swing {
+            entity: reader.id()?,
+            direction: Vector2::new(reader.f32()?, reader.f32()?),
+            radius: reader.f32()?,
+            angle: reader.f32()?,
+        },
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(command)
+}
+
+pub fn write_metadata(writer: &mut Writer, metadata: &Metadata) {
+    writer.u16(metadata.tags.len() as u16);
+    for tag in &metadata.tags {
+        writer.string(tag);
+    }
+
+    writer.u16(metadata.values.len() as u16);
+    for (key, value) in &metadata.values {
+        writer.string(key);
+        writer.string(value);
+    }
+}
+
+fn write_shape(writer: &mut Writer, shape: &Shape) {
+    match shape {
+        Shape::Cuboid { half_extents } => {
+            writer.u8(0);
+            writer.f32(half_extents.x);
+            writer.f32(half_extents.y);
+        },
+        Shape::Ball { radius } => {
+            writer.u8(1);
+            writer.f32(*radius);
+        },
+        Shape::Polyline { points } => {
+            writer.u8(2);
+            writer.u16(points.len() as u16);
+            for point in points {
+                writer.f32(point.x);
+                writer.f32(point.y);
+            }
+        },
+    }
+}
+
+fn read_event(reader: &mut Reader, version: u16) -> Result<Event, DecodeError> {
+    let event = match reader.u8()? {
+        0 => Event::Joined {
+            client: reader.id()?,
+            entity: reader.option(Reader::id)?,
+        },
+        1 => Event::Left {
+            client: reader.id()?,
+            entity: reader.option(Reader::id)?,
+        },
+        2 => Event::ContactStarted {
+            a: reader.id()?,
+            b: reader.id()?,
+            impulse: reader.f32()?,
+            point: Point2::new(reader.f32()?, reader.f32()?),
+            normal: Vector2::new(reader.f32()?, reader.f32()?),
+        },
+        3 => Event::ContactStopped(reader.id()?, reader.id()?),
+        4 => Event::AuthorityChanged {
+            entity: reader.id()?,
+            previous: reader.option(Reader::id)?,
+            owner: reader.option(Reader::id)?,
+        },
+        5 => Event::Despawned { entity: reader.id()?, reason: read_despawn_reason(reader)? },
+        6 => Event::Spawned {
+            entity: reader.id()?,
+            kind: read_entity_kind(reader)?,
+            shape: read_shape(reader)?,
+            position: Isometry2::new(Vector2::new(reader.f32()?, reader.f32()?), reader.f32()?),
+            metadata: read_metadata(reader)?,
+        },
+        7 => Event::MetadataChanged {
+            entity: reader.id()?,
+            metadata: read_metadata(reader)?,
+        },
+        8 => Event::Approached { a: reader.id()?, b: reader.id()? },
+        9 => Event::Separated { a: reader.id()?, b: reader.id()? },
+        10 => Event::VelocityClamped { entity: reader.id()? },
+        11 => Event::MotionStarted { entity: reader.id()?, motion: read_motion_kind(reader)? },
+        12 => Event::MotionEnded { entity: reader.id()?, motion: read_motion_kind(reader)? },
+        13 => Event::Respawned { client: reader.id()?, entity: reader.id()? },
+        14 => Event::MatchPhaseChanged { phase: read_match_phase(reader)? },
+        15 => Event::Scored { client: reader.id()?, points: reader.u32()?, score: reader.u32()? },
+        16 => Event::TimerExpired { name: reader.string()? },
+        17 if version >= 2 => Event::InvariantViolated { entity: reader.id()?, violation: read_violation(reader)? },
+        18 if version >= 4 => Event::PayloadChanged { entity: reader.id()?, payload: reader.bytes()? },
+        19 if version >= 6 => Event::ContinuousCollision { entity: reader.id()? },
+        20 if version >= 8 => Event::Hit {
+            shooter: reader.id()?,
+            target: reader.id()?,
+            point: Point2::new(reader.f32()?, reader.f32()?),
+            damage: reader.f32()?,
+        },
+        21 if version >= 9 => {
+            let entity = reader.id()?;
+            let len = reader.u16()?;
+            let mut targets = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                targets.push(reader.id()?);
+            }
+            Event::Swung { entity, targets }
+        },
+        22 if version >= 10 => Event::ImpactFx {
+            material_a: reader.u8()?,
+            material_b: reader.u8()?,
+            impulse: reader.f32()?,
+            point: Point2::new(reader.f32()?, reader.f32()?),
+        },
+        23 if version >= 14 => Event::ProtocolDeprecated { current: reader.u16()?, minimum: reader.u16()? },
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(event)
+}
+
+fn read_compression(reader: &mut Reader) -> Result<Compression, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(Compression::None),
+        1 => Ok(Compression::Lz4),
+        2 => Ok(Compression::Zstd),
+        tag => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+fn read_command_kind(reader: &mut Reader, version: u16) -> Result<CommandKind, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(CommandKind::Impulse),
+        1 => Ok(CommandKind::Move),
+        2 => Ok(CommandKind::Jump),
+        3 => Ok(CommandKind::Dash),
+        4 => Ok(CommandKind::Grapple),
+        5 => Ok(CommandKind::Reel),
+        6 => Ok(CommandKind::Release),
+        7 => Ok(CommandKind::Drive),
+        8 if version >= 8 => Ok(CommandKind::FireHitscan),
+        9 if version >= 9 => Ok(CommandKind::Swing),
+        tag => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+fn read_entity_kind(reader: &mut Reader) -> Result<EntityKind, DecodeError> {
+    let kind = match reader.u8()? {
+        0 => EntityKind::Wall,
+        1 => EntityKind::Player,
+        2 => EntityKind::Projectile,
+        3 => EntityKind::Pickup,
+        4 => EntityKind::Prop,
+        5 => EntityKind::Vehicle,
+        6 => EntityKind::SoftBody,
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(kind)
+}
+
+fn read_shape(reader: &mut Reader) -> Result<Shape, DecodeError> {
+    let shape = match reader.u8()? {
+        0 => Shape::Cuboid { half_extents: Vector2::new(reader.f32()?, reader.f32()?) },
+        1 => Shape::Ball { radius: reader.f32()? },
+        2 => {
+            let len = reader.u16()?;
+            let mut points = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                points.push(Point2::new(reader.f32()?, reader.f32()?));
+            }
+            Shape::Polyline { points }
+        },
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(shape)
+}
+
+pub fn read_metadata(reader: &mut Reader) -> Result<Metadata, DecodeError> {
+    let len = reader.u16()?;
+    let mut tags = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        tags.push(reader.string()?);
+    }
+
+    let len = reader.u16()?;
+    let mut values = BTreeMap::new();
+    for _ in 0..len {
+        values.insert(reader.string()?, reader.string()?);
+    }
+
+    Ok(Metadata { tags, values })
+}
+
+fn read_match_phase(reader: &mut Reader) -> Result<MatchPhase, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(MatchPhase::Lobby),
+        1 => Ok(MatchPhase::Countdown),
+        2 => Ok(MatchPhase::Playing),
+        3 => Ok(MatchPhase::Finished),
+        tag => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+fn read_despawn_reason(reader: &mut Reader) -> Result<DespawnReason, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(DespawnReason::Removed),
+        1 => Ok(DespawnReason::Destroyed),
+        2 => Ok(DespawnReason::Expired),
+        3 => Ok(DespawnReason::OutOfBounds),
+        4 => Ok(DespawnReason::Transferred),
+        tag => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+fn read_violation(reader: &mut Reader) -> Result<Violation, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(Violation::NonFinite),
+        1 => Ok(Violation::OutOfBounds),
+        2 => Ok(Violation::TooFast),
+        3 => Ok(Violation::Unmapped),
+        tag => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+fn read_motion_kind(reader: &mut Reader) -> Result<MotionKind, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(MotionKind::Dash),
+        1 => Ok(MotionKind::Knockback),
+        tag => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+fn read_reject_reason(reader: &mut Reader) -> Result<RejectReason, DecodeError> {
+    let reason = match reader.u8()? {
+        0 => RejectReason::UnknownEntity,
+        1 => RejectReason::NotOwner,
+        2 => RejectReason::Spectator,
+        3 => RejectReason::CommandNotAllowed,
+        4 => RejectReason::Malformed,
+        5 => RejectReason::ImpulseTooLarge,
+        6 => RejectReason::SpeedTooHigh,
+        7 => RejectReason::DashTooLong,
+        8 => RejectReason::MatchNotRunning,
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(reason)
+}
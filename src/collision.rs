@@ -0,0 +1,92 @@
+use ncollide2d::world::CollisionGroups;
+
+// ncollide supports up to 30 collision groups, layers take them from the first one and teams
+// from the last one down
+const MAX_GROUPS: usize = 30;
+pub const MAX_TEAMS: usize = 4;
+const MAX_LAYERS: usize = MAX_GROUPS - MAX_TEAMS;
+
+fn team_group(team: u8) -> usize {
+    MAX_GROUPS - 1 - team as usize
+}
+
+// puts the groups in `team` on top of their layer, `spares_team` keeps them from touching
+// their teammates, for projectiles when friendly fire is off
+pub fn with_team(groups: CollisionGroups, team: Option<u8>, spares_team: bool) -> CollisionGroups {
+    let mut groups = groups;
+    for other in 0..MAX_TEAMS as u8 {
+        groups.modify_membership(team_group(other), false);
+        groups.modify_blacklist(team_group(other), false);
+    }
+
+    if let Some(team) = team {
+        groups.modify_membership(team_group(team), true);
+        if spares_team {
+            groups.modify_blacklist(team_group(team), true);
+        }
+    }
+    groups
+}
+
+// membership, whitelist and blacklist as bit masks, self interaction isn't part of them
+pub fn masks(groups: &CollisionGroups) -> [u32; 3] {
+    let mut masks = [0; 3];
+    for group in 0..MAX_GROUPS {
+        if groups.is_member_of(group) {
+            masks[0] |= 1 << group;
+        }
+        if groups.is_group_whitelisted(group) {
+            masks[1] |= 1 << group;
+        }
+        if groups.is_group_blacklisted(group) {
+            masks[2] |= 1 << group;
+        }
+    }
+    masks
+}
+
+pub fn from_masks(masks: [u32; 3]) -> CollisionGroups {
+    let groups = |mask: u32| -> Vec<usize> { (0..MAX_GROUPS).filter(|group| mask & (1 << group) != 0).collect() };
+
+    CollisionGroups::new()
+        .with_membership(&groups(masks[0]))
+        .with_whitelist(&groups(masks[1]))
+        .with_blacklist(&groups(masks[2]))
+}
+
+// named layers with their interaction matrix, entities reference a layer by name when they spawn
+pub struct CollisionLayers {
+    names: Vec<String>,
+    groups: Vec<CollisionGroups>,
+}
+
+impl CollisionLayers {
+    pub fn new(names: Vec<String>, matrix: &[Vec<u8>]) -> Result<CollisionLayers, String> {
+        let len = names.len();
+        if len > MAX_LAYERS {
+            return Err(format!("{} collision layers, at most {} are supported", len, MAX_LAYERS));
+        }
+        if matrix.len() != len || matrix.iter().any(|row| row.len() != len) {
+            return Err(format!("the collision matrix must be {}x{}, one row and column per layer", len, len));
+        }
+
+        for i in 0..len {
+            for j in 0..i {
+                if (matrix[i][j] != 0) != (matrix[j][i] != 0) {
+                    return Err(format!("the collision matrix isn't symmetric for {} and {}", names[i], names[j]));
+                }
+            }
+        }
+
+        let groups = (0..len).map(|i| {
+            let whitelist: Vec<usize> = (0..len).filter(|&j| matrix[i][j] != 0).collect();
+            CollisionGroups::new().with_membership(&[i]).with_whitelist(&whitelist)
+        }).collect();
+
+        Ok(CollisionLayers { names, groups })
+    }
+
+    pub fn groups(&self, layer: &str) -> Option<CollisionGroups> {
+        self.names.iter().position(|name| name == layer).map(|index| self.groups[index])
+    }
+}
@@ -0,0 +1,71 @@
+use nphysics2d::joint::ConstraintHandle;
+use nphysics2d::object::{BodyHandle, ColliderHandle};
+
+use crate::protocol::{ClientId, NetId};
+
+// the body handle is kept next to the collider so reading an entity never goes through the collision world
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicsBody {
+    pub collider: ColliderHandle,
+    pub body: BodyHandle,
+}
+
+// entities without this component never leave the server
+#[derive(Debug, Clone, Copy)]
+pub struct Replicated {
+    pub id: NetId,
+}
+
+// the client allowed to command the entity, ownerless entities are server-controlled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner(pub ClientId);
+
+// flags the entity for near-miss detection with the other flagged entities, two of them
+// are close once their bounding boxes, each grown by its own margin, overlap
+#[derive(Debug, Clone, Copy)]
+pub struct Proximity {
+    pub margin: f32,
+}
+
+// a plain ball, its body goes back to the room's pool when it despawns
+#[derive(Debug, Clone, Copy)]
+pub struct Pooled;
+
+// swept along its motion every step so it can't pass through what it hits, for fast movers only
+#[derive(Debug, Clone, Copy)]
+pub struct Continuous;
+
+// the id of the level material the entity is made of, see `MaterialDefinition`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Surface(pub u8);
+
+// multiplies the world gravity for this body, 0 floats and above 1 feels heavy
+#[derive(Debug, Clone, Copy)]
+pub struct GravityScale(pub f32);
+
+// fraction of the velocity lost per second, so bodies come to rest on their own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Damping {
+    pub linear: f32,
+    pub angular: f32,
+}
+
+// joints removed along with the entity, a joint between two entities is listed on both
+#[derive(Debug, Clone)]
+pub struct Joints(pub Vec<ConstraintHandle>);
+
+// bytes the game keeps on the entity, the server never reads them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload(pub Vec<u8>);
+
+// entities of the same team can be kept from hurting each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Team(pub u8);
+
+// ticks left during which the entity can't be destroyed, given to players on respawn
+#[derive(Debug, Clone, Copy)]
+pub struct Invulnerable(pub u64);
+
+// the entity is despawned once it drops to zero
+#[derive(Debug, Clone, Copy)]
+pub struct Health(pub f32);
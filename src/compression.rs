@@ -0,0 +1,70 @@
+use crate::codec::DecodeError;
+
+// frames smaller than this aren't worth the cpu
+const COMPRESSION_THRESHOLD: usize = 256;
+const ZSTD_LEVEL: i32 = 3;
+// refuse to inflate frames beyond this, a snapshot never gets close
+const MAX_DECOMPRESSED_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+// picks the first algorithm of the client preference list the server supports
+pub fn negotiate(supported: &[Compression]) -> Compression {
+    supported.first().cloned().unwrap_or(Compression::None)
+}
+
+// one per session, the zstd context is kept between frames instead of allocated for each
+pub struct Compressor {
+    compression: Compression,
+    zstd: Option<zstd::block::Compressor>,
+}
+
+impl Compressor {
+    pub fn new(compression: Compression) -> Compressor {
+        Compressor {
+            compression,
+            zstd: match compression {
+                Compression::Zstd => Some(zstd::block::Compressor::new()),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    // returns a copy of the payload when it is too small or when compressing failed
+    pub fn compress(&mut self, payload: &[u8]) -> (Compression, Vec<u8>) {
+        if payload.len() < COMPRESSION_THRESHOLD {
+            return (Compression::None, payload.to_vec());
+        }
+
+        let compressed = match self.compression {
+            Compression::None => None,
+            Compression::Lz4 => Some(lz4_flex::compress_prepend_size(payload)),
+            Compression::Zstd => self.zstd.as_mut().and_then(|zstd| zstd.compress(payload, ZSTD_LEVEL).ok()),
+        };
+
+        match compressed {
+            Some(compressed) if compressed.len() < payload.len() => (self.compression, compressed),
+            _ => (Compression::None, payload.to_vec()),
+        }
+    }
+}
+
+pub fn decompress(compression: Compression, payload: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    match compression {
+        Compression::None => Ok(payload.to_vec()),
+        Compression::Lz4 => lz4_flex::decompress_size_prepended(payload)
+            .map_err(|error| DecodeError::Decompression(error.to_string())),
+        Compression::Zstd => zstd::block::Decompressor::new()
+            .decompress(payload, MAX_DECOMPRESSED_SIZE)
+            .map_err(|error| DecodeError::Decompression(error.to_string())),
+    }
+}
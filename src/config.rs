@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use na::Vector2;
+use serde::Deserialize;
+use tracing::warn;
+use tracing_subscriber::filter::LevelFilter;
+
+use crate::collision::CollisionLayers;
+use crate::entity_cap::{EntityCap, Eviction};
+use crate::scaling::ScalingPolicy;
+use crate::session::RateLimit;
+use crate::tenant::Tenant;
+use crate::usage::SoftLimits;
+
+// levels are checked without a config, their shapes must fit any margin up to this one
+pub const MAX_COLLIDER_MARGIN: f32 = 0.1;
+
+// used when there is no config file, it's also the reference for writing one
+const DEFAULT_CONFIG: &str = include_str!("../config.toml");
+
+// for configs written before the sections existed, input logs keep the one they were recorded with
+const DEFAULT_TICK_RATE: u64 = 60;
+const DEFAULT_COMMAND_LIMIT: RateLimit = RateLimit { per_tick: 2.0, burst: 8.0 };
+// a few chat lines in a row, then one every couple of seconds
+const DEFAULT_RELAY_LIMIT: RateLimit = RateLimit { per_tick: 0.05, burst: 5.0 };
+
+// the keys a running server applies on reload, whole sections or `section.key`, everything else is
+// built into the worlds, the listeners or the input logs already recorded
+const RELOADABLE: &[&str] = &["server.tick_rate", "server.log_level", "view", "budgets", "limits"];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+    // the keys a reload changed that only a restart applies
+    Rebuild(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "can't read config: {}", error),
+            ConfigError::Parse(error) => write!(f, "can't parse config: {}", error),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+            ConfigError::Rebuild(keys) => write!(f, "can't reload, a restart is needed to change {}", keys.join(", ")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ServerSection {
+    #[serde(default)]
+    tick_rate: Option<u64>,
+    #[serde(default)]
+    log_level: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WorldSection {
+    gravity: [f32; 2],
+    max_linear_speed: f32,
+    max_angular_speed: f32,
+    bounds_min: [f32; 2],
+    bounds_max: [f32; 2],
+    collider_margin: f32,
+    velocity_iterations: usize,
+    position_iterations: usize,
+    prediction: f32,
+}
+
+#[derive(Deserialize)]
+struct RopeSection {
+    interval_ticks: u64,
+}
+
+#[derive(Deserialize)]
+struct ResultsSection {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct RconSection {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct ScalingSection {
+    max_players: usize,
+    max_entities: usize,
+    empty_grace_ticks: u64,
+}
+
+#[derive(Deserialize)]
+struct ViewSection {
+    #[serde(default)]
+    max_radius: Option<f32>,
+    #[serde(default)]
+    line_of_sight: bool,
+}
+
+#[derive(Deserialize)]
+struct BudgetsSection {
+    commands_per_tick: f32,
+    command_burst: f32,
+    relays_per_tick: f32,
+    relay_burst: f32,
+}
+
+#[derive(Deserialize)]
+struct EntityCapSection {
+    max: usize,
+    eviction: Eviction,
+}
+
+#[derive(Deserialize)]
+struct ReplaySection {
+    deterministic: bool,
+    directory: String,
+    #[serde(default)]
+    lockstep: bool,
+}
+
+#[derive(Deserialize)]
+struct LimitsSection {
+    max_bodies: usize,
+    max_colliders: usize,
+    max_queued_messages: usize,
+    max_snapshot_bytes: usize,
+}
+
+#[derive(Deserialize)]
+struct SupervisionSection {
+    max_restarts: u32,
+    max_stall_ticks: u64,
+    restart_stalled: bool,
+}
+
+#[derive(Deserialize)]
+struct DebugSection {
+    check_invariants: bool,
+}
+
+#[derive(Deserialize)]
+struct TeamsSection {
+    friendly_fire: bool,
+}
+
+#[derive(Deserialize)]
+struct PlayerSection {
+    kinematic: bool,
+}
+
+#[derive(Deserialize)]
+struct CollisionSection {
+    layers: Vec<String>,
+    matrix: Vec<Vec<u8>>,
+}
+
+#[derive(Deserialize)]
+struct TenantSection {
+    name: String,
+    prefix: String,
+    key_sha256: String,
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    server: Option<ServerSection>,
+    world: WorldSection,
+    rope: RopeSection,
+    #[serde(default)]
+    results: Option<ResultsSection>,
+    #[serde(default)]
+    rcon: Option<RconSection>,
+    scaling: ScalingSection,
+    #[serde(default)]
+    entity_cap: Option<EntityCapSection>,
+    #[serde(default)]
+    view: Option<ViewSection>,
+    #[serde(default)]
+    budgets: Option<BudgetsSection>,
+    replay: ReplaySection,
+    supervision: SupervisionSection,
+    limits: LimitsSection,
+    debug: DebugSection,
+    teams: TeamsSection,
+    player: PlayerSection,
+    collision: CollisionSection,
+    #[serde(default)]
+    tenant: Vec<TenantSection>,
+}
+
+// what the solver spends per step, fewer iterations and a smaller prediction are cheaper but
+// let bodies sink into each other and jitter more
+#[derive(Debug, Clone, Copy)]
+pub struct WorldConfig {
+    // shapes are shrunk by it so the collider with its margin keeps the size the level asked for
+    pub collider_margin: f32,
+    pub velocity_iterations: usize,
+    pub position_iterations: usize,
+    // contacts are generated this far before the shapes touch
+    pub prediction: f32,
+}
+
+pub struct Config {
+    pub tick_rate: u64,
+    pub log_level: LevelFilter,
+    pub gravity: Vector2<f32>,
+    pub max_linear_speed: f32,
+    pub max_angular_speed: f32,
+    pub bounds_min: Vector2<f32>,
+    pub bounds_max: Vector2<f32>,
+    pub world: WorldConfig,
+    pub rope_interval_ticks: u64,
+    pub friendly_fire: bool,
+    pub results_path: Option<String>,
+    pub rcon_address: Option<String>,
+    pub scaling: ScalingPolicy,
+    pub entity_cap: Option<EntityCap>,
+    // the largest view radius a client can ask for, `None` lets them see the whole room
+    pub max_view_radius: Option<f32>,
+    pub line_of_sight: bool,
+    // what every client may send per tick
+    pub command_limit: RateLimit,
+    pub relay_limit: RateLimit,
+    pub deterministic: bool,
+    pub replay_directory: String,
+    pub lockstep: bool,
+    pub max_restarts: u32,
+    pub max_stall_ticks: u64,
+    pub restart_stalled: bool,
+    pub check_invariants: bool,
+    pub soft_limits: SoftLimits,
+    pub kinematic_players: bool,
+    pub layers: CollisionLayers,
+    // empty when the server only hosts the operator's rooms
+    pub tenants: Vec<Tenant>,
+    // kept for input logs, a replay rebuilds the room from it
+    pub source: String,
+}
+
+impl Config {
+    pub fn parse(source: &str) -> Result<Config, ConfigError> {
+        let file: ConfigFile = toml::from_str(source).map_err(ConfigError::Parse)?;
+        let layers = CollisionLayers::new(file.collision.layers, &file.collision.matrix)
+            .map_err(ConfigError::Invalid)?;
+        let tick_rate = file.server.as_ref().and_then(|server| server.tick_rate).unwrap_or(DEFAULT_TICK_RATE);
+        if tick_rate == 0 {
+            return Err(ConfigError::Invalid(String::from("server tick_rate must be at least 1")));
+        }
+        let log_level = match file.server.as_ref().and_then(|server| server.log_level.as_ref()) {
+            Some(name) => name.parse().map_err(|_| ConfigError::Invalid(format!("unknown server log_level {}, off, error, warn, info, debug or trace", name)))?,
+            None => LevelFilter::INFO,
+        };
+        let (command_limit, relay_limit) = match file.budgets {
+            Some(ref budgets) => (
+                RateLimit { per_tick: budgets.commands_per_tick, burst: budgets.command_burst },
+                RateLimit { per_tick: budgets.relays_per_tick, burst: budgets.relay_burst },
+            ),
+            None => (DEFAULT_COMMAND_LIMIT, DEFAULT_RELAY_LIMIT),
+        };
+        if [command_limit, relay_limit].iter().any(|limit| !(limit.per_tick >= 0.0) || !(limit.burst >= 1.0)) {
+            return Err(ConfigError::Invalid(String::from("budgets can't be negative and a burst must be at least 1")));
+        }
+        if file.world.bounds_min[0] >= file.world.bounds_max[0] || file.world.bounds_min[1] >= file.world.bounds_max[1] {
+            return Err(ConfigError::Invalid(String::from("world bounds_min must be below bounds_max")));
+        }
+        if !(file.world.collider_margin > 0.0) || file.world.collider_margin > MAX_COLLIDER_MARGIN {
+            return Err(ConfigError::Invalid(format!("world collider_margin must be above 0 and at most {}", MAX_COLLIDER_MARGIN)));
+        }
+        if file.world.velocity_iterations == 0 || file.world.position_iterations == 0 {
+            return Err(ConfigError::Invalid(String::from("world solver iterations must be at least 1")));
+        }
+        if !(file.world.prediction >= 0.0) {
+            return Err(ConfigError::Invalid(String::from("world prediction can't be negative")));
+        }
+        if file.scaling.max_players == 0 {
+            return Err(ConfigError::Invalid(String::from("scaling max_players must be at least 1")));
+        }
+        if file.entity_cap.as_ref().map_or(false, |cap| cap.max == 0) {
+            return Err(ConfigError::Invalid(String::from("entity_cap max must be at least 1")));
+        }
+        if file.view.as_ref().and_then(|view| view.max_radius).map_or(false, |max_radius| !(max_radius > 0.0)) {
+            return Err(ConfigError::Invalid(String::from("view max_radius must be positive")));
+        }
+        if file.supervision.max_stall_ticks == 0 {
+            return Err(ConfigError::Invalid(String::from("supervision max_stall_ticks must be at least 1")));
+        }
+        if file.replay.lockstep && !file.replay.deterministic {
+            return Err(ConfigError::Invalid(String::from("replay lockstep needs deterministic, clients step the same way the room does")));
+        }
+        if file.rope.interval_ticks == 0 {
+            return Err(ConfigError::Invalid(String::from("rope interval_ticks must be at least 1")));
+        }
+        let tenants = file.tenant.iter()
+            .map(|tenant| Tenant::new(&tenant.name, &tenant.prefix, &tenant.key_sha256))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ConfigError::Invalid)?;
+        for (i, tenant) in tenants.iter().enumerate() {
+            if tenant.name.is_empty() || tenant.prefix.is_empty() {
+                return Err(ConfigError::Invalid(String::from("a tenant needs a name and a room prefix")));
+            }
+            if let Some(other) = tenants[..i].iter().find(|other| other.name == tenant.name || other.owns(&tenant.prefix) || tenant.owns(&other.prefix)) {
+                return Err(ConfigError::Invalid(format!("tenants {} and {} share a name or their prefixes overlap, a room has one owner", other.name, tenant.name)));
+            }
+        }
+
+        Ok(Config {
+            tick_rate,
+            log_level,
+            gravity: Vector2::new(file.world.gravity[0], file.world.gravity[1]),
+            max_linear_speed: file.world.max_linear_speed,
+            max_angular_speed: file.world.max_angular_speed,
+            bounds_min: Vector2::new(file.world.bounds_min[0], file.world.bounds_min[1]),
+            bounds_max: Vector2::new(file.world.bounds_max[0], file.world.bounds_max[1]),
+            world: WorldConfig {
+                collider_margin: file.world.collider_margin,
+                velocity_iterations: file.world.velocity_iterations,
+                position_iterations: file.world.position_iterations,
+                prediction: file.world.prediction,
+            },
+            rope_interval_ticks: file.rope.interval_ticks,
+            friendly_fire: file.teams.friendly_fire,
+            results_path: file.results.map(|results| results.path),
+            rcon_address: file.rcon.map(|rcon| rcon.address),
+            scaling: ScalingPolicy {
+                max_players: file.scaling.max_players,
+                max_entities: file.scaling.max_entities,
+                empty_grace_ticks: file.scaling.empty_grace_ticks,
+            },
+            max_view_radius: file.view.as_ref().and_then(|view| view.max_radius),
+            line_of_sight: file.view.as_ref().map_or(false, |view| view.line_of_sight),
+            command_limit,
+            relay_limit,
+            entity_cap: file.entity_cap.map(|cap| EntityCap { max: cap.max, eviction: cap.eviction }),
+            deterministic: file.replay.deterministic,
+            replay_directory: file.replay.directory,
+            lockstep: file.replay.lockstep,
+            max_restarts: file.supervision.max_restarts,
+            max_stall_ticks: file.supervision.max_stall_ticks,
+            restart_stalled: file.supervision.restart_stalled,
+            check_invariants: file.debug.check_invariants,
+            soft_limits: SoftLimits {
+                max_bodies: file.limits.max_bodies,
+                max_colliders: file.limits.max_colliders,
+                max_queued_messages: file.limits.max_queued_messages,
+                max_snapshot_bytes: file.limits.max_snapshot_bytes,
+            },
+            kinematic_players: file.player.kinematic,
+            layers,
+            tenants,
+            source: source.to_string(),
+        })
+    }
+
+    pub fn defaults() -> Config {
+        Config::parse(DEFAULT_CONFIG).expect("the default config is valid")
+    }
+
+    // a missing file falls back to the defaults, a broken one is an error
+    pub fn load(path: &str) -> Result<Config, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(source) => Config::parse(&source),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                warn!(target: "main", path, "no config, using the defaults");
+                Ok(Config::defaults())
+            },
+            Err(error) => Err(ConfigError::Io(error)),
+        }
+    }
+
+    // whether a running server can swap this config for `next`, only the reloadable keys may
+    // differ, a deterministic room also steps at the rate its input log was recorded with
+    pub fn check_reload(&self, next: &Config) -> Result<(), ConfigError> {
+        let (current, other) = (keys(&self.source)?, keys(&next.source)?);
+        let mut changed: Vec<String> = current.iter()
+            .filter(|(key, value)| other.get(*key) != Some(*value))
+            .map(|(key, _)| key)
+            .chain(other.keys().filter(|key| !current.contains_key(*key)))
+            .filter(|key| !RELOADABLE.iter().any(|reloadable| key.as_str() == *reloadable || key.starts_with(&format!("{}.", reloadable))))
+            .cloned()
+            .collect();
+        if self.deterministic && next.tick_rate != self.tick_rate {
+            changed.push(String::from("server.tick_rate (the rooms are deterministic)"));
+        }
+
+        if changed.is_empty() {
+            Ok(())
+        } else {
+            changed.sort();
+            Err(ConfigError::Rebuild(changed))
+        }
+    }
+}
+
+// every value of the file by `section.key`, values outside a section by their own key
+fn keys(source: &str) -> Result<BTreeMap<String, toml::Value>, ConfigError> {
+    let file: BTreeMap<String, toml::Value> = toml::from_str(source).map_err(ConfigError::Parse)?;
+
+    let mut keys = BTreeMap::new();
+    for (name, value) in file {
+        match value {
+            toml::Value::Table(section) => keys.extend(section.into_iter().map(|(key, value)| (format!("{}.{}", name, key), value))),
+            value => {
+                keys.insert(name, value);
+            },
+        }
+    }
+    Ok(keys)
+}
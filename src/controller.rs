@@ -0,0 +1,221 @@
+use na::{Isometry2, Vector2};
+use ncollide2d::query;
+use nphysics2d::object::ColliderHandle;
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+
+// gap kept between the character and what it stands on, so the next cast doesn't start in contact
+const SKIN: f32 = 0.01;
+// a move can slide along this many surfaces before the rest of it is dropped
+const MAX_SLIDES: usize = 4;
+
+// drives a kinematic body by move-and-slide instead of letting the solver push it around
+#[derive(Debug, Clone)]
+pub struct CharacterController {
+    pub speed: f32,
+    // the controller has its own gravity, kinematic bodies ignore the world one
+    pub gravity: f32,
+    // steepest walkable slope in radians, steeper ones behave like walls
+    pub max_slope: f32,
+    // obstacles up to this height are climbed instead of blocking
+    pub step_height: f32,
+    // horizontal intent from the client, between -1 and 1
+    pub input: f32,
+    pub velocity: Vector2<f32>,
+    pub grounded: bool,
+    pub jump_speed: f32,
+    // a jump is still allowed this long after walking off a ledge, in seconds
+    pub coyote_time: f32,
+    // fraction of the jump speed kept when the jump is released early, for variable height
+    pub jump_cut: f32,
+    pub jumping: bool,
+    jump_requested: bool,
+    jump_held: bool,
+    airborne_for: f32,
+}
+
+impl CharacterController {
+    pub fn new(speed: f32) -> CharacterController {
+        CharacterController {
+            speed,
+            gravity: 30.0,
+            max_slope: std::f32::consts::FRAC_PI_4,
+            step_height: 0.5,
+            input: 0.0,
+            velocity: Vector2::zeros(),
+            grounded: false,
+            jump_speed: 15.0,
+            coyote_time: 0.1,
+            jump_cut: 0.5,
+            jumping: false,
+            jump_requested: false,
+            jump_held: false,
+            airborne_for: 0.0,
+        }
+    }
+
+    pub fn set_jump(&mut self, pressed: bool) {
+        self.jump_requested = pressed && !self.jump_held;
+        self.jump_held = pressed;
+    }
+
+    fn update_jump(&mut self, timestep: f32) {
+        if self.grounded {
+            self.airborne_for = 0.0;
+            self.jumping = false;
+        } else {
+            self.airborne_for += timestep;
+        }
+
+        // a press is only good for the tick it arrives in, holding doesn't jump again on landing
+        if self.jump_requested && !self.jumping && self.airborne_for <= self.coyote_time {
+            self.velocity.y = self.jump_speed;
+            self.jumping = true;
+            self.grounded = false;
+        }
+        self.jump_requested = false;
+
+        if self.jumping && !self.jump_held {
+            self.velocity.y = self.velocity.y.min(self.jump_speed * self.jump_cut);
+        }
+    }
+
+    fn is_walkable(&self, normal: &Vector2<f32>) -> bool {
+        normal.y >= self.max_slope.cos()
+    }
+}
+
+fn translate(position: &Isometry2<f32>, displacement: &Vector2<f32>) -> Isometry2<f32> {
+    Isometry2::new(position.translation.vector + displacement, position.rotation.angle())
+}
+
+// earliest hit of the collider's shape moved by `displacement`, as a fraction of it, with the
+// normal of the obstacle pointing back towards the character
+pub fn cast(world: &World<f32>, collider: ColliderHandle, position: &Isometry2<f32>, displacement: &Vector2<f32>) -> Option<(f32, Vector2<f32>)> {
+    let collision_world = world.collision_world();
+    let own = collision_world.collision_object(collider)?;
+    let groups = own.collision_groups();
+    let shape = own.shape();
+
+    let mut earliest: Option<(f32, ColliderHandle)> = None;
+    for other in collision_world.collision_objects() {
+        if other.handle() == collider || !groups.can_interact_with_groups(other.collision_groups()) {
+            continue;
+        }
+
+        let toi = query::time_of_impact(position, displacement, shape.as_ref(), other.position(), &Vector2::zeros(), other.shape().as_ref());
+        if let Some(toi) = toi.filter(|toi| *toi <= 1.0) {
+            if earliest.map_or(true, |(best, _)| toi < best) {
+                earliest = Some((toi, other.handle()));
+            }
+        }
+    }
+
+    let (toi, handle) = earliest?;
+    let other = collision_world.collision_object(handle)?;
+    let at = translate(position, &(displacement * toi));
+    let contact = query::contact(&at, shape.as_ref(), other.position(), other.shape().as_ref(), SKIN * 2.0)?;
+
+    Some((toi, -contact.normal.into_inner()))
+}
+
+// climbs an obstacle up to `step_height` high, `None` when it's too high or there's no room above
+fn step_up(world: &World<f32>, collider: ColliderHandle, position: &Isometry2<f32>, horizontal: &Vector2<f32>, step_height: f32) -> Option<Isometry2<f32>> {
+    let up = Vector2::y() * step_height;
+    if cast(world, collider, position, &up).is_some() {
+        return None;
+    }
+
+    let raised = translate(position, &up);
+    if cast(world, collider, &raised, horizontal).is_some() {
+        return None;
+    }
+
+    let moved = translate(&raised, horizontal);
+    let toi = cast(world, collider, &moved, &-up).map_or(1.0, |(toi, _)| toi);
+    Some(translate(&moved, &(-up * toi + Vector2::y() * SKIN)))
+}
+
+// returns the displacement actually possible and whether the character ended up on walkable ground
+fn move_and_slide(world: &World<f32>, collider: ColliderHandle, start: &Isometry2<f32>, displacement: Vector2<f32>, controller: &CharacterController) -> (Vector2<f32>, bool) {
+    let mut position = *start;
+    let mut remaining = displacement;
+    let mut grounded = false;
+
+    for _ in 0..MAX_SLIDES {
+        let length = remaining.norm();
+        if length < SKIN {
+            break;
+        }
+
+        let (toi, normal) = match cast(world, collider, &position, &remaining) {
+            Some(hit) => hit,
+            None => {
+                position = translate(&position, &remaining);
+                break;
+            },
+        };
+
+        let travel = (toi * length - SKIN).max(0.0);
+        position = translate(&position, &(remaining / length * travel));
+        remaining *= 1.0 - toi;
+
+        let normal = if controller.is_walkable(&normal) {
+            grounded = true;
+            normal
+        } else {
+            let horizontal = Vector2::new(remaining.x, 0.0);
+            if controller.grounded && horizontal.x != 0.0 {
+                if let Some(stepped) = step_up(world, collider, &position, &horizontal, controller.step_height) {
+                    position = stepped;
+                    remaining.x = 0.0;
+                    grounded = true;
+                    continue;
+                }
+            }
+
+            // too steep to walk on, it only blocks sideways so the character can't climb it
+            match Vector2::new(normal.x, 0.0).try_normalize(1.0e-6) {
+                Some(wall) => wall,
+                None => normal,
+            }
+        };
+
+        remaining -= normal * remaining.dot(&normal).min(0.0);
+    }
+
+    (position.translation.vector - start.translation.vector, grounded)
+}
+
+// runs before the step: the body is given the velocity that lands it where move-and-slide allowed
+pub fn move_characters(world: &mut World<f32>, entities: &hecs::World) {
+    let timestep = world.timestep();
+
+    for (_, (physics_body, controller)) in entities.query::<(&PhysicsBody, &mut CharacterController)>().iter() {
+        let position = match world.rigid_body(physics_body.body) {
+            Some(rigid_body) => *rigid_body.position(),
+            None => continue,
+        };
+
+        controller.update_jump(timestep);
+
+        let mut velocity = Vector2::new(controller.input * controller.speed, controller.velocity.y - controller.gravity * timestep);
+        if controller.grounded && velocity.y < 0.0 {
+            // keeps it pressed on the ground so walking down a slope doesn't turn into falling
+            velocity.y = -controller.speed;
+        }
+
+        let (moved, grounded) = move_and_slide(world, physics_body.collider, &position, velocity * timestep, controller);
+        controller.grounded = grounded;
+        controller.velocity = moved / timestep;
+        if grounded {
+            controller.velocity.y = controller.velocity.y.max(0.0);
+        }
+
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            rigid_body.set_linear_velocity(moved / timestep);
+            rigid_body.set_angular_velocity(0.0);
+        }
+    }
+}
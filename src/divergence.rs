@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use tracing::{error, info};
+
+use crate::protocol::{EntityState, NetId};
+use crate::replay::{self, InputLog, ReplayError, Replayer};
+
+// one entity whose state differs between the two runs, `None` when it only exists in the other one
+#[derive(Debug, Clone)]
+pub struct Difference {
+    pub entity: NetId,
+    pub first: Option<EntityState>,
+    pub second: Option<EntityState>,
+}
+
+// bit for bit, a difference in the last bit is still a divergence
+fn same(a: &EntityState, b: &EntityState) -> bool {
+    let bits = |state: &EntityState| [
+        state.position.translation.vector.x.to_bits(),
+        state.position.translation.vector.y.to_bits(),
+        state.position.rotation.angle().to_bits(),
+        state.velocity.linear.x.to_bits(),
+        state.velocity.linear.y.to_bits(),
+        state.velocity.angular.to_bits(),
+    ];
+    bits(a) == bits(b)
+}
+
+pub fn compare(first: &[EntityState], second: &[EntityState]) -> Vec<Difference> {
+    let mut entities: BTreeMap<NetId, (Option<&EntityState>, Option<&EntityState>)> = BTreeMap::new();
+    for state in first {
+        entities.entry(state.id).or_insert((None, None)).0 = Some(state);
+    }
+    for state in second {
+        entities.entry(state.id).or_insert((None, None)).1 = Some(state);
+    }
+
+    entities.into_iter()
+        .filter(|(_, states)| match states {
+            (Some(a), Some(b)) => !same(a, b),
+            _ => true,
+        })
+        .map(|(entity, (first, second))| Difference { entity, first: first.cloned(), second: second.cloned() })
+        .collect()
+}
+
+fn describe(state: &Option<EntityState>) -> String {
+    match state {
+        Some(state) => format!(
+            "at ({}, {}) angle {} moving ({}, {}) spin {}",
+            state.position.translation.vector.x,
+            state.position.translation.vector.y,
+            state.position.rotation.angle(),
+            state.velocity.linear.x,
+            state.velocity.linear.y,
+            state.velocity.angular,
+        ),
+        None => String::from("missing"),
+    }
+}
+
+// runs the log twice side by side, one tick each in turn, and stops at the first tick where the
+// two rooms don't agree on every entity, returns that tick and what differs
+pub fn run(path: &str) -> Result<Option<(u64, Vec<Difference>)>, ReplayError> {
+    let log = InputLog::load(path)?;
+    let mut first = Replayer::new(&log)?;
+    let mut second = Replayer::new(&log)?;
+
+    info!(target: "diverge", room = %log.room, ticks = log.ticks, path, "running the input log twice");
+    for (tick, inputs) in replay::ticks(&log) {
+        first.step(tick, inputs);
+        second.step(tick, inputs);
+
+        let differences = compare(&first.room.states(), &second.room.states());
+        if differences.is_empty() {
+            continue;
+        }
+
+        error!(target: "diverge", room = %log.room, tick, entities = differences.len(), "the runs split");
+        for difference in differences.iter() {
+            error!(target: "diverge", room = %log.room, tick, entity = difference.entity, first = %describe(&difference.first), second = %describe(&difference.second), "entity differs");
+        }
+        return Ok(Some((tick, differences)));
+    }
+
+    info!(target: "diverge", room = %log.room, ticks = log.ticks, "both runs agreed");
+    Ok(None)
+}
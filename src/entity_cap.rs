@@ -0,0 +1,42 @@
+use hecs::Entity;
+use nphysics2d::world::World;
+use serde::Deserialize;
+
+use crate::components::{Joints, Owner, PhysicsBody, Replicated};
+use crate::protocol::EntityKind;
+
+// what happens to a spawn once the room holds `max` entities, players always get in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Eviction {
+    Reject,
+    // ownerless props and projectiles, the first spawned goes first
+    DespawnOldestDebris,
+    // ownerless bodies that fell asleep, players and their entities are never picked
+    DespawnSleeping,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EntityCap {
+    pub max: usize,
+    pub eviction: Eviction,
+}
+
+// the entity to despawn to make room for a new one, `None` when the spawn has to be rejected
+pub fn victim(eviction: Eviction, world: &World<f32>, entities: &hecs::World) -> Option<Entity> {
+    let mut query = entities.query::<(&PhysicsBody, &Replicated, &EntityKind, Option<&Owner>, Option<&Joints>)>();
+    let candidates = query.iter().filter(|(_, (physics_body, _, kind, owner, joints))| {
+        // jointed entities are part of something larger, like the links of a chain
+        if owner.is_some() || joints.is_some() || **kind == EntityKind::Player {
+            return false;
+        }
+        match eviction {
+            Eviction::Reject => false,
+            Eviction::DespawnOldestDebris => **kind == EntityKind::Prop || **kind == EntityKind::Projectile,
+            Eviction::DespawnSleeping => world.rigid_body(physics_body.body).map_or(false, |rigid_body| !rigid_body.is_active()),
+        }
+    });
+
+    // ids follow spawn order
+    candidates.min_by_key(|(_, (_, replicated, _, _, _))| replicated.id).map(|(entity, _)| entity)
+}
@@ -0,0 +1,144 @@
+use std::sync::mpsc::Sender;
+
+use na::{Isometry2, Point2, Vector2};
+use nphysics2d::algebra::Velocity2;
+use nphysics2d::world::World;
+
+use crate::components::{Owner, PhysicsBody};
+use crate::net_ids::NetIds;
+use crate::protocol::{ClientId, Command, EntityKind, NetId};
+
+#[derive(Debug, Clone)]
+pub struct ContactReport {
+    // `None` for colliders that aren't replicated, like chunk blocks
+    pub other: Option<NetId>,
+    pub point: Point2<f32>,
+    // pointing away from the watched entity
+    pub normal: Vector2<f32>,
+    pub depth: f32,
+}
+
+// everything known about one entity at the end of a tick
+#[derive(Debug, Clone)]
+pub struct EntityReport {
+    pub tick: u64,
+    pub entity: NetId,
+    pub kind: EntityKind,
+    pub owner: Option<ClientId>,
+    pub position: Isometry2<f32>,
+    pub velocity: Velocity2<f32>,
+    pub asleep: bool,
+    pub mass: f32,
+    // what every force, impulse and contact did to it since the previous report, gravity included
+    pub velocity_change: Vector2<f32>,
+    pub contacts: Vec<ContactReport>,
+    // the commands its owner sent since the previous report, rejected ones included
+    pub commands: Vec<Command>,
+}
+
+struct Watch {
+    entity: NetId,
+    tx: Sender<EntityReport>,
+    last_velocity: Option<Vector2<f32>>,
+    commands: Vec<Command>,
+}
+
+// entities someone asked to follow, a watch ends when its receiver is dropped or the entity despawned
+#[derive(Default)]
+pub struct EntityWatches {
+    watches: Vec<Watch>,
+}
+
+impl EntityWatches {
+    pub fn add(&mut self, entity: NetId, tx: Sender<EntityReport>) {
+        self.watches.push(Watch { entity, tx, last_velocity: None, commands: vec![] });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    pub fn record_command(&mut self, command: &Command) {
+        for watch in self.watches.iter_mut().filter(|watch| watch.entity == command.entity()) {
+            watch.commands.push(command.clone());
+        }
+    }
+
+    pub fn report(&mut self, tick: u64, world: &World<f32>, entities: &hecs::World, net_ids: &NetIds) {
+        let mut ended = vec![];
+
+        for (i, watch) in self.watches.iter_mut().enumerate() {
+            let report = match entity_report(tick, watch, world, entities, net_ids) {
+                Some(report) => report,
+                None => {
+                    ended.push(i);
+                    continue;
+                },
+            };
+            watch.last_velocity = Some(report.velocity.linear);
+            watch.commands.clear();
+            if watch.tx.send(report).is_err() {
+                ended.push(i);
+            }
+        }
+
+        for i in ended.into_iter().rev() {
+            self.watches.remove(i);
+        }
+    }
+}
+
+fn entity_report(tick: u64, watch: &Watch, world: &World<f32>, entities: &hecs::World, net_ids: &NetIds) -> Option<EntityReport> {
+    let entity = net_ids.entity(watch.entity)?;
+    let physics_body = *entities.get::<PhysicsBody>(entity).ok()?;
+    let kind = *entities.get::<EntityKind>(entity).ok()?;
+    let owner = entities.get::<Owner>(entity).ok().map(|owner| owner.0);
+
+    let (position, velocity, asleep, mass) = match world.rigid_body(physics_body.body) {
+        Some(rigid_body) => (*rigid_body.position(), *rigid_body.velocity(), !rigid_body.is_active(), rigid_body.local_inertia().linear),
+        None => (*world.collider(physics_body.collider)?.position(), Velocity2::zero(), false, 0.0),
+    };
+
+    Some(EntityReport {
+        tick,
+        entity: watch.entity,
+        kind,
+        owner,
+        position,
+        velocity,
+        asleep,
+        mass,
+        velocity_change: watch.last_velocity.map_or_else(Vector2::zeros, |last| velocity.linear - last),
+        contacts: contacts(world, net_ids, physics_body),
+        commands: watch.commands.clone(),
+    })
+}
+
+// the deepest point of every manifold the entity's collider is part of
+fn contacts(world: &World<f32>, net_ids: &NetIds, physics_body: PhysicsBody) -> Vec<ContactReport> {
+    let mut contacts = vec![];
+    let mut manifolds = vec![];
+
+    for (object_a, object_b, algorithm) in world.collision_world().contact_pairs() {
+        let (other, flipped) = if object_a.handle() == physics_body.collider {
+            (object_b.handle(), false)
+        } else if object_b.handle() == physics_body.collider {
+            (object_a.handle(), true)
+        } else {
+            continue;
+        };
+
+        manifolds.clear();
+        algorithm.contacts(&mut manifolds);
+        for deepest in manifolds.iter().filter_map(|manifold| manifold.deepest_contact()) {
+            let normal = deepest.contact.normal.into_inner();
+            contacts.push(ContactReport {
+                other: net_ids.by_collider(other),
+                point: na::center(&deepest.contact.world1, &deepest.contact.world2),
+                normal: if flipped { -normal } else { normal },
+                depth: deepest.contact.depth,
+            });
+        }
+    }
+    contacts
+}
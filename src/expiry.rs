@@ -0,0 +1,30 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::protocol::NetId;
+
+// despawns planned for a given tick, ids are never reused so an entity gone in the meantime
+// is simply not found when its time comes
+#[derive(Default)]
+pub struct DespawnSchedule {
+    due: BinaryHeap<Reverse<(u64, NetId)>>,
+}
+
+impl DespawnSchedule {
+    pub fn schedule(&mut self, tick: u64, id: NetId) {
+        self.due.push(Reverse((tick, id)));
+    }
+
+    // everything due at `tick` or earlier, in order
+    pub fn expired(&mut self, tick: u64) -> Vec<NetId> {
+        let mut expired = vec![];
+        while let Some(&Reverse((due, id))) = self.due.peek() {
+            if due > tick {
+                break;
+            }
+            self.due.pop();
+            expired.push(id);
+        }
+        expired
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use na::{Point2, Vector2};
+use nphysics2d::world::World;
+
+use crate::components::{Invulnerable, Owner, Payload, Team};
+use crate::melee;
+use crate::net_ids::NetIds;
+use crate::protocol::{CameraHint, ClientId, EntityKind, Event, Metadata, NetId};
+use crate::random::Random;
+use crate::room::SpawnDescriptor;
+use crate::spatial_cache::SpatialCache;
+
+const BOUNCE_IMPULSE: f32 = 30.0;
+
+// what handlers can ask for, applied by the room once every handler has run
+pub enum GameplayCommand {
+    Spawn { position: Vector2<f32>, descriptor: SpawnDescriptor },
+    Despawn(NetId),
+    Impulse { entity: NetId, impulse: Vector2<f32> },
+    Knockback { entity: NetId, impulse: Vector2<f32> },
+    Transfer { entity: NetId, room: String, spawn_point: String },
+    SetPayload { entity: NetId, payload: Vec<u8> },
+    SetCamera { client: ClientId, hint: CameraHint },
+    Explode { position: Vector2<f32>, radius: f32, impulse: f32, damage: f32, cover: Option<f32> },
+}
+
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Vec<GameplayCommand>,
+}
+
+impl CommandQueue {
+    pub fn spawn(&mut self, position: Vector2<f32>, descriptor: SpawnDescriptor) {
+        self.commands.push(GameplayCommand::Spawn { position, descriptor });
+    }
+
+    pub fn despawn(&mut self, entity: NetId) {
+        self.commands.push(GameplayCommand::Despawn(entity));
+    }
+
+    pub fn impulse(&mut self, entity: NetId, impulse: Vector2<f32>) {
+        self.commands.push(GameplayCommand::Impulse { entity, impulse });
+    }
+
+    pub fn knockback(&mut self, entity: NetId, impulse: Vector2<f32>) {
+        self.commands.push(GameplayCommand::Knockback { entity, impulse });
+    }
+
+    pub fn transfer(&mut self, entity: NetId, room: String, spawn_point: String) {
+        self.commands.push(GameplayCommand::Transfer { entity, room, spawn_point });
+    }
+
+    // replicated to clients only when it differs from the current one
+    pub fn set_payload(&mut self, entity: NetId, payload: Vec<u8>) {
+        self.commands.push(GameplayCommand::SetPayload { entity, payload });
+    }
+
+    // follow a ball, zoom out for a replay, see `CameraHint`
+    pub fn set_camera(&mut self, client: ClientId, hint: CameraHint) {
+        self.commands.push(GameplayCommand::SetCamera { client, hint });
+    }
+
+    // see `AdminCommand::Explode`
+    pub fn explode(&mut self, position: Vector2<f32>, radius: f32, impulse: f32, damage: f32, cover: Option<f32>) {
+        self.commands.push(GameplayCommand::Explode { position, radius, impulse, damage, cover });
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = GameplayCommand> + '_ {
+        self.commands.drain(..)
+    }
+}
+
+// read-only view of the room given to handlers
+pub struct Context<'a> {
+    world: &'a World<f32>,
+    entities: &'a hecs::World,
+    net_ids: &'a NetIds,
+    spatial: &'a SpatialCache,
+    random: &'a Random,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(world: &'a World<f32>, entities: &'a hecs::World, net_ids: &'a NetIds, spatial: &'a SpatialCache, random: &'a Random) -> Context<'a> {
+        Context {
+            world,
+            entities,
+            net_ids,
+            spatial,
+            random,
+        }
+    }
+
+    // drops and anything else players could argue about, every draw is logged, see `Random`
+    pub fn random(&self) -> &Random {
+        self.random
+    }
+
+    // the entities around the region, handlers asking about the same area in a tick share the
+    // answer, see `SpatialCache`
+    pub fn entities_in(&self, mins: Point2<f32>, maxs: Point2<f32>) -> Arc<Vec<NetId>> {
+        self.spatial.entities_in(self.world, self.net_ids, mins, maxs)
+    }
+
+    // see `melee::in_arc`
+    pub fn entities_in_arc(&self, origin: Point2<f32>, direction: Vector2<f32>, radius: f32, angle: f32) -> Vec<NetId> {
+        melee::in_arc(self.world, self.entities, self.net_ids, self.spatial, origin, direction, radius, angle)
+    }
+
+    pub fn kind(&self, entity: NetId) -> Option<EntityKind> {
+        let entity = self.net_ids.entity(entity)?;
+        self.entities.get::<EntityKind>(entity).ok().map(|kind| *kind)
+    }
+
+    pub fn owner(&self, entity: NetId) -> Option<ClientId> {
+        let entity = self.net_ids.entity(entity)?;
+        self.entities.get::<Owner>(entity).ok().map(|owner| owner.0)
+    }
+
+    pub fn is_invulnerable(&self, entity: NetId) -> bool {
+        self.net_ids.entity(entity).map_or(false, |entity| self.entities.get::<Invulnerable>(entity).is_ok())
+    }
+
+    // damage rules check this when friendly fire is off, collisions are already filtered
+    pub fn same_team(&self, a: NetId, b: NetId) -> bool {
+        let team = |entity| self.net_ids.entity(entity).and_then(|entity| self.entities.get::<Team>(entity).ok().map(|team| *team));
+        match (team(a), team(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    pub fn has_tag(&self, entity: NetId, tag: &str) -> bool {
+        self.metadata(entity, |metadata| metadata.tags.iter().any(|candidate| candidate == tag)).unwrap_or(false)
+    }
+
+    pub fn value(&self, entity: NetId, key: &str) -> Option<String> {
+        self.metadata(entity, |metadata| metadata.values.get(key).cloned())?
+    }
+
+    pub fn payload(&self, entity: NetId) -> Option<Vec<u8>> {
+        let entity = self.net_ids.entity(entity)?;
+        self.entities.get::<Payload>(entity).ok().map(|payload| payload.0.clone())
+    }
+
+    fn metadata<T>(&self, entity: NetId, read: impl FnOnce(&Metadata) -> T) -> Option<T> {
+        let entity = self.net_ids.entity(entity)?;
+        self.entities.get::<Metadata>(entity).ok().map(|metadata| read(&metadata))
+    }
+}
+
+// gameplay rules live here instead of in the tick loop, handlers see every event of the tick
+pub trait GameplayHandler: Send {
+    fn on_event(&mut self, event: &Event, context: &Context, commands: &mut CommandQueue);
+}
+
+// entities tagged `bounce_pad` push whatever hits them along the contact normal,
+// the `bounce` value overrides the impulse
+pub struct BouncePad;
+
+impl GameplayHandler for BouncePad {
+    fn on_event(&mut self, event: &Event, context: &Context, commands: &mut CommandQueue) {
+        if let Event::ContactStarted { a, b, normal, .. } = *event {
+            let (pad, target, direction) = if context.has_tag(a, "bounce_pad") {
+                (a, b, normal)
+            } else if context.has_tag(b, "bounce_pad") {
+                (b, a, -normal)
+            } else {
+                return;
+            };
+
+            let strength = context.value(pad, "bounce")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(BOUNCE_IMPULSE);
+            commands.impulse(target, direction * strength);
+        }
+    }
+}
+
+// entities tagged `kill_zone` despawn anything that touches them, unless it's invulnerable
+pub struct KillZone;
+
+impl GameplayHandler for KillZone {
+    fn on_event(&mut self, event: &Event, context: &Context, commands: &mut CommandQueue) {
+        if let Event::ContactStarted { a, b, .. } = *event {
+            if context.has_tag(a, "kill_zone") && !context.has_tag(b, "kill_zone") && !context.is_invulnerable(b) {
+                commands.despawn(b);
+            } else if context.has_tag(b, "kill_zone") && !context.has_tag(a, "kill_zone") && !context.is_invulnerable(a) {
+                commands.despawn(a);
+            }
+        }
+    }
+}
+
+// entities tagged `portal` send the players touching them, along with their client, to the spawn
+// point named by their `spawn_point` value in the room named by their `room` value
+pub struct Portal;
+
+impl GameplayHandler for Portal {
+    fn on_event(&mut self, event: &Event, context: &Context, commands: &mut CommandQueue) {
+        if let Event::ContactStarted { a, b, .. } = *event {
+            let (portal, target) = if context.has_tag(a, "portal") {
+                (a, b)
+            } else if context.has_tag(b, "portal") {
+                (b, a)
+            } else {
+                return;
+            };
+            if context.kind(target) != Some(EntityKind::Player) {
+                return;
+            }
+
+            if let (Some(room), Some(spawn_point)) = (context.value(portal, "room"), context.value(portal, "spawn_point")) {
+                commands.transfer(target, room, spawn_point);
+            }
+        }
+    }
+}
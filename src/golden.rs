@@ -0,0 +1,115 @@
+use std::fmt;
+use std::fs;
+use std::io;
+
+use tracing::info;
+
+use crate::protocol::EntityState;
+use crate::replay::{self, InputLog, ReplayError, Replayer};
+
+// set to rewrite golden files with the current state instead of comparing against them
+pub const BLESS_VAR: &str = "SERVER_PHYSIC_BLESS";
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    Replay(ReplayError),
+    // one line per difference
+    Mismatch(Vec<String>),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Io(error) => write!(f, "can't access golden file: {}", error),
+            GoldenError::Replay(error) => write!(f, "{}", error),
+            GoldenError::Mismatch(differences) => write!(f, "state differs from the golden file:\n{}", differences.join("\n")),
+        }
+    }
+}
+
+// one line per entity in id order, floats printed so they read back to the same bits
+pub fn summary(states: &[EntityState]) -> String {
+    let mut states: Vec<&EntityState> = states.iter().collect();
+    states.sort_by_key(|state| state.id);
+
+    states.iter().map(|state| format!(
+        "entity {} position {:?} {:?} {:?} velocity {:?} {:?} {:?}\n",
+        state.id,
+        state.position.translation.vector.x,
+        state.position.translation.vector.y,
+        state.position.rotation.angle(),
+        state.velocity.linear.x,
+        state.velocity.linear.y,
+        state.velocity.angular,
+    )).collect()
+}
+
+// words have to match exactly, numbers within `tolerance`
+pub fn compare(expected: &str, actual: &str, tolerance: f32) -> Vec<String> {
+    let mut differences = vec![];
+    let (expected_lines, actual_lines): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+
+    for i in 0..expected_lines.len().max(actual_lines.len()) {
+        match (expected_lines.get(i), actual_lines.get(i)) {
+            (Some(expected), Some(actual)) if !same_line(expected, actual, tolerance) => {
+                differences.push(format!("line {}: expected `{}`, got `{}`", i + 1, expected, actual));
+            },
+            (Some(expected), None) => differences.push(format!("line {}: expected `{}`, got nothing", i + 1, expected)),
+            (None, Some(actual)) => differences.push(format!("line {}: unexpected `{}`", i + 1, actual)),
+            _ => {},
+        }
+    }
+    differences
+}
+
+fn same_line(expected: &str, actual: &str, tolerance: f32) -> bool {
+    let (expected, actual): (Vec<&str>, Vec<&str>) = (expected.split_whitespace().collect(), actual.split_whitespace().collect());
+
+    expected.len() == actual.len() && expected.iter().zip(actual.iter()).all(|(a, b)| {
+        match (a.parse::<f32>(), b.parse::<f32>()) {
+            (Ok(a), Ok(b)) => a == b || (a - b).abs() <= tolerance,
+            _ => a == b,
+        }
+    })
+}
+
+// a missing golden file is written from `actual`, like when blessing
+pub fn check(path: &str, actual: &str, tolerance: f32) -> Result<(), GoldenError> {
+    let expected = match fs::read_to_string(path) {
+        Ok(_) if std::env::var(BLESS_VAR).is_ok() => None,
+        Ok(expected) => Some(expected),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(GoldenError::Io(error)),
+    };
+
+    match expected {
+        Some(expected) => {
+            let differences = compare(&expected, actual, tolerance);
+            if differences.is_empty() {
+                Ok(())
+            } else {
+                Err(GoldenError::Mismatch(differences))
+            }
+        },
+        None => {
+            info!(target: "golden", path, "writing the golden file");
+            fs::write(path, actual).map_err(GoldenError::Io)
+        },
+    }
+}
+
+// replays the input log up to `tick`, the last one when `None`, and checks the room state then
+pub fn run(log_path: &str, tick: Option<u64>, golden_path: &str, tolerance: f32) -> Result<(), GoldenError> {
+    let log = InputLog::load(log_path).map_err(GoldenError::Replay)?;
+    let mut replayer = Replayer::new(&log).map_err(GoldenError::Replay)?;
+    let last = tick.unwrap_or(log.ticks.saturating_sub(1));
+
+    for (tick, inputs) in replay::ticks(&log).take_while(|(tick, _)| *tick <= last) {
+        replayer.step(tick, inputs);
+    }
+
+    check(golden_path, &summary(&replayer.room.states()), tolerance)?;
+    info!(target: "golden", room = %log.room, tick = last, path = golden_path, "state matches the golden file");
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use hecs::Entity;
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::query::Ray;
+use nphysics2d::object::{BodyHandle, ColliderHandle};
+use nphysics2d::world::World;
+
+use crate::body_pool;
+use crate::components::PhysicsBody;
+
+// reeling in stops at this length so the rope never collapses on the player
+const MIN_LENGTH: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Anchor {
+    // a static point in the world, walls included
+    Point(Point2<f32>),
+    // follows the hooked body, `local` is the hit point in the body frame
+    Body { body: BodyHandle, local: Point2<f32> },
+}
+
+// a rope that only pulls: the entity can move freely closer than `length` to the anchor
+#[derive(Debug, Clone, Copy)]
+pub struct Grapple {
+    pub anchor: Anchor,
+    pub length: f32,
+    // positive reels in, negative lets the rope out, in units per second
+    pub reel_speed: f32,
+    // world position of the anchor after the last step, this is what clients draw
+    pub end: Point2<f32>,
+}
+
+// closest collider hit by the ray within `range`, the caster's own collider is skipped
+pub fn attach(world: &World<f32>, physics_body: &PhysicsBody, direction: Vector2<f32>, range: f32) -> Option<Grapple> {
+    let collision_world = world.collision_world();
+    let own = collision_world.collision_object(physics_body.collider)?;
+    let origin = Point2::from(own.position().translation.vector);
+    let ray = Ray::new(origin, direction.normalize());
+
+    let mut closest: Option<(f32, ColliderHandle)> = None;
+    for (object, intersection) in collision_world.interferences_with_ray(&ray, own.collision_groups()) {
+        if object.handle() == physics_body.collider || intersection.toi > range {
+            continue;
+        }
+        if closest.map_or(true, |(toi, _)| intersection.toi < toi) {
+            closest = Some((intersection.toi, object.handle()));
+        }
+    }
+
+    let (toi, handle) = closest?;
+    let hit = ray.origin + ray.dir * toi;
+    let body = collision_world.collision_object(handle)?.data().body();
+    let anchor = match world.rigid_body(body) {
+        Some(rigid_body) => Anchor::Body { body, local: rigid_body.position().inverse_transform_point(&hit) },
+        None => Anchor::Point(hit),
+    };
+
+    Some(Grapple { anchor, length: toi.max(MIN_LENGTH), reel_speed: 0.0, end: hit })
+}
+
+// runs after each step, pulls the entity back on the rope and the hooked body towards it,
+// returns the entities whose rope lost its anchor
+pub fn constrain(world: &mut World<f32>, entities: &hecs::World) -> Vec<Entity> {
+    let timestep = world.timestep();
+    let mut broken = vec![];
+
+    for (entity, (physics_body, grapple)) in entities.query::<(&PhysicsBody, &mut Grapple)>().iter() {
+        let anchor = match grapple.anchor {
+            Anchor::Point(point) => point,
+            Anchor::Body { body, local } => match world.rigid_body(body) {
+                Some(rigid_body) if !body_pool::is_parked(world, body) => rigid_body.position() * local,
+                _ => {
+                    broken.push(entity);
+                    continue;
+                },
+            },
+        };
+        grapple.end = anchor;
+        grapple.length = (grapple.length - grapple.reel_speed * timestep).max(MIN_LENGTH);
+
+        let (position, velocity, mass) = match world.rigid_body(physics_body.body) {
+            Some(rigid_body) => (*rigid_body.position(), rigid_body.velocity().linear, rigid_body.local_inertia().linear),
+            None => continue,
+        };
+
+        let offset = position.translation.vector - anchor.coords;
+        let distance = offset.norm();
+        if distance <= grapple.length || distance == 0.0 {
+            continue;
+        }
+
+        let direction = offset / distance;
+        let outward = velocity.dot(&direction);
+
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            let corrected = anchor.coords + direction * grapple.length;
+            rigid_body.set_position(Isometry2::new(corrected, position.rotation.angle()));
+            if outward > 0.0 {
+                rigid_body.set_linear_velocity(velocity - direction * outward);
+            }
+            rigid_body.activate();
+        }
+
+        // the momentum taken from the entity goes to the hooked body
+        if let Anchor::Body { body, .. } = grapple.anchor {
+            if let Some(hooked) = world.rigid_body_mut(body) {
+                let hooked_mass = hooked.local_inertia().linear;
+                if outward > 0.0 && hooked_mass > 0.0 {
+                    let pulled = hooked.velocity().linear + direction * (outward * mass / hooked_mass);
+                    hooked.set_linear_velocity(pulled);
+                    hooked.activate();
+                }
+            }
+        }
+    }
+
+    broken
+}
@@ -0,0 +1,23 @@
+// gamepad rumble from what the client's own entities went through, a hit this hard shakes at full
+// strength, softer ones shake less and shorter
+const FULL_IMPULSE: f32 = 100.0;
+const MIN_DURATION_MS: f32 = 40.0;
+const MAX_DURATION_MS: f32 = 250.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Feedback {
+    // between 0 and 1
+    pub magnitude: f32,
+    pub duration_ms: u16,
+}
+
+// `None` below `min_impulse`, landing from a small hop shouldn't rumble
+pub fn feedback(impulse: f32, min_impulse: f32) -> Option<Feedback> {
+    if !(impulse >= min_impulse) {
+        return None;
+    }
+
+    let magnitude = (impulse / FULL_IMPULSE).min(1.0);
+    let duration_ms = MIN_DURATION_MS + (MAX_DURATION_MS - MIN_DURATION_MS) * magnitude;
+    Some(Feedback { magnitude, duration_ms: duration_ms.round() as u16 })
+}
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::query::Ray;
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::object::ColliderHandle;
+use nphysics2d::world::World;
+
+use crate::components::{PhysicsBody, Replicated};
+use crate::protocol::NetId;
+
+// where every replicated collider was at the end of the last ticks, shots are checked against the
+// room their shooter saw when firing rather than the one the server moved on to since
+pub struct History {
+    frames: VecDeque<(u64, Vec<(NetId, ColliderHandle, Isometry2<f32>)>)>,
+    max_ticks: usize,
+}
+
+impl History {
+    pub fn new(max_ticks: usize) -> History {
+        History { frames: VecDeque::with_capacity(max_ticks), max_ticks }
+    }
+
+    // after the step, what the snapshot of `tick` shows
+    pub fn record(&mut self, tick: u64, world: &World<f32>, entities: &hecs::World) {
+        if self.frames.len() >= self.max_ticks {
+            self.frames.pop_front();
+        }
+
+        let colliders = entities.query::<(&PhysicsBody, &Replicated)>()
+            .iter()
+            .filter_map(|(_, (physics_body, replicated))| {
+                let collider = world.collider(physics_body.collider)?;
+                Some((replicated.id, physics_body.collider, *collider.position()))
+            })
+            .collect();
+        self.frames.push_back((tick, colliders));
+    }
+
+    // clamped to the ticks kept, a client can't rewind further by claiming an older tick
+    fn at(&self, tick: u64) -> Option<&[(NetId, ColliderHandle, Isometry2<f32>)]> {
+        self.frames.iter()
+            .find(|(recorded, _)| *recorded >= tick)
+            .or_else(|| self.frames.back())
+            .map(|(_, colliders)| colliders.as_slice())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    pub target: NetId,
+    pub point: Point2<f32>,
+}
+
+// the closest entity the shot crosses as it was at `tick`, static geometry doesn't move so it's
+// checked where it is and stops the shot, `alive` skips the entities despawned since
+pub fn fire(
+    world: &World<f32>,
+    history: &History,
+    tick: u64,
+    shooter: NetId,
+    origin: Point2<f32>,
+    direction: Vector2<f32>,
+    range: f32,
+    alive: impl Fn(NetId) -> bool,
+) -> Option<Hit> {
+    let ray = Ray::new(origin, direction.normalize());
+    let cover = world.collision_world().interferences_with_ray(&ray, &CollisionGroups::new())
+        .filter(|(object, _)| object.data().body().is_ground())
+        .map(|(_, intersection)| intersection.toi)
+        .fold(range, f32::min);
+
+    let mut closest: Option<(f32, NetId)> = None;
+    for (id, collider, position) in history.at(tick)? {
+        if *id == shooter || !alive(*id) {
+            continue;
+        }
+        let toi = world.collider(*collider)
+            .and_then(|collider| collider.shape().as_ray_cast())
+            .and_then(|shape| shape.toi_with_ray(position, &ray, true));
+        match toi {
+            Some(toi) if toi <= cover && closest.map_or(true, |(closest, _)| toi < closest) => closest = Some((toi, *id)),
+            _ => {},
+        }
+    }
+
+    closest.map(|(toi, target)| Hit { target, point: ray.origin + ray.dir * toi })
+}
@@ -0,0 +1,25 @@
+use std::collections::HashMap;
+
+// a pile of crates settling makes dozens of impacts a tick, clients only need one sound per
+// material pair every so often, the pair is counted in either order
+pub struct ImpactLimiter {
+    last: HashMap<(u8, u8), u64>,
+    interval_ticks: u64,
+}
+
+impl ImpactLimiter {
+    pub fn new(interval_ticks: u64) -> ImpactLimiter {
+        ImpactLimiter { last: HashMap::new(), interval_ticks }
+    }
+
+    pub fn allow(&mut self, tick: u64, a: u8, b: u8) -> bool {
+        let pair = (a.min(b), a.max(b));
+        match self.last.get(&pair) {
+            Some(last) if tick < last + self.interval_ticks => false,
+            _ => {
+                self.last.insert(pair, tick);
+                true
+            },
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::Write;
+
+use na::{Isometry2, Point2};
+use ncollide2d::shape::{Ball, Cuboid, Polyline};
+use nphysics2d::algebra::Velocity2;
+use nphysics2d::world::World;
+
+use crate::components::{Owner, PhysicsBody, Replicated};
+use crate::net_ids::NetIds;
+use crate::protocol::{ClientId, EntityKind, NetId};
+use crate::replay::{self, InputLog, ReplayError, Replayer};
+
+pub struct EntityRow {
+    pub id: NetId,
+    pub kind: EntityKind,
+    pub position: Isometry2<f32>,
+    pub velocity: Velocity2<f32>,
+    pub owner: Option<ClientId>,
+    // `None` for static entities, they have no rigid body to fall asleep
+    pub asleep: Option<bool>,
+}
+
+// a collider attached to the ground, walls and terrain as well as the blocks of loaded chunks
+pub struct ColliderRow {
+    pub uid: usize,
+    pub shape: &'static str,
+    pub mins: Point2<f32>,
+    pub maxs: Point2<f32>,
+    // the replicated entity it belongs to, chunk blocks have none
+    pub entity: Option<NetId>,
+}
+
+// what a room holds at a given tick, laid out for reading
+pub struct Inspection {
+    pub room: String,
+    pub tick: u64,
+    pub entities: Vec<EntityRow>,
+    pub colliders: Vec<ColliderRow>,
+}
+
+pub fn entities(world: &World<f32>, entities: &hecs::World) -> Vec<EntityRow> {
+    let mut query = entities.query::<(&PhysicsBody, &Replicated, &EntityKind, Option<&Owner>)>();
+    let mut rows: Vec<EntityRow> = query.iter().filter_map(|(_, (physics_body, replicated, kind, owner))| {
+        let (position, velocity, asleep) = match world.rigid_body(physics_body.body) {
+            Some(rigid_body) => (*rigid_body.position(), *rigid_body.velocity(), Some(!rigid_body.is_active())),
+            None => (*world.collider(physics_body.collider)?.position(), Velocity2::zero(), None),
+        };
+
+        Some(EntityRow { id: replicated.id, kind: *kind, position, velocity, owner: owner.map(|owner| owner.0), asleep })
+    }).collect();
+
+    rows.sort_by_key(|row| row.id);
+    rows
+}
+
+pub fn static_colliders(world: &World<f32>, net_ids: &NetIds) -> Vec<ColliderRow> {
+    let mut rows: Vec<ColliderRow> = world.colliders()
+        .filter(|collider| collider.data().body().is_ground())
+        .map(|collider| {
+            let shape = collider.shape();
+            let aabb = shape.aabb(collider.position());
+
+            ColliderRow {
+                uid: collider.handle().uid(),
+                shape: if shape.is_shape::<Cuboid<f32>>() {
+                    "cuboid"
+                } else if shape.is_shape::<Ball<f32>>() {
+                    "ball"
+                } else if shape.is_shape::<Polyline<f32>>() {
+                    "polyline"
+                } else {
+                    "other"
+                },
+                mins: *aabb.mins(),
+                maxs: *aabb.maxs(),
+                entity: net_ids.by_collider(collider.handle()),
+            }
+        })
+        .collect();
+
+    rows.sort_by_key(|row| row.uid);
+    rows
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| String::from("-"), |value| value.to_string())
+}
+
+pub fn table(inspection: &Inspection) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "room {} at tick {}, {} entities, {} static colliders", inspection.room, inspection.tick, inspection.entities.len(), inspection.colliders.len()).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "{:>6}  {:<10}  {:>20}  {:>7}  {:>20}  {:>7}  {:>5}  {:>6}", "id", "kind", "position", "angle", "velocity", "spin", "owner", "asleep").unwrap();
+    for row in inspection.entities.iter() {
+        let translation = row.position.translation.vector;
+        writeln!(
+            out,
+            "{:>6}  {:<10}  {:>20}  {:>7.3}  {:>20}  {:>7.3}  {:>5}  {:>6}",
+            row.id,
+            format!("{:?}", row.kind),
+            format!("{:.3}, {:.3}", translation.x, translation.y),
+            row.position.rotation.angle(),
+            format!("{:.3}, {:.3}", row.velocity.linear.x, row.velocity.linear.y),
+            row.velocity.angular,
+            optional(row.owner),
+            row.asleep.map_or("static", |asleep| if asleep { "yes" } else { "no" }),
+        ).unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "{:>6}  {:<8}  {:>20}  {:>20}  {:>6}", "uid", "shape", "min", "max", "entity").unwrap();
+    for row in inspection.colliders.iter() {
+        writeln!(
+            out,
+            "{:>6}  {:<8}  {:>20}  {:>20}  {:>6}",
+            row.uid,
+            row.shape,
+            format!("{:.3}, {:.3}", row.mins.x, row.mins.y),
+            format!("{:.3}, {:.3}", row.maxs.x, row.maxs.y),
+            optional(row.entity),
+        ).unwrap();
+    }
+
+    out
+}
+
+// replays the input log up to `tick`, the last one when `None`, and prints the room then
+pub fn run(log_path: &str, tick: Option<u64>) -> Result<(), ReplayError> {
+    let log = InputLog::load(log_path)?;
+    let mut replayer = Replayer::new(&log)?;
+    let last = tick.unwrap_or(log.ticks.saturating_sub(1));
+
+    for (tick, inputs) in replay::ticks(&log).take_while(|(tick, _)| *tick <= last) {
+        replayer.step(tick, inputs);
+    }
+
+    print!("{}", table(&replayer.room.inspect()));
+    Ok(())
+}
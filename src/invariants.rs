@@ -0,0 +1,58 @@
+use na::Vector2;
+use nphysics2d::world::World;
+
+use crate::components::{PhysicsBody, Replicated};
+use crate::net_ids::NetIds;
+use crate::protocol::{NetId, Violation};
+
+// the velocity clamp runs before the check, this only leaves room for rounding
+const SPEED_TOLERANCE: f32 = 1.01;
+
+pub struct Limits {
+    pub bounds_min: Vector2<f32>,
+    pub bounds_max: Vector2<f32>,
+    pub max_linear_speed: f32,
+    pub max_angular_speed: f32,
+}
+
+// what a replicated entity must look like once a tick has run, one violation per entity at most
+pub fn check(world: &World<f32>, entities: &hecs::World, net_ids: &NetIds, limits: &Limits) -> Vec<(NetId, Violation)> {
+    let mut violations = vec![];
+
+    for (entity, (physics_body, replicated)) in entities.query::<(&PhysicsBody, &Replicated)>().iter() {
+        let id = replicated.id;
+
+        if net_ids.entity(id) != Some(entity) || net_ids.by_collider(physics_body.collider) != Some(id) || world.collider(physics_body.collider).is_none() {
+            violations.push((id, Violation::Unmapped));
+            continue;
+        }
+        let rigid_body = match world.rigid_body(physics_body.body) {
+            Some(rigid_body) => rigid_body,
+            None => continue,
+        };
+
+        let position = rigid_body.position();
+        let velocity = rigid_body.velocity();
+        let translation = position.translation.vector;
+        if !translation.x.is_finite() || !translation.y.is_finite() || !position.rotation.angle().is_finite()
+            || !velocity.linear.x.is_finite() || !velocity.linear.y.is_finite() || !velocity.angular.is_finite() {
+            violations.push((id, Violation::NonFinite));
+        } else if translation.x < limits.bounds_min.x || translation.y < limits.bounds_min.y || translation.x > limits.bounds_max.x || translation.y > limits.bounds_max.y {
+            violations.push((id, Violation::OutOfBounds));
+        } else if velocity.linear.norm() > limits.max_linear_speed * SPEED_TOLERANCE || velocity.angular.abs() > limits.max_angular_speed * SPEED_TOLERANCE {
+            violations.push((id, Violation::TooFast));
+        }
+    }
+
+    // ids still allocated to an entity that was despawned, or that replicates under another id
+    let mut stale: Vec<NetId> = net_ids.ids()
+        .filter(|id| {
+            let entity = net_ids.entity(*id);
+            entity.and_then(|entity| entities.get::<Replicated>(entity).ok().map(|replicated| replicated.id)) != Some(*id)
+        })
+        .collect();
+    stale.sort();
+    violations.extend(stale.into_iter().map(|id| (id, Violation::Unmapped)));
+
+    violations
+}
@@ -0,0 +1,386 @@
+use std::fs;
+use std::io;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::protocol::{EntityKind, MaterialEntry};
+use crate::room::SpawnDescriptor;
+
+use crate::config::{ConfigError, MAX_COLLIDER_MARGIN};
+use crate::levelgen::ArenaParams;
+use crate::match_state::MatchRules;
+use crate::tilemap;
+
+// used when there is no level file, it's also the reference for writing one
+const DEFAULT_LEVEL: &str = include_str!("../level.toml");
+
+fn default_thickness() -> f32 {
+    0.2
+}
+
+fn default_layer() -> String {
+    String::from("prop")
+}
+
+fn default_gravity_scale() -> f32 {
+    1.0
+}
+
+// names used for entity kinds in level files
+pub fn entity_kind(name: &str) -> Option<EntityKind> {
+    match name {
+        "wall" => Some(EntityKind::Wall),
+        "projectile" => Some(EntityKind::Projectile),
+        "pickup" => Some(EntityKind::Pickup),
+        "prop" => Some(EntityKind::Prop),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainDefinition {
+    pub start: [f32; 2],
+    pub end: [f32; 2],
+    pub links: usize,
+    #[serde(default = "default_thickness")]
+    pub thickness: f32,
+    #[serde(default = "default_layer")]
+    pub layer: String,
+}
+
+// what a spawner emits, a ball on the given layer
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateDefinition {
+    pub kind: String,
+    #[serde(default = "default_layer")]
+    pub layer: String,
+    #[serde(default = "default_gravity_scale")]
+    pub gravity_scale: f32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub ttl_ticks: Option<u64>,
+    #[serde(default)]
+    pub continuous: bool,
+    #[serde(default)]
+    pub material: Option<String>,
+    // the id of `material`, set when the level is parsed
+    #[serde(skip)]
+    pub surface: u8,
+}
+
+impl TemplateDefinition {
+    // the kind is checked when the level is parsed
+    pub fn descriptor(&self) -> SpawnDescriptor {
+        let kind = entity_kind(&self.kind).unwrap_or(EntityKind::Prop);
+
+        let mut descriptor = SpawnDescriptor::new(kind, &self.layer);
+        descriptor.gravity_scale = self.gravity_scale;
+        descriptor.metadata.tags = self.tags.clone();
+        descriptor.ttl = self.ttl_ticks;
+        descriptor.continuous = self.continuous;
+        descriptor.surface = self.surface;
+        descriptor
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpawnerDefinition {
+    pub position: [f32; 2],
+    pub template: TemplateDefinition,
+    pub interval_ticks: u64,
+    pub max_alive: usize,
+}
+
+fn default_wall_layer() -> String {
+    String::from("wall")
+}
+
+// a static box, keep them smaller than a chunk since they're only loaded with the chunk of their center
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockDefinition {
+    pub position: [f32; 2],
+    pub half_extents: [f32; 2],
+    #[serde(default = "default_wall_layer")]
+    pub layer: String,
+    #[serde(default)]
+    pub material: Option<String>,
+    #[serde(skip)]
+    pub surface: u8,
+}
+
+// rolling ground as one long static line, for what boxes would only approximate
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolylineDefinition {
+    pub points: Vec<[f32; 2]>,
+    #[serde(default = "default_wall_layer")]
+    pub layer: String,
+    #[serde(default)]
+    pub material: Option<String>,
+    #[serde(skip)]
+    pub surface: u8,
+}
+
+// a polyline through evenly spaced heights, starting at `origin` and going right
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeightfieldDefinition {
+    pub origin: [f32; 2],
+    pub spacing: f32,
+    pub heights: Vec<f32>,
+    #[serde(default = "default_wall_layer")]
+    pub layer: String,
+    #[serde(default)]
+    pub material: Option<String>,
+    #[serde(skip)]
+    pub surface: u8,
+}
+
+impl HeightfieldDefinition {
+    pub fn points(&self) -> Vec<[f32; 2]> {
+        self.heights.iter().enumerate()
+            .map(|(i, height)| [self.origin[0] + self.spacing * i as f32, self.origin[1] + height])
+            .collect()
+    }
+}
+
+// a grid of tiles from a tilemap editor, `#` solid and `.` empty, rows listed top to bottom from
+// `origin`, the top left corner, baked into blocks when the level is parsed
+#[derive(Debug, Clone, Deserialize)]
+pub struct TilemapDefinition {
+    pub origin: [f32; 2],
+    pub tile_size: f32,
+    pub rows: Vec<String>,
+    #[serde(default = "default_wall_layer")]
+    pub layer: String,
+    // given to every block baked from it
+    #[serde(default)]
+    pub material: Option<String>,
+}
+
+// what a surface is made of as far as clients are concerned, they pick sounds and particles by it,
+// blocks, terrain and templates name one, events carry its id: its place in the list plus one,
+// 0 is for whatever has no material
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaterialDefinition {
+    pub name: String,
+    // names of the client's own assets, the server only passes them on
+    #[serde(default)]
+    pub sound_set: Option<String>,
+    #[serde(default)]
+    pub particle_set: Option<String>,
+    #[serde(default)]
+    pub footstep: Option<String>,
+}
+
+impl MaterialDefinition {
+    pub fn entry(&self) -> MaterialEntry {
+        MaterialEntry {
+            name: self.name.clone(),
+            sound_set: self.sound_set.clone(),
+            particle_set: self.particle_set.clone(),
+            footstep: self.footstep.clone(),
+        }
+    }
+}
+
+fn surface(materials: &[MaterialDefinition], material: &Option<String>) -> Result<u8, ConfigError> {
+    match material {
+        Some(name) => materials.iter()
+            .position(|candidate| candidate.name == *name)
+            .map(|i| i as u8 + 1)
+            .ok_or_else(|| ConfigError::Invalid(format!("unknown material {}", name))),
+        None => Ok(0),
+    }
+}
+
+// a random map instead of a fixed one, rooms pick their own seed when there's none
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArenaDefinition {
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(flatten)]
+    pub params: ArenaParams,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChunkingDefinition {
+    pub size: f32,
+    pub load_radius: u32,
+}
+
+impl Default for ChunkingDefinition {
+    fn default() -> ChunkingDefinition {
+        ChunkingDefinition {
+            size: 64.0,
+            load_radius: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpawnPointDefinition {
+    pub name: String,
+    pub position: [f32; 2],
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TimelineAction {
+    Spawn { position: [f32; 2], template: TemplateDefinition },
+    SetGravity { gravity: [f32; 2] },
+    End,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimelineEntry {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub action: TimelineAction,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Level {
+    #[serde(default, rename = "match")]
+    pub rules: MatchRules,
+    #[serde(default, rename = "spawn_point")]
+    pub spawn_points: Vec<SpawnPointDefinition>,
+    #[serde(default)]
+    pub arena: Option<ArenaDefinition>,
+    #[serde(default)]
+    pub chunks: ChunkingDefinition,
+    #[serde(default, rename = "block")]
+    pub blocks: Vec<BlockDefinition>,
+    #[serde(default, rename = "polyline")]
+    pub polylines: Vec<PolylineDefinition>,
+    #[serde(default, rename = "heightfield")]
+    pub heightfields: Vec<HeightfieldDefinition>,
+    #[serde(default, rename = "tilemap")]
+    pub tilemaps: Vec<TilemapDefinition>,
+    #[serde(default, rename = "chain")]
+    pub chains: Vec<ChainDefinition>,
+    #[serde(default, rename = "spawner")]
+    pub spawners: Vec<SpawnerDefinition>,
+    #[serde(default)]
+    pub timeline: Vec<TimelineEntry>,
+    #[serde(default, rename = "material")]
+    pub materials: Vec<MaterialDefinition>,
+    // kept for input logs, a replay rebuilds the room from it
+    #[serde(skip)]
+    pub source: String,
+}
+
+impl Level {
+    pub fn parse(source: &str) -> Result<Level, ConfigError> {
+        let mut level: Level = toml::from_str(source).map_err(ConfigError::Parse)?;
+        level.source = source.to_string();
+
+        if let Some(ref arena) = level.arena {
+            let params = &arena.params;
+            if !(params.min_size > MAX_COLLIDER_MARGIN) || params.max_size < params.min_size {
+                return Err(ConfigError::Invalid(String::from("arena obstacle sizes must go from above the largest collider margin up to max_size")));
+            }
+            if params.width <= params.min_size * 2.0 || params.height <= params.min_size * 2.0 {
+                return Err(ConfigError::Invalid(String::from("the arena is too small for its obstacles")));
+            }
+        }
+        if !(level.chunks.size > 0.0) {
+            return Err(ConfigError::Invalid(String::from("the chunk size must be positive")));
+        }
+        for tilemap in level.tilemaps.iter() {
+            if !(tilemap.tile_size / 2.0 > MAX_COLLIDER_MARGIN) {
+                return Err(ConfigError::Invalid(String::from("a tile must be larger than the largest collider margin")));
+            }
+            if tilemap.rows.iter().any(|row| !row.chars().all(tilemap::is_tile)) {
+                return Err(ConfigError::Invalid(String::from("tilemap rows only take # and .")));
+            }
+        }
+        let baked: Vec<BlockDefinition> = level.tilemaps.iter().flat_map(|tilemap| tilemap::bake(tilemap, level.chunks.size)).collect();
+        level.blocks.extend(baked);
+        for block in level.blocks.iter() {
+            if !(block.half_extents[0] > MAX_COLLIDER_MARGIN) || !(block.half_extents[1] > MAX_COLLIDER_MARGIN) {
+                return Err(ConfigError::Invalid(String::from("a block must be larger than the largest collider margin")));
+            }
+        }
+
+        if level.polylines.iter().any(|polyline| polyline.points.len() < 2) {
+            return Err(ConfigError::Invalid(String::from("a polyline needs at least two points")));
+        }
+        for heightfield in level.heightfields.iter() {
+            if heightfield.heights.len() < 2 || !(heightfield.spacing > 0.0) {
+                return Err(ConfigError::Invalid(String::from("a heightfield needs at least two heights and a positive spacing")));
+            }
+        }
+
+        for chain in level.chains.iter() {
+            if chain.links == 0 || !(chain.thickness > 0.0) {
+                return Err(ConfigError::Invalid(String::from("a chain needs at least one link and a positive thickness")));
+            }
+            if chain.start == chain.end {
+                return Err(ConfigError::Invalid(String::from("a chain can't start and end at the same point")));
+            }
+        }
+
+        for spawner in level.spawners.iter() {
+            if entity_kind(&spawner.template.kind).is_none() {
+                return Err(ConfigError::Invalid(format!("unknown entity kind {} in a spawner template", spawner.template.kind)));
+            }
+            if spawner.interval_ticks == 0 {
+                return Err(ConfigError::Invalid(String::from("a spawner interval must be at least 1 tick")));
+            }
+        }
+
+        for entry in level.timeline.iter() {
+            if let TimelineAction::Spawn { ref template, .. } = entry.action {
+                if entity_kind(&template.kind).is_none() {
+                    return Err(ConfigError::Invalid(format!("unknown entity kind {} in the timeline at tick {}", template.kind, entry.tick)));
+                }
+            }
+        }
+
+        if level.materials.len() > usize::from(u8::MAX) {
+            return Err(ConfigError::Invalid(String::from("a level can't have more than 255 materials")));
+        }
+        for (i, material) in level.materials.iter().enumerate() {
+            if level.materials[..i].iter().any(|other| other.name == material.name) {
+                return Err(ConfigError::Invalid(format!("material {} is defined twice", material.name)));
+            }
+        }
+        let materials = level.materials.clone();
+        for block in level.blocks.iter_mut() {
+            block.surface = surface(&materials, &block.material)?;
+        }
+        for polyline in level.polylines.iter_mut() {
+            polyline.surface = surface(&materials, &polyline.material)?;
+        }
+        for heightfield in level.heightfields.iter_mut() {
+            heightfield.surface = surface(&materials, &heightfield.material)?;
+        }
+        for spawner in level.spawners.iter_mut() {
+            spawner.template.surface = surface(&materials, &spawner.template.material)?;
+        }
+        for entry in level.timeline.iter_mut() {
+            if let TimelineAction::Spawn { ref mut template, .. } = entry.action {
+                template.surface = surface(&materials, &template.material)?;
+            }
+        }
+
+        Ok(level)
+    }
+
+    pub fn defaults() -> Level {
+        Level::parse(DEFAULT_LEVEL).expect("the default level is valid")
+    }
+
+    // a missing file falls back to the default level, a broken one is an error
+    pub fn load(path: &str) -> Result<Level, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(source) => Level::parse(&source),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                warn!(target: "main", path, "no level, using the default one");
+                Ok(Level::defaults())
+            },
+            Err(error) => Err(ConfigError::Io(error)),
+        }
+    }
+}
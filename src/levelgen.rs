@@ -0,0 +1,125 @@
+use serde::Deserialize;
+
+use crate::level::{BlockDefinition, Level, SpawnPointDefinition};
+
+// attempts at placing something before giving up on it, a crowded arena just gets fewer obstacles
+const PLACEMENT_ATTEMPTS: usize = 32;
+
+// splitmix64, clients rebuild the arena from the seed so the generator has to be trivial to port
+pub(crate) struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    pub(crate) fn new(seed: u64) -> Generator {
+        Generator { state: seed }
+    }
+
+    // a generator made from it draws what this one will
+    pub(crate) fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [min, max), from the top 24 bits so it's exact as an f32
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * unit
+    }
+}
+
+fn default_width() -> f32 {
+    50.0
+}
+
+fn default_height() -> f32 {
+    50.0
+}
+
+fn default_obstacles() -> u16 {
+    6
+}
+
+fn default_min_size() -> f32 {
+    1.0
+}
+
+fn default_max_size() -> f32 {
+    4.0
+}
+
+fn default_spawn_points() -> u16 {
+    4
+}
+
+// the arena spans `width` centered on x = 0 and `height` up from the floor at y = 0,
+// obstacle sizes are half extents
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ArenaParams {
+    #[serde(default = "default_width")]
+    pub width: f32,
+    #[serde(default = "default_height")]
+    pub height: f32,
+    #[serde(default = "default_obstacles")]
+    pub obstacles: u16,
+    #[serde(default = "default_min_size")]
+    pub min_size: f32,
+    #[serde(default = "default_max_size")]
+    pub max_size: f32,
+    #[serde(default = "default_spawn_points")]
+    pub spawn_points: u16,
+}
+
+fn overlaps(block: &BlockDefinition, position: [f32; 2], half_extents: [f32; 2]) -> bool {
+    (block.position[0] - position[0]).abs() < block.half_extents[0] + half_extents[0]
+        && (block.position[1] - position[1]).abs() < block.half_extents[1] + half_extents[1]
+}
+
+// the obstacles and spawn points of the arena on top of `base`, the same seed and params always
+// give the same arena
+pub fn generate(base: &Level, seed: u64, params: &ArenaParams) -> Level {
+    let mut generator = Generator::new(seed);
+    let mut level = base.clone();
+    let (left, right) = (-params.width / 2.0, params.width / 2.0);
+
+    let mut obstacles: Vec<BlockDefinition> = vec![];
+    for _ in 0..params.obstacles {
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let half_extents = [generator.range(params.min_size, params.max_size), generator.range(params.min_size, params.max_size)];
+            let position = [
+                generator.range(left + half_extents[0], right - half_extents[0]),
+                generator.range(half_extents[1], params.height - half_extents[1]),
+            ];
+
+            if obstacles.iter().all(|obstacle| !overlaps(obstacle, position, half_extents)) {
+                obstacles.push(BlockDefinition { position, half_extents, layer: String::from("wall"), material: None, surface: 0 });
+                break;
+            }
+        }
+    }
+
+    // a ball sized gap around each spawn point keeps players from appearing inside an obstacle
+    let clearance = [params.min_size, params.min_size];
+    let mut spawned = 0;
+    for _ in 0..params.spawn_points as usize * PLACEMENT_ATTEMPTS {
+        if spawned == params.spawn_points {
+            break;
+        }
+        let position = [generator.range(left + clearance[0], right - clearance[0]), generator.range(clearance[1], params.height - clearance[1])];
+
+        if obstacles.iter().all(|obstacle| !overlaps(obstacle, position, clearance)) {
+            level.spawn_points.push(SpawnPointDefinition { name: format!("arena-{}", spawned), position });
+            spawned += 1;
+        }
+    }
+
+    level.blocks.extend(obstacles);
+    level
+}
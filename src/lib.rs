@@ -0,0 +1,86 @@
+// the simulation as a library, the server binary and the games built on it share it
+extern crate nalgebra as na;
+extern crate ncollide2d;
+extern crate nphysics2d;
+extern crate rand;
+extern crate hmac;
+extern crate sha2;
+extern crate hex;
+extern crate lz4_flex;
+extern crate zstd;
+extern crate rayon;
+extern crate hecs;
+extern crate serde;
+extern crate serde_json;
+extern crate toml;
+extern crate sled;
+extern crate tracing;
+extern crate tracing_subscriber;
+
+pub mod auth;
+pub mod body_pool;
+pub mod ccd;
+pub mod chain;
+pub mod changes;
+pub mod channel;
+pub mod chunks;
+pub mod clock;
+pub mod codec;
+pub mod collision;
+pub mod components;
+pub mod compression;
+pub mod config;
+pub mod controller;
+pub mod divergence;
+pub mod entity_cap;
+pub mod entity_watch;
+pub mod expiry;
+pub mod gameplay;
+pub mod golden;
+pub mod grapple;
+pub mod haptics;
+pub mod hitscan;
+pub mod impact_fx;
+pub mod inspect;
+pub mod invariants;
+pub mod level;
+pub mod logging;
+pub mod levelgen;
+pub mod match_state;
+pub mod matchmaking;
+pub mod melee;
+pub mod motion;
+pub mod net_ids;
+pub mod protocol;
+pub mod ragdoll;
+pub mod random;
+pub mod rcon;
+pub mod replay;
+pub mod respawn;
+pub mod results;
+pub mod room;
+pub mod rope;
+pub mod scaling;
+pub mod scheduler;
+pub mod schema;
+pub mod session;
+pub mod snapshot_diff;
+pub mod soft_body;
+pub mod spatial_cache;
+pub mod spawner;
+pub mod supervision;
+pub mod systems;
+pub mod tenant;
+pub mod terrain;
+pub mod testing;
+pub mod tilemap;
+pub mod timeline;
+pub mod timing;
+pub mod timers;
+pub mod typescript;
+pub mod usage;
+pub mod validation;
+pub mod vehicle;
+pub mod view;
+pub mod watchdog;
+pub mod xor_delta;
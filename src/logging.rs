@@ -0,0 +1,51 @@
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::reload;
+
+// text for reading a terminal, json for aggregating the logs of many rooms, every field gets its own key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(name: &str) -> Option<LogFormat> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// changes the level of the subscriber `init` installed, the handle type depends on the format
+pub struct LogLevel(Box<dyn Fn(LevelFilter) -> Result<(), reload::Error> + Send + Sync>);
+
+impl LogLevel {
+    pub fn set(&self, level: LevelFilter) -> Result<(), reload::Error> {
+        (self.0)(level)
+    }
+}
+
+// the target tells the part of the server the event comes from: physics, main, replay...
+// info until the config says otherwise
+pub fn init(format: LogFormat) -> LogLevel {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::INFO)
+        .with_target(true);
+
+    match format {
+        LogFormat::Text => {
+            let builder = builder.with_filter_reloading();
+            let handle = builder.reload_handle();
+            builder.init();
+            LogLevel(Box::new(move |level| handle.reload(level)))
+        },
+        LogFormat::Json => {
+            let builder = builder.json().with_filter_reloading();
+            let handle = builder.reload_handle();
+            builder.init();
+            LogLevel(Box::new(move |level| handle.reload(level)))
+        },
+    }
+}
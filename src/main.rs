@@ -2,78 +2,48 @@ extern crate nalgebra as na;
 extern crate ncollide2d;
 extern crate nphysics2d;
 extern crate nphysics_testbed2d;
+extern crate rayon;
+extern crate tracing;
+extern crate server_physic;
 
-use std::collections::HashSet;
+use std::env;
+use std::process;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{thread, time};
-use std::sync::mpsc::{self, Sender};
+use std::sync::mpsc::{self, Receiver};
 
-use na::{Isometry2, Point2, Vector2};
-use ncollide2d::shape::{Cuboid, ShapeHandle};
+use na::Point2;
 use ncollide2d::events::{ContactEvent};
-use nphysics2d::object::{BodyHandle, Material, ColliderHandle};
-use nphysics2d::volumetric::Volumetric;
 use nphysics2d::world::World;
-use nphysics2d::algebra::{Inertia2, Velocity2};
 use nphysics_testbed2d::Testbed;
 use nphysics_testbed2d::{GraphicsManager, WorldOwner};
-
-const COLLIDER_MARGIN: f32 = 0.01;
-
-fn create_ground (world: &mut World<f32>) {
-    let material = Material::new(1.0, 0.0);
-
-    let ground_radius = 50.0;
-    let ground_shape = ShapeHandle::new(Cuboid::new(Vector2::new(
-        ground_radius - COLLIDER_MARGIN,
-        ground_radius - COLLIDER_MARGIN,
-    )));
-
-    let positions = [
-        Isometry2::new(-Vector2::y() * ground_radius, na::zero()),
-        Isometry2::new(Vector2::y() * ground_radius * 2.0, na::zero()),
-        Isometry2::new(Vector2::new(ground_radius * 1.5, ground_radius * 0.5), na::zero()),
-        Isometry2::new(Vector2::new(-ground_radius * 1.5, ground_radius * 0.5), na::zero()),
-    ];
-
-    for position in positions.iter() {
-        world.add_collider(
-            COLLIDER_MARGIN,
-            ground_shape.clone(),
-            BodyHandle::ground(),
-            *position,
-            material.clone(),
-        );
-    }
-}
-
-fn create_balls (world: &mut World<f32>, num: usize) -> Vec<ColliderHandle> {
-    let material = Material::new(1.0, 0.0);
-    let rad = 1.5;
-
-    // let geom = ShapeHandle::new(Ball::new(rad - COLLIDER_MARGIN));
-    let geom = ShapeHandle::new(Cuboid::new(Vector2::new(rad - COLLIDER_MARGIN, rad - COLLIDER_MARGIN)));
-    let inertia = Inertia2::new(1.0, 0.0);
-    let center_of_mass = geom.center_of_mass();
-
-    let mut handlers = vec![];
-    for i in 0..num {
-        let x = (i as f32 -1.0) * 4.0;
-
-        let pos = Isometry2::new(Vector2::new(x, 3.0), 0.0);
-        let handle = world.add_rigid_body(pos, inertia, center_of_mass);
-
-        handlers.push(world.add_collider(
-            COLLIDER_MARGIN,
-            geom.clone(),
-            handle,
-            Isometry2::identity(),
-            material.clone(),
-        ));
-    }
-
-    handlers
-}
+use rayon::prelude::*;
+use tracing::{debug, error, info, warn};
+
+use server_physic::auth::{AuthError, Authenticator, HmacAuthenticator};
+use server_physic::compression::Compression;
+use server_physic::config::{Config, ConfigError};
+use server_physic::level::Level;
+use server_physic::logging::{self, LogFormat, LogLevel};
+use server_physic::matchmaking::{Matchmaker, RequestedRoom};
+use server_physic::protocol::{ClientMessage, Frame, MIN_LOCKSTEP_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, Role, ServerMessage};
+use server_physic::results::ResultsStore;
+use server_physic::room::Room;
+use server_physic::scaling::{RoomChurn, Scaling};
+use server_physic::scheduler::TickScheduler;
+use server_physic::supervision::{self, Restarts};
+use server_physic::tenant;
+use server_physic::view::ViewRequest;
+use server_physic::watchdog::Watchdog;
+use server_physic::{divergence, golden, inspect, rcon, replay, schema, snapshot_diff, typescript};
+
+const ROOM: &str = "lobby";
+const CONFIG_PATH: &str = "config.toml";
+const LEVEL_PATH: &str = "level.toml";
+// how often the phase percentiles and usage of every room are handed to the watchdog
+const TIMING_REPORT_TICKS: u64 = 300;
 
 fn test<F: Fn(&mut WorldOwner, &mut GraphicsManager, f32) + 'static>(world: World<f32>, callback: F) {
     let mut testbed = Testbed::new(world);
@@ -84,86 +54,444 @@ fn test<F: Fn(&mut WorldOwner, &mut GraphicsManager, f32) + 'static>(world: Worl
     testbed.run();
 }
 
-#[derive(Debug)]
-struct Ball {
-    id: usize,
-    position: Isometry2<f32>,
-    velocity: Velocity2<f32>,
+fn tick_period(tick_rate: u64) -> time::Duration {
+    time::Duration::from_nanos(1_000_000_000 / tick_rate)
+}
+
+fn reject(tx: &mpsc::Sender<Frame>, reason: String) {
+    let _ = tx.send(Frame::uncompressed(&ServerMessage::Rejected(reason)));
 }
 
-impl Ball {
-    fn new(id: usize, position: Isometry2<f32>, velocity: Velocity2<f32>) -> Ball {
-        Ball {
-            id,
-            position,
-            velocity,
+// tears down the rooms that panicked, their clients are moved to a fresh room of the same name while restarts are left
+fn recover(rooms: &mut Vec<Room>, crashed: Vec<(String, String)>, restarts: &mut Restarts, watchdog: &Watchdog, config: &Arc<Config>, level: &Arc<Level>, results: &Option<ResultsStore>) {
+    for (name, error) in crashed {
+        let index = match rooms.iter().position(|room| room.name == name) {
+            Some(index) => index,
+            None => continue,
+        };
+        let restarting = restarts.allow(&name);
+        error!(target: "physics", room = %name, tenant = ?rooms[index].tenant(), %error, "room crashed");
+        let sessions = rooms[index].crash(restarting);
+        rooms.remove(index);
+        watchdog.forget(&name);
+
+        if restarting {
+            warn!(target: "physics", room = %name, clients = sessions.len(), "restarting room");
+            let mut room = Room::new(&name, config.clone(), level.clone(), results.clone());
+            for session in sessions {
+                room.readmit(session);
+            }
+            rooms.push(room);
         }
     }
 }
 
-fn physics(txBalls: Sender<Vec<Ball>>, txMessages: Sender<String>) {
-    let mut world = World::new();
-
-    create_ground(&mut world);
-    let mut balls_handler = create_balls(&mut world, 2);
-
-    let mut balls = HashSet::new();
-    for handler in balls_handler.clone() {
-        balls.insert(handler.uid());
+// the config file read again for the rooms running and the ones to come, `Config::check_reload`
+// keeps out what only a restart applies, the running config is left as it was then
+fn reload(path: &str, config: &mut Arc<Config>, rooms: &mut Vec<Room>, scheduler: &mut TickScheduler, watchdog: &Watchdog, log_level: &LogLevel) -> Result<(), ConfigError> {
+    let next = match Config::load(path).and_then(|next| config.check_reload(&next).map(|()| next)) {
+        Ok(next) => Arc::new(next),
+        Err(error) => {
+            error!(target: "physics", path, %error, "config not reloaded");
+            return Err(error);
+        },
+    };
+
+    if let Err(error) = log_level.set(next.log_level) {
+        warn!(target: "physics", %error, "can't change the log level");
     }
+    scheduler.set_period(tick_period(next.tick_rate));
+    watchdog.set_max_stall(tick_period(next.tick_rate) * next.max_stall_ticks as u32);
+    for room in rooms.iter_mut() {
+        room.reconfigure(next.clone());
+    }
+    info!(target: "physics", path, tick_rate = next.tick_rate, log_level = %next.log_level, "config reloaded");
+    *config = next;
+    Ok(())
+}
 
-    let body_collision_handler = balls_handler.last().unwrap();
-    let body_handler = world.collider_body_handle(*body_collision_handler).unwrap();
+fn physics(mut config: Arc<Config>, config_path: String, log_level: LogLevel, hangup: Arc<AtomicBool>, level: Arc<Level>, results: Option<ResultsStore>, authenticator: Box<dyn Authenticator>, mut matchmaker: Box<dyn Matchmaker>, watchdog: Arc<Watchdog>, rxClients: Receiver<ClientMessage>) {
+    let mut rooms = vec![Room::new(ROOM, config.clone(), level.clone(), results.clone())];
+    let mut churn = RoomChurn::new(config.scaling);
+    let mut restarts = Restarts::new(config.max_restarts);
+    let mut tick: u64 = 0;
+
+    info!(target: "physics", "start the simulation");
+    let mut scheduler = TickScheduler::new(tick_period(config.tick_rate));
+    // runs until the timeline of every room has ended it
+    while !rooms.is_empty() {
+        scheduler.wait();
+        tick += 1;
+        // a SIGHUP is applied between two ticks, like the rcon reload
+        if hangup.swap(false, Ordering::Relaxed) {
+            let _ = reload(&config_path, &mut config, &mut rooms, &mut scheduler, &watchdog, &log_level);
+        }
+        // rooms that panicked on a message this tick, with the panic message
+        let mut crashed = vec![];
+
+        while let Ok(message) = rxClients.try_recv() {
+            match message {
+                ClientMessage::Join { token, room, role, version, compression, view, tx } => {
+                    if version < MIN_PROTOCOL_VERSION {
+                        reject(&tx, format!("protocol version {} is no longer supported, {} at least", version, MIN_PROTOCOL_VERSION));
+                        continue;
+                    }
+                    if config.lockstep && version < MIN_LOCKSTEP_VERSION {
+                        reject(&tx, format!("rooms run in lockstep, protocol version {} at least", MIN_LOCKSTEP_VERSION));
+                        continue;
+                    }
+                    // a newer client can still talk to this server with the newest version it knows
+                    let version = version.min(PROTOCOL_VERSION);
+                    let claims = match authenticator.authenticate(&token) {
+                        Ok(claims) => claims,
+                        Err(error) => {
+                            reject(&tx, error.to_string());
+                            continue;
+                        },
+                    };
+
+                    let summaries: Vec<_> = rooms.iter().map(|room| room.summary()).collect();
+                    let room = match matchmaker.assign(&claims, &room, role, &summaries) {
+                        Some(ref room) if !claims.allows(room) => {
+                            reject(&tx, AuthError::Forbidden.to_string());
+                            continue;
+                        },
+                        Some(room) => room,
+                        None => {
+                            reject(&tx, String::from("no room available"));
+                            continue;
+                        },
+                    };
+
+                    let index = match rooms.iter().position(|candidate| candidate.name == room) {
+                        Some(index) => index,
+                        None => {
+                            rooms.push(Room::new(&room, config.clone(), level.clone(), results.clone()));
+                            churn.created(&room);
+                            rooms.len() - 1
+                        },
+                    };
+                    let client = rooms[index].join(claims.player, role, version, &compression, tx);
+                    rooms[index].set_view(client, view);
+                },
+                ClientMessage::Resume { token, tx } => {
+                    match rooms.iter_mut().find(|room| room.has_session(token)) {
+                        Some(room) => if let Err(error) = supervision::guard(|| room.handle(ClientMessage::Resume { token, tx })) {
+                            crashed.push((room.name.clone(), error));
+                        },
+                        None => reject(&tx, String::from("unknown or expired session")),
+                    }
+                },
+                // another tenant's room is as unknown as a room that doesn't exist
+                ClientMessage::Admin { room, scope, command } => {
+                    match rooms.iter_mut().find(|candidate| candidate.name == room && tenant::reaches(scope.as_ref().map(String::as_str), candidate.tenant())) {
+                        Some(target) => if let Err(error) = supervision::guard(|| target.handle(ClientMessage::Admin { room, scope, command })) {
+                            crashed.push((target.name.clone(), error));
+                        },
+                        None => warn!(target: "physics", room = %room, tenant = ?scope, "admin command for unknown room"),
+                    }
+                },
+                ClientMessage::WatchEntity { room, scope, entity, tx } => {
+                    match rooms.iter_mut().find(|candidate| candidate.name == room && tenant::reaches(scope.as_ref().map(String::as_str), candidate.tenant())) {
+                        Some(target) => if let Err(error) = supervision::guard(|| target.handle(ClientMessage::WatchEntity { room, scope, entity, tx })) {
+                            crashed.push((target.name.clone(), error));
+                        },
+                        None => warn!(target: "physics", room = %room, tenant = ?scope, entity, "entity watch for unknown room"),
+                    }
+                },
+                ClientMessage::Reload { reply } => {
+                    let result = reload(&config_path, &mut config, &mut rooms, &mut scheduler, &watchdog, &log_level);
+                    let _ = reply.send(result.map_err(|error| error.to_string()));
+                },
+                message => {
+                    let client = message.client();
+                    if let Some(room) = rooms.iter_mut().find(|room| client.map_or(false, |client| room.has_client(client))) {
+                        if let Err(error) = supervision::guard(|| room.handle(message)) {
+                            crashed.push((room.name.clone(), error));
+                        }
+                    }
+                },
+            }
+        }
 
-    let body = world.rigid_body_mut(body_handler).unwrap();
-    body.set_linear_velocity(Vector2::new(30.0, 30.0));
+        // a room that panicked on a message doesn't get to tick
+        recover(&mut rooms, crashed, &mut restarts, &watchdog, &config, &level, &results);
 
-    println!("[physics] start the simulation.");
-    let ten_millis = time::Duration::from_millis(1000 / 60);
-    for _ in 0..300 {
-        // TODO: make it real 60FPS in the main thread
-        thread::sleep(ten_millis);
-        world.step();
+        // rooms don't share anything, a heavy one only keeps its own worker busy and a panic only takes its own room down
+        let panicked: Vec<_> = rooms.par_iter_mut()
+            .filter_map(|room| match supervision::guard(|| room.tick()) {
+                Ok(()) => {
+                    watchdog.beat(&room.name, room.tenant(), tick);
+                    None
+                },
+                Err(error) => Some((room.name.clone(), error)),
+            })
+            .collect();
+        recover(&mut rooms, panicked, &mut restarts, &watchdog, &config, &level, &results);
+
+        // a stalled room is back by now, it's only restarted when asked to
+        let stalled = watchdog.take_flagged();
+        if config.restart_stalled {
+            let stalled = stalled.into_iter().map(|name| (name, String::from("stalled"))).collect();
+            recover(&mut rooms, stalled, &mut restarts, &watchdog, &config, &level, &results);
+        }
 
-        let sync_balls = balls_handler.iter().map(|&handler| {
-            let body_handler = world.collider_body_handle(handler).unwrap();
-            let rigid_body = world.rigid_body_mut(body_handler).unwrap();
+        // players leave and enter between ticks, never in the middle of one
+        let transfers: Vec<_> = rooms.iter_mut().flat_map(|room| room.take_transfers()).collect();
+        for transfer in transfers {
+            let index = match rooms.iter().position(|candidate| candidate.name == transfer.room) {
+                Some(index) => index,
+                None => {
+                    rooms.push(Room::new(&transfer.room, config.clone(), level.clone(), results.clone()));
+                    churn.created(&transfer.room);
+                    rooms.len() - 1
+                },
+            };
+            rooms[index].accept(transfer);
+        }
 
-            Ball::new(handler.uid(), rigid_body.position().clone(), rigid_body.velocity().clone())
-        });
-        txBalls.send(sync_balls.collect());
-        txMessages.send(String::from("balls"));
+        for room in rooms.iter().filter(|room| room.last_tick_duration > scheduler.period()) {
+            warn!(target: "physics", room = %room.name, tenant = ?room.tenant(), tick, took = ?room.last_tick_duration, slowest = ?room.timings().slowest(), "room is late");
+        }
+        if tick % TIMING_REPORT_TICKS == 0 {
+            for room in rooms.iter() {
+                watchdog.refresh(&room.name, room.timings().report(), room.usage());
+            }
+        }
+        let summaries: Vec<_> = rooms.iter().map(|room| room.summary()).collect();
+        for name in churn.update(tick, &summaries) {
+            if let Some(room) = rooms.iter_mut().find(|room| room.name == name) {
+                room.end();
+            }
+        }
+        for room in rooms.iter().filter(|room| room.has_ended()) {
+            watchdog.forget(&room.name);
+        }
+        rooms.retain(|room| !room.has_ended());
     }
 
-    println!("[physics] end.");
-    txMessages.send(String::from("end"));
+    info!(target: "physics", "end");
 }
 
 fn main() {
-    let (txMessages, rxMessages) = mpsc::channel();
-    let (txBalls, rxBalls) = mpsc::channel();
+    // SERVER_PHYSIC_LOG=json for one json object per line
+    let format = env::var("SERVER_PHYSIC_LOG").ok().and_then(|name| LogFormat::parse(&name)).unwrap_or(LogFormat::Text);
+    let log_level = logging::init(format);
+
+    // SERVER_PHYSIC_SCHEMA=<file> writes the wire layout for client codegen and exits
+    if let Ok(path) = env::var("SERVER_PHYSIC_SCHEMA") {
+        match schema::export(&path) {
+            Ok(()) => {
+                info!(target: "main", path = %path, version = PROTOCOL_VERSION, "schema written");
+                return;
+            },
+            Err(error) => {
+                error!(target: "main", %error);
+                process::exit(1);
+            },
+        }
+    }
+    // SERVER_PHYSIC_TYPESCRIPT=<file> writes typescript types and a decoder generated from the schema
+    if let Ok(path) = env::var("SERVER_PHYSIC_TYPESCRIPT") {
+        match typescript::export(&path) {
+            Ok(()) => {
+                info!(target: "main", path = %path, version = PROTOCOL_VERSION, "typescript decoder written");
+                return;
+            },
+            Err(error) => {
+                error!(target: "main", %error);
+                process::exit(1);
+            },
+        }
+    }
 
-    let handle = thread::spawn(move || physics(txBalls, txMessages));
+    // replays run on their own, without the simulation thread nor any client
+    if let Ok(path) = env::var("SERVER_PHYSIC_DIVERGE") {
+        match divergence::run(&path) {
+            Ok(None) => return,
+            Ok(Some(_)) => process::exit(2),
+            Err(error) => {
+                error!(target: "main", %error);
+                process::exit(1);
+            },
+        }
+    }
+    if let Ok(path) = env::var("SERVER_PHYSIC_GOLDEN") {
+        let tick = env::var("SERVER_PHYSIC_GOLDEN_TICK").ok().and_then(|tick| tick.parse().ok());
+        let tolerance = env::var("SERVER_PHYSIC_GOLDEN_TOLERANCE").ok().and_then(|tolerance| tolerance.parse().ok()).unwrap_or(0.0);
+        let golden_path = format!("{}.golden", path);
+        match golden::run(&path, tick, &golden_path, tolerance) {
+            Ok(()) => return,
+            Err(error) => {
+                error!(target: "main", %error);
+                process::exit(1);
+            },
+        }
+    }
+    if let Ok(path) = env::var("SERVER_PHYSIC_INSPECT") {
+        let tick = env::var("SERVER_PHYSIC_INSPECT_TICK").ok().and_then(|tick| tick.parse().ok());
+        match inspect::run(&path, tick) {
+            Ok(()) => return,
+            Err(error) => {
+                error!(target: "main", %error);
+                process::exit(1);
+            },
+        }
+    }
+    // two golden files when SERVER_PHYSIC_DIFF_AGAINST is set, two ticks of an input log otherwise
+    if let Ok(path) = env::var("SERVER_PHYSIC_DIFF") {
+        let threshold = env::var("SERVER_PHYSIC_DIFF_THRESHOLD").ok().and_then(|threshold| threshold.parse().ok()).unwrap_or(0.0);
+        let result = match env::var("SERVER_PHYSIC_DIFF_AGAINST") {
+            Ok(against) => snapshot_diff::run_files(&path, &against, threshold),
+            Err(_) => {
+                let from = env::var("SERVER_PHYSIC_DIFF_FROM").ok().and_then(|tick| tick.parse().ok()).unwrap_or(0);
+                let to = env::var("SERVER_PHYSIC_DIFF_TO").ok().and_then(|tick| tick.parse().ok());
+                snapshot_diff::run(&path, from, to, threshold)
+            },
+        };
+        match result {
+            Ok(()) => return,
+            Err(error) => {
+                error!(target: "main", %error);
+                process::exit(1);
+            },
+        }
+    }
+    if let Ok(path) = env::var("SERVER_PHYSIC_REPLAY") {
+        match replay::play(&path) {
+            Ok(()) => return,
+            Err(error) => {
+                error!(target: "main", %error);
+                process::exit(1);
+            },
+        }
+    }
 
+    let config_path = env::var("SERVER_PHYSIC_CONFIG").unwrap_or_else(|_| String::from(CONFIG_PATH));
+    let config = match Config::load(&config_path) {
+        Ok(config) => Arc::new(config),
+        Err(error) => {
+            error!(target: "main", %error);
+            process::exit(1);
+        },
+    };
+    if let Err(error) = log_level.set(config.log_level) {
+        warn!(target: "main", %error, "can't change the log level");
+    }
+    let level_path = env::var("SERVER_PHYSIC_LEVEL").unwrap_or_else(|_| String::from(LEVEL_PATH));
+    let level = match Level::load(&level_path) {
+        Ok(level) => Arc::new(level),
+        Err(error) => {
+            error!(target: "main", %error);
+            process::exit(1);
+        },
+    };
+
+    // match history is a nice to have, the server runs without it
+    let results = config.results_path.as_ref().and_then(|path| match ResultsStore::open(path) {
+        Ok(results) => Some(results),
+        Err(error) => {
+            warn!(target: "main", %error, "match results won't be kept");
+            None
+        },
+    });
+
+    // anyone can sign tokens with the development secret, it's only used when asked for
+    let secret = match env::var("SERVER_PHYSIC_SECRET") {
+        Ok(ref secret) if secret.is_empty() => {
+            error!(target: "main", "SERVER_PHYSIC_SECRET is empty");
+            process::exit(1);
+        },
+        Ok(secret) => secret,
+        Err(_) if env::var("SERVER_PHYSIC_INSECURE_DEV_SECRET").is_ok() => {
+            warn!(target: "main", "SERVER_PHYSIC_SECRET is not set, using the insecure development secret as SERVER_PHYSIC_INSECURE_DEV_SECRET asks");
+            String::from("development")
+        },
+        Err(_) => {
+            error!(target: "main", "SERVER_PHYSIC_SECRET is not set, set SERVER_PHYSIC_INSECURE_DEV_SECRET to run with a development secret anyone can sign tokens with");
+            process::exit(1);
+        },
+    };
+    let authenticator = HmacAuthenticator::new(secret.as_bytes());
+    let token = authenticator.sign("player-1", ROOM);
+
+    let (txClients, rxClients) = mpsc::channel();
+    let (tx, rx) = mpsc::channel();
+
+    let matchmaker = Scaling::new(RequestedRoom, config.scaling);
+    let watchdog = Watchdog::new(tick_period(config.tick_rate) * config.max_stall_ticks as u32);
+    Watchdog::spawn(watchdog.clone());
+    let physics_watchdog = watchdog.clone();
+    // the console is off without a password, even when an address is configured
+    if let Some(ref address) = config.rcon_address {
+        match env::var("SERVER_PHYSIC_RCON_PASSWORD") {
+            Ok(ref password) if password.is_empty() => warn!(target: "main", "SERVER_PHYSIC_RCON_PASSWORD is empty, the remote console is off"),
+            Ok(password) => if let Err(error) = rcon::listen(address, password, config.tenants.clone(), txClients.clone(), watchdog.clone()) {
+                error!(target: "main", address = %address, %error, "can't start the remote console");
+            },
+            Err(_) => warn!(target: "main", "SERVER_PHYSIC_RCON_PASSWORD is not set, the remote console is off"),
+        }
+    }
+    // SIGHUP reloads the config, the same as the rcon reload command
+    let hangup = Arc::new(AtomicBool::new(false));
+    if let Err(error) = signal_hook::flag::register(signal_hook::SIGHUP, hangup.clone()) {
+        warn!(target: "main", %error, "can't listen for SIGHUP, the config only reloads from the remote console");
+    }
+    let handle = thread::spawn(move || physics(config, config_path, log_level, hangup, level, results, Box::new(authenticator), Box::new(matchmaker), physics_watchdog, rxClients));
+    let compression = vec![Compression::Lz4];
+    txClients.send(ClientMessage::Join { token, room: String::from(ROOM), role: Role::Player, version: PROTOCOL_VERSION, compression, view: ViewRequest::default(), tx }).unwrap();
+
+    let started_at = time::Instant::now();
+    let mut client = None;
     loop {
-        if let Ok(message) = rxMessages.recv() {
-            match message.as_str() {
-                "balls" => {
-                    let sync_balls = rxBalls.try_recv().unwrap();
-                    println!("[main] balls! {:?}", sync_balls);
-                },
-                "end" => {
-                    break;
-                },
-                message => {
-                    panic!("Unknown message: {}", message);
+        // the simulation dropped every sender it had, there's nothing left to wait for
+        let frame = match rx.recv() {
+            Ok(frame) => frame,
+            Err(_) => {
+                info!(target: "main", "the simulation stopped");
+                break;
+            },
+        };
+
+        let message = match frame.decode() {
+            Ok(message) => message,
+            Err(error) => {
+                warn!(target: "main", %error, "can't decode frame");
+                continue;
+            },
+        };
+
+        match message {
+            ServerMessage::Welcome { client: id, token, entity, .. } => {
+                info!(target: "main", client = id, entity = ?entity, session = ?token, "joined");
+                client = Some(id);
+            },
+            ServerMessage::Ping { sent_at } => {
+                if let Some(client) = client {
+                    let elapsed = started_at.elapsed();
+                    let client_time = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+                    let _ = txClients.send(ClientMessage::Pong { client, sent_at, client_time });
                 }
+            },
+            ServerMessage::Snapshot(snapshot) => {
+                debug!(target: "main", tick = snapshot.tick, entities = ?snapshot.entities, "snapshot");
+            },
+            ServerMessage::End => {
+                break;
+            },
+            ServerMessage::Crashed { restarting } => {
+                warn!(target: "main", restarting, stalls = watchdog.stalls(), "the room crashed");
+            },
+            message => {
+                info!(target: "main", message = ?message, "received");
             }
         }
     }
 
-    handle.join();
-
+    if handle.join().is_err() {
+        error!(target: "main", "the simulation thread panicked");
+        process::exit(1);
+    }
 
     // test(world, move |_,_,_| {
     //     let mut step = 1;
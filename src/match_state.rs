@@ -0,0 +1,100 @@
+use serde::Deserialize;
+
+use crate::protocol::{CommandKind, MatchPhase};
+
+fn default_min_players() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatchRules {
+    // the countdown starts once this many players are in, and stops if they drop below
+    #[serde(default = "default_min_players")]
+    pub min_players: usize,
+    #[serde(default)]
+    pub countdown_ticks: u64,
+    // the match finishes after this long, or never without it
+    #[serde(default)]
+    pub duration_ticks: Option<u64>,
+    // or as soon as a client reaches this score
+    #[serde(default)]
+    pub score_limit: Option<u32>,
+}
+
+impl Default for MatchRules {
+    fn default() -> MatchRules {
+        MatchRules {
+            min_players: default_min_players(),
+            countdown_ticks: 0,
+            duration_ticks: None,
+            score_limit: None,
+        }
+    }
+}
+
+// Lobby -> Countdown -> Playing -> Finished, the countdown goes back to the lobby when players leave
+pub struct MatchState {
+    pub phase: MatchPhase,
+    rules: MatchRules,
+    entered_at: u64,
+    started_at: Option<u64>,
+}
+
+impl MatchState {
+    pub fn new(rules: MatchRules) -> MatchState {
+        MatchState {
+            phase: MatchPhase::Lobby,
+            rules,
+            entered_at: 0,
+            started_at: None,
+        }
+    }
+
+    // ticks spent in the current phase
+    pub fn elapsed(&self, tick: u64) -> u64 {
+        tick - self.entered_at
+    }
+
+    // ticks since the match started, 0 before that
+    pub fn played(&self, tick: u64) -> u64 {
+        self.started_at.map_or(0, |started_at| tick - started_at)
+    }
+
+    // ticks left in the current phase, when it's timed
+    pub fn remaining(&self, tick: u64) -> Option<u64> {
+        let elapsed = self.elapsed(tick);
+        match self.phase {
+            MatchPhase::Countdown => Some(self.rules.countdown_ticks.saturating_sub(elapsed)),
+            MatchPhase::Playing => self.rules.duration_ticks.map(|duration| duration.saturating_sub(elapsed)),
+            MatchPhase::Lobby | MatchPhase::Finished => None,
+        }
+    }
+
+    // called once per tick, returns the new phase when it changed
+    pub fn update(&mut self, tick: u64, players: usize, top_score: u32) -> Option<MatchPhase> {
+        let elapsed = self.elapsed(tick);
+        let next = match self.phase {
+            MatchPhase::Lobby if players >= self.rules.min_players => MatchPhase::Countdown,
+            MatchPhase::Countdown if players < self.rules.min_players => MatchPhase::Lobby,
+            MatchPhase::Countdown if elapsed >= self.rules.countdown_ticks => MatchPhase::Playing,
+            MatchPhase::Playing if self.rules.duration_ticks.map_or(false, |duration| elapsed >= duration) => MatchPhase::Finished,
+            MatchPhase::Playing if self.rules.score_limit.map_or(false, |limit| top_score >= limit) => MatchPhase::Finished,
+            _ => return None,
+        };
+
+        self.phase = next;
+        self.entered_at = tick;
+        if next == MatchPhase::Playing {
+            self.started_at = Some(tick);
+        }
+        Some(next)
+    }
+
+    // players warm up freely in the lobby, everything is frozen during the countdown and after the end
+    pub fn accepts(&self, _command: CommandKind) -> bool {
+        match self.phase {
+            MatchPhase::Lobby | MatchPhase::Playing => true,
+            MatchPhase::Countdown | MatchPhase::Finished => false,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::auth::Claims;
+use crate::protocol::{MatchPhase, Role};
+
+// what the matchmaker gets to see of a running room
+#[derive(Debug, Clone)]
+pub struct RoomSummary {
+    pub name: String,
+    pub players: usize,
+    pub entities: usize,
+    pub phase: MatchPhase,
+}
+
+// hook called once a client is authenticated, implement it to plug an existing matchmaking
+// backend, returns the room to join, created if no room has that name, or None to turn the
+// client away
+pub trait Matchmaker: Send {
+    fn assign(&mut self, claims: &Claims, requested: &str, role: Role, rooms: &[RoomSummary]) -> Option<String>;
+}
+
+// sends clients to the room they asked for
+pub struct RequestedRoom;
+
+impl Matchmaker for RequestedRoom {
+    fn assign(&mut self, _claims: &Claims, requested: &str, _role: Role, _rooms: &[RoomSummary]) -> Option<String> {
+        Some(String::from(requested))
+    }
+}
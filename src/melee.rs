@@ -0,0 +1,41 @@
+use na::{Point2, Vector2};
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+use crate::net_ids::NetIds;
+use crate::protocol::NetId;
+use crate::spatial_cache::SpatialCache;
+
+// the entities whose center is within `radius` of `origin` and at most half of `angle` off
+// `direction`, for melee swings and shotgun spreads, the broad phase narrows them down to the
+// square around the origin first, an entity larger than the reach only counts if its center is in
+pub fn in_arc(
+    world: &World<f32>,
+    entities: &hecs::World,
+    net_ids: &NetIds,
+    spatial: &SpatialCache,
+    origin: Point2<f32>,
+    direction: Vector2<f32>,
+    radius: f32,
+    angle: f32,
+) -> Vec<NetId> {
+    let reach = Vector2::repeat(radius);
+    let direction = direction.normalize();
+    // a whole turn or more is every direction
+    let min_cos = (angle / 2.0).min(std::f32::consts::PI).cos();
+
+    let candidates = spatial.entities_in(world, net_ids, origin - reach, origin + reach);
+    candidates.iter().cloned().filter(|id| {
+        let position = net_ids.entity(*id)
+            .and_then(|entity| entities.get::<PhysicsBody>(entity).ok())
+            .and_then(|physics_body| world.collider(physics_body.collider))
+            .map(|collider| collider.position().translation.vector);
+        let offset = match position {
+            Some(position) => position - origin.coords,
+            None => return false,
+        };
+
+        let distance = offset.norm();
+        distance <= radius && (distance == 0.0 || offset.dot(&direction) / distance >= min_cos)
+    }).collect()
+}
@@ -0,0 +1,87 @@
+use hecs::Entity;
+use na::Vector2;
+use nphysics2d::world::World;
+
+use crate::components::{PhysicsBody, Replicated};
+use crate::controller;
+use crate::protocol::{Event, MotionKind};
+
+// how long a knockback pushes the body, its speed fades out over that time
+const KNOCKBACK_DURATION: f32 = 0.2;
+
+// a velocity the server imposes on a body over several ticks, it ends early against an obstacle
+#[derive(Debug, Clone)]
+pub struct ScriptedMotion {
+    pub kind: MotionKind,
+    velocity: Vector2<f32>,
+    duration: f32,
+    remaining: f32,
+    fades: bool,
+}
+
+impl ScriptedMotion {
+    pub fn dash(direction: Vector2<f32>, distance: f32, duration: f32) -> ScriptedMotion {
+        ScriptedMotion {
+            kind: MotionKind::Dash,
+            velocity: direction.normalize() * (distance / duration),
+            duration,
+            remaining: duration,
+            fades: false,
+        }
+    }
+
+    // starts at the speed the impulse would give and fades linearly, so it covers the same
+    // distance whatever the mass as long as the impulse scales with it
+    pub fn knockback(impulse: Vector2<f32>, mass: f32) -> ScriptedMotion {
+        ScriptedMotion {
+            kind: MotionKind::Knockback,
+            velocity: impulse / mass,
+            duration: KNOCKBACK_DURATION,
+            remaining: KNOCKBACK_DURATION,
+            fades: true,
+        }
+    }
+
+    fn current_velocity(&self) -> Vector2<f32> {
+        if self.fades {
+            self.velocity * (self.remaining / self.duration)
+        } else {
+            self.velocity
+        }
+    }
+}
+
+// runs before each step, after the character controllers so it wins over them,
+// returns the entities whose motion ended for the caller to drop the component
+pub fn drive(world: &mut World<f32>, entities: &hecs::World, events: &mut Vec<Event>) -> Vec<Entity> {
+    let timestep = world.timestep();
+    let mut ended = vec![];
+
+    for (entity, (physics_body, replicated, motion)) in entities.query::<(&PhysicsBody, &Replicated, &mut ScriptedMotion)>().iter() {
+        let position = match world.rigid_body(physics_body.body) {
+            Some(rigid_body) => *rigid_body.position(),
+            None => continue,
+        };
+
+        let mut velocity = motion.current_velocity();
+        motion.remaining -= timestep;
+
+        // the shape cast stops the motion right against whatever is in the way
+        let blocked = controller::cast(world, physics_body.collider, &position, &(velocity * timestep));
+        if let Some((toi, _)) = blocked {
+            velocity *= toi;
+        }
+
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            rigid_body.set_linear_velocity(velocity);
+            rigid_body.activate();
+        }
+
+        if blocked.is_some() || motion.remaining <= 0.0 {
+            events.push(Event::MotionEnded { entity: replicated.id, motion: motion.kind });
+            ended.push(entity);
+        }
+    }
+
+    ended
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use hecs::Entity;
+use nphysics2d::object::ColliderHandle;
+
+use crate::protocol::NetId;
+
+// ids are handed out in spawn order and never reused within a room, so a late message
+// about a despawned entity can't be mistaken for a newer one
+#[derive(Default)]
+pub struct NetIds {
+    next: NetId,
+    entities: HashMap<NetId, Entity>,
+    // keyed by collider uid, contact events only know about colliders
+    colliders: HashMap<usize, NetId>,
+}
+
+impl NetIds {
+    pub fn allocate(&mut self, entity: Entity, collider: ColliderHandle) -> NetId {
+        let id = self.next;
+        self.next += 1;
+
+        self.entities.insert(id, entity);
+        self.colliders.insert(collider.uid(), id);
+        id
+    }
+
+    pub fn release(&mut self, id: NetId) {
+        self.entities.remove(&id);
+        self.colliders.retain(|_, net_id| *net_id != id);
+    }
+
+    pub fn entity(&self, id: NetId) -> Option<Entity> {
+        self.entities.get(&id).cloned()
+    }
+
+    pub fn by_collider(&self, collider: ColliderHandle) -> Option<NetId> {
+        self.colliders.get(&collider.uid()).cloned()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = NetId> + '_ {
+        self.entities.keys().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+}
@@ -0,0 +1,621 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::algebra::Velocity2;
+
+use crate::clock::ClockSync;
+use crate::codec::{self, DecodeError};
+use crate::compression::{self, Compression, Compressor};
+use crate::entity_watch::EntityReport;
+use crate::levelgen::ArenaParams;
+use crate::session::SessionToken;
+use crate::view::ViewRequest;
+
+pub type ClientId = usize;
+// assigned by the room in spawn order, unlike collider uids it never depends on the physics world
+pub type NetId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Player,
+    // receives the room state but can't control anything
+    Spectator,
+}
+
+#[derive(Debug, Clone)]
+pub struct EntityState {
+    pub id: NetId,
+    // ownerless entities are driven by the server, clients only predict the ones they own
+    pub owner: Option<ClientId>,
+    pub position: Isometry2<f32>,
+    pub velocity: Velocity2<f32>,
+    // only for character-controlled entities
+    pub character: Option<CharacterState>,
+    // where the grappling rope of the entity is attached, the other end is the entity itself
+    pub grapple: Option<Point2<f32>>,
+    // angles of a vehicle's wheels relative to its chassis, empty for anything else
+    pub wheels: Vec<f32>,
+    // limb positions of a ragdoll, the entity itself is the torso
+    pub limbs: Vec<Isometry2<f32>>,
+    // surface of a soft body relative to its position, updated at a reduced rate
+    pub outline: Vec<Point2<f32>>,
+    // points of a rope hanging from the entity, the first one is on the entity
+    pub rope: Vec<Point2<f32>>,
+    // ticks left before the entity can be destroyed again
+    pub invulnerable: Option<u32>,
+    pub team: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharacterState {
+    pub grounded: bool,
+    pub jumping: bool,
+}
+
+impl EntityState {
+    pub fn new(id: NetId, owner: Option<ClientId>, position: Isometry2<f32>, velocity: Velocity2<f32>) -> EntityState {
+        EntityState {
+            id,
+            owner,
+            position,
+            velocity,
+            character: None,
+            grapple: None,
+            wheels: vec![],
+            limbs: vec![],
+            outline: vec![],
+            rope: vec![],
+            invulnerable: None,
+            team: None,
+        }
+    }
+}
+
+// tells clients which visual to instantiate, the order matters to the codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    Wall,
+    Player,
+    Projectile,
+    Pickup,
+    Prop,
+    Vehicle,
+    SoftBody,
+}
+
+// sizes include the collider margin, this is what clients should draw
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Cuboid { half_extents: Vector2<f32> },
+    Ball { radius: f32 },
+    // open line through the points, relative to the entity position
+    Polyline { points: Vec<Point2<f32>> },
+}
+
+// static geometry is streamed by chunk, outside of the entities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkId {
+    pub x: i32,
+    pub y: i32,
+}
+
+// where gameplay would like the client's camera, the client is free to ignore it, `None` leaves
+// the target to the client, usually its own entity, zoom 1 is the client's default
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraHint {
+    pub target: Option<NetId>,
+    pub zoom: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkEntry {
+    pub id: ChunkId,
+    pub blocks: u16,
+    // whether it collides right now, clients may draw it either way
+    pub loaded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub position: Vector2<f32>,
+    pub shape: Shape,
+}
+
+// a level material as clients see it, the one with id 1 comes first, the sets and the footstep
+// are names the client maps to its own assets
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialEntry {
+    pub name: String,
+    pub sound_set: Option<String>,
+    pub particle_set: Option<String>,
+    pub footstep: Option<String>,
+}
+
+// replicated with the spawn and when it changes, never in snapshots, so keep it small
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub tags: Vec<String>,
+    // sorted so the encoding doesn't depend on insertion order
+    pub values: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimerState {
+    pub name: String,
+    pub remaining_ticks: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub tick: u64,
+    // a keyframe holds every replicated entity, other snapshots only the ones that changed since the previous one
+    pub keyframe: bool,
+    // last command sequence the server processed for the receiving client
+    pub ack: Option<u32>,
+    pub clock: ClockSync,
+    // shared by every client of the room for a given tick
+    pub entities: Arc<Vec<EntityState>>,
+    // every running timer, in every snapshot so clients never have to count on their own
+    pub timers: Arc<Vec<TimerState>>,
+}
+
+// multi-tick movements resolved by the server, the order matters to the codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    Dash,
+    Knockback,
+}
+
+// the order matters to the codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchPhase {
+    Lobby,
+    Countdown,
+    Playing,
+    Finished,
+}
+
+// the order matters to the codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DespawnReason {
+    // by gameplay rules, the host or the session of its owner expiring
+    Removed,
+    // its health ran out
+    Destroyed,
+    // its time to live ran out
+    Expired,
+    // it left the world bounds
+    OutOfBounds,
+    // a portal sent it to another room
+    Transferred,
+}
+
+// found by the invariant checks after a tick, the order matters to the codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    // NaN or infinite position or velocity, the entity is removed before it reaches a snapshot
+    NonFinite,
+    // outside the world bounds even though those are enforced
+    OutOfBounds,
+    // faster than the room limits even though those are enforced
+    TooFast,
+    // the entity, its net id and its collider don't point to each other anymore
+    Unmapped,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Joined { client: ClientId, entity: Option<NetId> },
+    Left { client: ClientId, entity: Option<NetId> },
+    // `impulse` is the largest one either body received on impact, `normal` points from `a` to `b`
+    ContactStarted { a: NetId, b: NetId, impulse: f32, point: Point2<f32>, normal: Vector2<f32> },
+    ContactStopped(NetId, NetId),
+    AuthorityChanged { entity: NetId, previous: Option<ClientId>, owner: Option<ClientId> },
+    // sent once per entity, snapshots only carry its motion afterwards
+    Spawned { entity: NetId, kind: EntityKind, shape: Shape, position: Isometry2<f32>, metadata: Metadata },
+    // the entity won't appear in snapshots anymore, clients can drop it
+    Despawned { entity: NetId, reason: DespawnReason },
+    // replaces the whole metadata of the entity
+    MetadataChanged { entity: NetId, metadata: Metadata },
+    // two entities flagged for proximity came within their margins, or left them
+    Approached { a: NetId, b: NetId },
+    Separated { a: NetId, b: NetId },
+    // the solver produced a speed above the room limits, the entity was slowed down
+    VelocityClamped { entity: NetId },
+    MotionStarted { entity: NetId, motion: MotionKind },
+    // sent when the motion ran its course or hit something
+    MotionEnded { entity: NetId, motion: MotionKind },
+    // the client controls a new player entity, its previous one was destroyed
+    Respawned { client: ClientId, entity: NetId },
+    // also sent to joining clients with the spawns
+    MatchPhaseChanged { phase: MatchPhase },
+    // `points` were added to the score of the client
+    Scored { client: ClientId, points: u32, score: u32 },
+    // a host timer ran out, the match ones show up as phase changes instead
+    TimerExpired { name: String },
+    // only with invariant checks on, something the simulation should never produce
+    InvariantViolated { entity: NetId, violation: Violation },
+    // the game's own bytes for the entity, only sent when they change and to joining clients
+    PayloadChanged { entity: NetId, payload: Vec<u8> },
+    // follows the spawn of an entity with continuous collision, predicting clients sweep it the same way
+    ContinuousCollision { entity: NetId },
+    // a hitscan shot landed, `damage` is 0 when the target can't be hurt by it
+    Hit { shooter: NetId, target: NetId, point: Point2<f32>, damage: f32 },
+    // the entities a swing of `entity` reached, gameplay handlers decide what it does to them
+    Swung { entity: NetId, targets: Vec<NetId> },
+    // something hit hard enough to be heard, materials are level material ids, 0 for none, contacts
+    // with level blocks are included and only one is sent per pair of materials every few ticks
+    ImpactFx { material_a: u8, material_b: u8, impulse: f32, point: Point2<f32> },
+    // sent on join to clients on an older version than `current`, support for versions below
+    // `minimum` is already gone and the client's may be next
+    ProtocolDeprecated { current: u16, minimum: u16 },
+}
+
+impl Event {
+    // the protocol version that brought the event in, older clients never get it
+    pub fn version(&self) -> u16 {
+        match self {
+            Event::InvariantViolated { .. } => 2,
+            Event::PayloadChanged { .. } => 4,
+            Event::ContinuousCollision { .. } => 6,
+            Event::Hit { .. } => 8,
+            Event::Swung { .. } => 9,
+            Event::ImpactFx { .. } => 10,
+            Event::ProtocolDeprecated { .. } => 14,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Impulse { entity: NetId, impulse: Vector2<f32> },
+    // horizontal intent for character-controlled entities, between -1 and 1
+    Move { entity: NetId, direction: f32 },
+    // sent on press and on release, releasing early makes a shorter jump
+    Jump { entity: NetId, pressed: bool },
+    // covers `distance` along `direction` in `duration` seconds, or less if something is in the way
+    Dash { entity: NetId, direction: Vector2<f32>, distance: f32, duration: f32 },
+    // fires the grappling hook, a miss leaves the entity free
+    Grapple { entity: NetId, direction: Vector2<f32> },
+    // positive reels in, negative lets out, 0 holds the current length
+    Reel { entity: NetId, speed: f32 },
+    Release { entity: NetId },
+    // both between -1 and 1, steering leans the chassis
+    Drive { entity: NetId, throttle: f32, steer: f32 },
+    // `client_tick` is the last snapshot the shooter had, targets are put back where it showed them
+    FireHitscan { shooter: NetId, dir: Vector2<f32>, client_tick: u64 },
+    // what's within `radius` and `angle` radians around `direction` comes back as `Swung`,
+    // the radius is capped at the server reach
+    Swing { entity: NetId, direction: Vector2<f32>, radius: f32, angle: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandKind {
+    Impulse,
+    Move,
+    Jump,
+    Dash,
+    Grapple,
+    Reel,
+    Release,
+    Drive,
+    FireHitscan,
+    Swing,
+}
+
+impl Command {
+    // the protocol version that brought the command in, lockstep rooms only take clients that know them all
+    pub fn version(&self) -> u16 {
+        match self {
+            Command::FireHitscan { .. } => 8,
+            Command::Swing { .. } => 9,
+            _ => 1,
+        }
+    }
+
+    pub fn kind(&self) -> CommandKind {
+        match self {
+            Command::Impulse { .. } => CommandKind::Impulse,
+            Command::Move { .. } => CommandKind::Move,
+            Command::Jump { .. } => CommandKind::Jump,
+            Command::Dash { .. } => CommandKind::Dash,
+            Command::Grapple { .. } => CommandKind::Grapple,
+            Command::Reel { .. } => CommandKind::Reel,
+            Command::Release { .. } => CommandKind::Release,
+            Command::Drive { .. } => CommandKind::Drive,
+            Command::FireHitscan { .. } => CommandKind::FireHitscan,
+            Command::Swing { .. } => CommandKind::Swing,
+        }
+    }
+
+    pub fn entity(&self) -> NetId {
+        match *self {
+            Command::Impulse { entity, .. }
+            | Command::Move { entity, .. }
+            | Command::Jump { entity, .. }
+            | Command::Dash { entity, .. }
+            | Command::Grapple { entity, .. }
+            | Command::Reel { entity, .. }
+            | Command::Release { entity }
+            | Command::Drive { entity, .. }
+            | Command::Swing { entity, .. } => entity,
+            Command::FireHitscan { shooter, .. } => shooter,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    UnknownEntity,
+    NotOwner,
+    Spectator,
+    CommandNotAllowed,
+    Malformed,
+    ImpulseTooLarge,
+    SpeedTooHigh,
+    DashTooLong,
+    // the match is in its countdown or over
+    MatchNotRunning,
+}
+
+// commands issued by the host application rather than by a client
+#[derive(Debug, Clone)]
+pub enum AdminCommand {
+    TransferAuthority { entity: NetId, owner: Option<ClientId> },
+    SetMetadata { entity: NetId, metadata: Metadata },
+    SetCollisionGroups { entity: NetId, groups: CollisionGroups },
+    // the layer is looked up in the room config
+    SetCollisionLayer { entity: NetId, layer: String },
+    SetGravityScale { entity: NetId, scale: f32 },
+    // `None` takes the entity out of any team
+    SetTeam { entity: NetId, team: Option<u8> },
+    AddScore { client: ClientId, points: u32 },
+    // restarts the timer when it's already running
+    StartTimer { name: String, ticks: u64 },
+    CancelTimer { name: String },
+    Knockback { entity: NetId, impulse: Vector2<f32> },
+    // ownerless until authority over it is transferred to a client
+    SpawnVehicle { position: Vector2<f32> },
+    // the entity goes limp for good, typically a player on death
+    Ragdoll { entity: NetId },
+    SpawnSoftBody { position: Vector2<f32>, radius: f32 },
+    // replaces the rope the entity already had
+    AttachRope { entity: NetId, length: f32, segments: u8 },
+    // `cover` is the share of the blast walls and terrain let through, `None` doesn't look for any
+    Explode { position: Vector2<f32>, radius: f32, impulse: f32, damage: f32, cover: Option<f32> },
+}
+
+// messages sent by the connection layer to the physics thread
+pub enum ClientMessage {
+    // `compression` lists the algorithms the client can decode, preferred first, `version` is the
+    // newest protocol it knows, `view` how far and how often it wants to see, within the server caps
+    Join { token: String, room: String, role: Role, version: u16, compression: Vec<Compression>, view: ViewRequest, tx: Sender<Frame> },
+    Resume { token: SessionToken, tx: Sender<Frame> },
+    Leave { client: ClientId },
+    Command { client: ClientId, sequence: u32, command: Command },
+    // `scope` is the tenant whose key sent it, it only reaches the tenant's rooms, `None` for the
+    // operator who reaches every room
+    Admin { room: String, scope: Option<String>, command: AdminCommand },
+    // streams the entity's state at the end of every tick until `tx` is dropped, never recorded
+    WatchEntity { room: String, scope: Option<String>, entity: NetId, tx: Sender<EntityReport> },
+    // reads the config file again and applies it to every room, the same as a SIGHUP, `reply` gets
+    // what went wrong when the new config needs a restart
+    Reload { reply: Sender<Result<(), String>> },
+    // times are in milliseconds, `sent_at` is the server time echoed from our ping
+    Ping { client: ClientId, client_time: u64 },
+    Pong { client: ClientId, sent_at: u64, client_time: u64 },
+    FetchChunk { client: ClientId, chunk: ChunkId },
+    // chat, emotes or anything else the game makes of it, to one client of the room or, with no `to`,
+    // to every other one
+    Relay { client: ClientId, to: Option<ClientId>, channel: u8, payload: Vec<u8> },
+    // the snapshot of `tick` arrived, the first ack switches the client to deltas
+    AckSnapshot { client: ClientId, tick: u64 },
+    // in lockstep rooms, the checksum of a keyframe didn't match what the client stepped, it gets
+    // the next keyframe to start over from
+    Desynced { client: ClientId },
+}
+
+impl ClientMessage {
+    pub fn client(&self) -> Option<ClientId> {
+        match *self {
+            ClientMessage::Leave { client }
+            | ClientMessage::Command { client, .. }
+            | ClientMessage::Ping { client, .. }
+            | ClientMessage::Pong { client, .. }
+            | ClientMessage::FetchChunk { client, .. }
+            | ClientMessage::Relay { client, .. }
+            | ClientMessage::AckSnapshot { client, .. }
+            | ClientMessage::Desynced { client } => Some(client),
+            ClientMessage::Join { .. }
+            | ClientMessage::Resume { .. }
+            | ClientMessage::Admin { .. }
+            | ClientMessage::WatchEntity { .. }
+            | ClientMessage::Reload { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    // may be dropped when a newer message supersedes it
+    Unreliable,
+    ReliableOrdered,
+    ReliableUnordered,
+}
+
+// the encoding clients get unless they joined with an older one, bumped whenever a message or
+// an event is added or changes layout
+pub const PROTOCOL_VERSION: u16 = 14;
+// the oldest version still encoded and decoded, clients on it are warned it's going away
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+// lockstep rooms replace snapshots with `Inputs`, older clients would get nothing, and every
+// command of the inputs has to be known to the clients stepping them, so it follows the newest
+// `Command::version`
+pub const MIN_LOCKSTEP_VERSION: u16 = 9;
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    // the protocol version the message is encoded with
+    pub version: u16,
+    pub channel: Channel,
+    pub compression: Compression,
+    // never compressed, the message encoding is `header` followed by the decompressed `payload`
+    pub header: Vec<u8>,
+    pub payload: Arc<[u8]>,
+}
+
+impl Frame {
+    // `scratch` only holds the uncompressed encoding, reuse it between frames
+    // snapshots are laid out the same in every version, their bodies are shared whatever the client's
+    pub fn new(message: &ServerMessage, version: u16, compressor: &mut Compressor, scratch: &mut Vec<u8>, shared: &Mutex<SharedPayloads>) -> Frame {
+        if let ServerMessage::Snapshot(snapshot) = message {
+            // held while the body is encoded, the clients that want the same one wait for it
+            let (compression, payload) = shared.lock().unwrap().snapshot_body(snapshot, compressor, scratch);
+            codec::encode_snapshot_header(snapshot, scratch);
+
+            return Frame {
+                version,
+                channel: message.channel(),
+                compression,
+                header: scratch.clone(),
+                payload,
+            };
+        }
+
+        codec::encode_into(message, scratch);
+        let (compression, payload) = compressor.compress(scratch);
+
+        Frame {
+            version,
+            channel: message.channel(),
+            compression,
+            header: vec![],
+            payload: payload.into(),
+        }
+    }
+
+    // used before a compression and a version have been agreed on, for messages every version shares
+    pub fn uncompressed(message: &ServerMessage) -> Frame {
+        Frame {
+            version: MIN_PROTOCOL_VERSION,
+            channel: message.channel(),
+            compression: Compression::None,
+            header: vec![],
+            payload: codec::encode(message).into(),
+        }
+    }
+
+    pub fn decode(&self) -> Result<ServerMessage, DecodeError> {
+        let mut bytes = self.header.clone();
+        bytes.extend(compression::decompress(self.compression, &self.payload)?);
+        codec::decode_version(self.version, &bytes)
+    }
+}
+
+// snapshot bodies encoded during one flush, so every client using the same compression
+// shares a single buffer instead of encoding the room again, entity lists are told apart by address
+#[derive(Default)]
+pub struct SharedPayloads {
+    bodies: Vec<(usize, u64, Compression, Compression, Arc<[u8]>)>,
+}
+
+impl SharedPayloads {
+    fn snapshot_body(&mut self, snapshot: &Snapshot, compressor: &mut Compressor, scratch: &mut Vec<u8>) -> (Compression, Arc<[u8]>) {
+        let entities = Arc::as_ptr(&snapshot.entities) as usize;
+        let requested = compressor.compression();
+
+        let cached = self.bodies.iter()
+            .find(|(ptr, tick, compression, _, _)| *ptr == entities && *tick == snapshot.tick && *compression == requested);
+        if let Some((_, _, _, used, payload)) = cached {
+            return (*used, payload.clone());
+        }
+
+        codec::encode_snapshot_body(snapshot, scratch);
+        let (used, payload) = compressor.compress(scratch);
+        let payload: Arc<[u8]> = payload.into();
+
+        self.bodies.push((entities, snapshot.tick, requested, used, payload.clone()));
+        (used, payload)
+    }
+}
+
+// messages sent by the physics thread to a client
+#[derive(Debug, Clone)]
+pub enum ServerMessage {
+    Welcome { client: ClientId, token: SessionToken, entity: Option<NetId>, compression: Compression },
+    Resumed { client: ClientId, entity: Option<NetId> },
+    Rejected(String),
+    Throttled { dropped: u32 },
+    CommandRejected { command: CommandKind, reason: RejectReason },
+    Snapshot(Snapshot),
+    Events(Vec<Event>),
+    Ping { sent_at: u64 },
+    Pong { client_time: u64, tick: u64, server_time: u64 },
+    End,
+    ChunkManifest(Vec<ChunkEntry>),
+    Chunk { id: ChunkId, blocks: Vec<Block> },
+    // sent on join with the chunk manifest, the materials `ImpactFx` and surfaces refer to
+    Materials(Vec<MaterialEntry>),
+    // rumble for the client's gamepad, one of its entities hit something hard, `magnitude` is
+    // between 0 and 1, stale ones are better dropped than played late
+    Feedback { magnitude: f32, duration_ms: u16 },
+    // only sent when gameplay changes it, and again on resume
+    Camera(CameraHint),
+    // sent on join when the room plays on a generated arena
+    Arena { seed: u64, params: ArenaParams },
+    // the room panicked, when it's restarting a new welcome follows with a fresh entity
+    Crashed { restarting: bool },
+    // an opaque message from another client of the room, `channel` is up to the game
+    Relayed { from: ClientId, channel: u8, payload: Vec<u8> },
+    // lockstep rooms send this instead of snapshots, the commands each client got through before
+    // `tick` was stepped in the order they were applied, `checksum` on keyframes to catch a desync
+    Inputs { tick: u64, commands: Arc<Vec<(ClientId, Command)>>, checksum: Option<u64> },
+    // between keyframes for clients that ack snapshots, the transforms of every entity xored with
+    // the ones of the `baseline` tick, see xor_delta.rs, `len` is the size once decoded
+    SnapshotDelta { tick: u64, baseline: u64, ack: Option<u32>, len: u32, bytes: Vec<u8> },
+}
+
+impl ServerMessage {
+    // what a client on an older protocol gets instead, parts it has no equivalent for are left out
+    // and `None` when nothing is left
+    pub fn downgrade(self, version: u16) -> Option<ServerMessage> {
+        if version >= PROTOCOL_VERSION {
+            return Some(self);
+        }
+
+        match self {
+            ServerMessage::Events(events) => Some(ServerMessage::Events(events.into_iter().filter(|event| event.version() <= version).collect())),
+            // version 12
+            ServerMessage::Camera(_) if version < 13 => None,
+            // version 11
+            ServerMessage::Feedback { .. } if version < 12 => None,
+            // version 10
+            ServerMessage::Materials(_) if version < 11 => None,
+            // version 6, only clients that ack snapshots get them
+            ServerMessage::SnapshotDelta { .. } if version < 7 => None,
+            // version 4
+            ServerMessage::Inputs { .. } if version < MIN_LOCKSTEP_VERSION => None,
+            // version 2
+            ServerMessage::Relayed { .. } if version < 3 => None,
+            // version 1
+            ServerMessage::Crashed { .. } if version < 2 => Some(ServerMessage::End),
+            message => Some(message),
+        }
+    }
+
+    pub fn channel(&self) -> Channel {
+        match self {
+            ServerMessage::Snapshot(_)
+            | ServerMessage::SnapshotDelta { .. }
+            | ServerMessage::Ping { .. }
+            | ServerMessage::Pong { .. }
+            | ServerMessage::Feedback { .. } => Channel::Unreliable,
+            ServerMessage::Relayed { .. } => Channel::ReliableUnordered,
+            _ => Channel::ReliableOrdered,
+        }
+    }
+}
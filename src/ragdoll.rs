@@ -0,0 +1,199 @@
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::shape::{Cuboid, ShapeHandle};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::joint::{ConstraintHandle, RevoluteConstraint};
+use nphysics2d::object::{BodyHandle, ColliderHandle, Material};
+use nphysics2d::algebra::Inertia2;
+use nphysics2d::volumetric::Volumetric;
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+
+const LIMB_DENSITY: f32 = 1.0;
+
+// a limb hangs from the torso or from a limb listed before it
+#[derive(Debug, Clone)]
+pub struct LimbDescriptor {
+    pub half_extents: Vector2<f32>,
+    // index in `RagdollDescriptor::limbs`, `None` for the torso
+    pub parent: Option<usize>,
+    // where the joint sits, in the parent frame and in the limb frame
+    pub parent_anchor: Point2<f32>,
+    pub anchor: Point2<f32>,
+    // bounds of the limb angle relative to its parent, in radians
+    pub min_angle: f32,
+    pub max_angle: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RagdollDescriptor {
+    pub torso: Vector2<f32>,
+    pub limbs: Vec<LimbDescriptor>,
+    // limbs collide with the world but not with each other nor with the torso
+    pub limb_layer: String,
+}
+
+impl RagdollDescriptor {
+    // fits the player box: a head, two arms and two legs of two segments each
+    pub fn humanoid(torso: Vector2<f32>) -> RagdollDescriptor {
+        let limb = |parent, parent_anchor: Point2<f32>, half_extents: Vector2<f32>, min_angle, max_angle| LimbDescriptor {
+            half_extents,
+            parent,
+            parent_anchor,
+            anchor: Point2::new(0.0, half_extents.y),
+            min_angle,
+            max_angle,
+        };
+        let upper = Vector2::new(0.25, 0.5);
+        let lower = Vector2::new(0.2, 0.5);
+        let (width, height) = (torso.x, torso.y);
+
+        RagdollDescriptor {
+            torso,
+            limbs: vec![
+                LimbDescriptor {
+                    half_extents: Vector2::new(0.4, 0.4),
+                    parent: None,
+                    parent_anchor: Point2::new(0.0, height),
+                    anchor: Point2::new(0.0, -0.4),
+                    min_angle: -0.5,
+                    max_angle: 0.5,
+                },
+                limb(None, Point2::new(-width, height * 0.8), upper, -2.5, 0.5),
+                limb(Some(1), Point2::new(0.0, -upper.y), lower, -2.0, 0.0),
+                limb(None, Point2::new(width, height * 0.8), upper, -0.5, 2.5),
+                limb(Some(3), Point2::new(0.0, -upper.y), lower, 0.0, 2.0),
+                limb(None, Point2::new(-width * 0.5, -height), upper, -1.2, 0.8),
+                limb(Some(5), Point2::new(0.0, -upper.y), lower, 0.0, 2.0),
+                limb(None, Point2::new(width * 0.5, -height), upper, -0.8, 1.2),
+                limb(Some(7), Point2::new(0.0, -upper.y), lower, 0.0, 2.0),
+            ],
+            limb_layer: String::from("limb"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limb {
+    pub body: BodyHandle,
+    pub collider: ColliderHandle,
+    pub joint: ConstraintHandle,
+    // the torso for the first level of limbs
+    pub parent: BodyHandle,
+    pub parent_anchor: Point2<f32>,
+    pub anchor: Point2<f32>,
+    pub min_angle: f32,
+    pub max_angle: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ragdoll {
+    pub limbs: Vec<Limb>,
+}
+
+impl Ragdoll {
+    // every body and joint behind the torso, for the room to remove along with it
+    pub fn parts(&self) -> (Vec<BodyHandle>, Vec<ConstraintHandle>) {
+        (self.limbs.iter().map(|limb| limb.body).collect(), self.limbs.iter().map(|limb| limb.joint).collect())
+    }
+}
+
+// shared by the torso of a spawned ragdoll and its limbs
+pub fn create_part(world: &mut World<f32>, margin: f32, position: Isometry2<f32>, half_extents: Vector2<f32>) -> PhysicsBody {
+    let shape = ShapeHandle::new(Cuboid::new(half_extents - Vector2::repeat(margin)));
+    let inertia = shape.inertia(LIMB_DENSITY);
+    let center_of_mass = shape.center_of_mass();
+    let body = world.add_rigid_body(position, inertia, center_of_mass);
+    let collider = world.add_collider(margin, shape, body, Isometry2::identity(), Material::new(0.0, 0.5));
+
+    PhysicsBody { collider, body }
+}
+
+// a player box turned into a torso has to be free to tumble
+pub fn unlock_rotation(world: &mut World<f32>, body: BodyHandle, half_extents: Vector2<f32>) {
+    if let Some(rigid_body) = world.rigid_body_mut(body) {
+        let mass = rigid_body.local_inertia().linear;
+        let unit = Cuboid::new(half_extents).inertia(1.0);
+        rigid_body.set_local_inertia(Inertia2::new(mass, mass * unit.angular / unit.linear));
+        rigid_body.activate();
+    }
+}
+
+// limbs start hanging straight from their joints in the torso frame, the torso keeps its velocity
+// and hands it down to them so a falling player keeps falling as a whole
+pub fn build(world: &mut World<f32>, margin: f32, torso: &PhysicsBody, descriptor: &RagdollDescriptor, groups: CollisionGroups) -> Ragdoll {
+    let (torso_position, velocity) = match world.rigid_body(torso.body) {
+        Some(rigid_body) => (*rigid_body.position(), rigid_body.velocity().linear),
+        None => return Ragdoll { limbs: vec![] },
+    };
+
+    let mut limbs: Vec<Limb> = Vec::with_capacity(descriptor.limbs.len());
+    let mut positions: Vec<Isometry2<f32>> = Vec::with_capacity(descriptor.limbs.len());
+    for limb in descriptor.limbs.iter() {
+        let (parent, parent_position) = match limb.parent {
+            Some(index) if index < limbs.len() => (limbs[index].body, positions[index]),
+            _ => (torso.body, torso_position),
+        };
+
+        let joint_at = parent_position * limb.parent_anchor;
+        let position = Isometry2::new(joint_at.coords - parent_position.rotation * limb.anchor.coords, parent_position.rotation.angle());
+        let physics_body = create_part(world, margin, position, limb.half_extents);
+        world.collision_world_mut().set_collision_groups(physics_body.collider, groups);
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            rigid_body.set_linear_velocity(velocity);
+        }
+        let joint = world.add_constraint(RevoluteConstraint::new(parent, physics_body.body, limb.parent_anchor, limb.anchor));
+
+        positions.push(position);
+        limbs.push(Limb {
+            body: physics_body.body,
+            collider: physics_body.collider,
+            joint,
+            parent,
+            parent_anchor: limb.parent_anchor,
+            anchor: limb.anchor,
+            min_angle: limb.min_angle,
+            max_angle: limb.max_angle,
+        });
+    }
+
+    Ragdoll { limbs }
+}
+
+// runs after each step, the joints have no angular limits of their own so a limb bent too far is
+// put back at the limit around its joint and stops turning relative to its parent
+pub fn limit_joints(world: &mut World<f32>, entities: &hecs::World) {
+    for (_, ragdoll) in entities.query::<&Ragdoll>().iter() {
+        for limb in ragdoll.limbs.iter() {
+            let (parent_position, parent_angular) = match world.rigid_body(limb.parent) {
+                Some(rigid_body) => (*rigid_body.position(), rigid_body.velocity().angular),
+                None => continue,
+            };
+            let position = match world.rigid_body(limb.body) {
+                Some(rigid_body) => *rigid_body.position(),
+                None => continue,
+            };
+
+            let angle = parent_position.rotation.angle_to(&position.rotation);
+            if angle >= limb.min_angle && angle <= limb.max_angle {
+                continue;
+            }
+
+            let limited = Isometry2::new(Vector2::zeros(), parent_position.rotation.angle() + angle.max(limb.min_angle).min(limb.max_angle));
+            let joint_at = parent_position * limb.parent_anchor;
+            let corrected = Isometry2::new(joint_at.coords - limited.rotation * limb.anchor.coords, limited.rotation.angle());
+            if let Some(rigid_body) = world.rigid_body_mut(limb.body) {
+                rigid_body.set_position(corrected);
+                rigid_body.set_angular_velocity(parent_angular);
+            }
+        }
+    }
+}
+
+// limb poses in the order of the descriptor, clients draw them around the torso
+pub fn limb_positions(world: &World<f32>, ragdoll: &Ragdoll) -> Vec<Isometry2<f32>> {
+    ragdoll.limbs.iter()
+        .filter_map(|limb| world.rigid_body(limb.body))
+        .map(|rigid_body| *rigid_body.position())
+        .collect()
+}
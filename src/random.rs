@@ -0,0 +1,81 @@
+use std::cell::{Cell, RefCell};
+
+use tracing::info;
+
+use crate::levelgen::Generator;
+
+// the draws gameplay makes, loot tables, drops, shuffled orders, come from one generator per room
+// whose seed is recorded with the inputs, so a replay hands out the same items, and every draw is
+// logged with the tick and the generator state before it, enough to redo a disputed one on its own
+pub struct Random {
+    room: String,
+    generator: RefCell<Generator>,
+    draws: Cell<u64>,
+    tick: Cell<u64>,
+}
+
+impl Random {
+    pub fn new(room: &str, seed: u64) -> Random {
+        info!(target: "loot", room, seed, "loot generator seeded");
+        Random {
+            room: room.to_string(),
+            generator: RefCell::new(Generator::new(seed)),
+            draws: Cell::new(0),
+            tick: Cell::new(0),
+        }
+    }
+
+    // the tick the next draws are logged with
+    pub fn at(&self, tick: u64) {
+        self.tick.set(tick);
+    }
+
+    // uniform in [0, n), `n` has to be positive
+    fn below(&self, n: u64) -> u64 {
+        let next = self.generator.borrow_mut().next_u64();
+        ((u128::from(next) * u128::from(n)) >> 64) as u64
+    }
+
+    // the generator state before the draw and its index
+    fn start(&self) -> (u64, u64) {
+        let draw = self.draws.get();
+        self.draws.set(draw + 1);
+        (self.generator.borrow().state(), draw)
+    }
+
+    // an entry picked with a chance proportional to its weight, `None` when they are all zero
+    pub fn weighted<'t, T>(&self, reason: &str, table: &'t [(T, u32)]) -> Option<&'t T> {
+        let total: u64 = table.iter().map(|(_, weight)| u64::from(*weight)).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let (state, draw) = self.start();
+        let mut roll = self.below(total);
+        let pick = table.iter().position(|(_, weight)| {
+            if roll < u64::from(*weight) {
+                return true;
+            }
+            roll -= u64::from(*weight);
+            false
+        })?;
+        info!(target: "loot", room = %self.room, tick = self.tick.get(), draw, state, reason, total, pick, "weighted draw");
+        Some(&table[pick].0)
+    }
+
+    // fisher-yates, the order is logged as the indices the items came from
+    pub fn shuffle<T>(&self, reason: &str, items: &mut [T]) {
+        if items.len() < 2 {
+            return;
+        }
+
+        let (state, draw) = self.start();
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+            order.swap(i, j);
+        }
+        info!(target: "loot", room = %self.room, tick = self.tick.get(), draw, state, reason, order = ?order, "shuffle");
+    }
+}
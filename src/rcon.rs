@@ -0,0 +1,270 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use na::Vector2;
+use tracing::{info, warn};
+
+use crate::protocol::{AdminCommand, ClientMessage};
+use crate::tenant::{self, Tenant};
+use crate::watchdog::Watchdog;
+
+// the source rcon protocol, so the usual rcon clients work: every packet is its i32 size, an i32 id,
+// an i32 type, a nul terminated body and one more nul, all little endian
+const AUTH: i32 = 3;
+const AUTH_RESPONSE: i32 = 2;
+const EXEC_COMMAND: i32 = 2;
+const RESPONSE_VALUE: i32 = 0;
+// a reload is applied between two ticks, longer than this and the tick loop is stuck anyway
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(5);
+// the size the protocol allows, responses are truncated to it
+const MAX_PACKET_SIZE: usize = 4096;
+// id, type and the two nuls
+const HEADER_SIZE: usize = 10;
+
+const HELP: &str = "status
+reload
+transfer <room> <entity> <client|none>
+collision_layer <room> <entity> <layer>
+gravity_scale <room> <entity> <scale>
+team <room> <entity> <team|none>
+score <room> <client> <points>
+start_timer <room> <name> <ticks>
+cancel_timer <room> <name>
+knockback <room> <entity> <x> <y>
+spawn_vehicle <room> <x> <y>
+ragdoll <room> <entity>
+spawn_soft_body <room> <x> <y> <radius>
+rope <room> <entity> <length> <segments>
+explode <room> <x> <y> <radius> <impulse> <damage> <cover|none>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub id: i32,
+    pub kind: i32,
+    pub body: String,
+}
+
+fn read_i32(stream: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    stream.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+pub fn read_packet(stream: &mut impl Read) -> io::Result<Packet> {
+    let size = read_i32(stream)?;
+    if size < HEADER_SIZE as i32 || size as usize > MAX_PACKET_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("packet size {} out of range", size)));
+    }
+    let id = read_i32(stream)?;
+    let kind = read_i32(stream)?;
+
+    let mut body = vec![0; size as usize - 8];
+    stream.read_exact(&mut body)?;
+    // both nuls, some clients send a body that is already nul terminated on top of it
+    while body.last() == Some(&0) {
+        body.pop();
+    }
+    let body = String::from_utf8(body).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the body isn't utf-8"))?;
+
+    Ok(Packet { id, kind, body })
+}
+
+pub fn write_packet(stream: &mut impl Write, packet: &Packet) -> io::Result<()> {
+    let body = &packet.body.as_bytes()[..packet.body.len().min(MAX_PACKET_SIZE - HEADER_SIZE)];
+
+    let mut bytes = Vec::with_capacity(body.len() + HEADER_SIZE + 4);
+    bytes.extend_from_slice(&((body.len() + HEADER_SIZE) as i32).to_le_bytes());
+    bytes.extend_from_slice(&packet.id.to_le_bytes());
+    bytes.extend_from_slice(&packet.kind.to_le_bytes());
+    bytes.extend_from_slice(body);
+    bytes.extend_from_slice(&[0, 0]);
+    stream.write_all(&bytes)
+}
+
+fn arg<T: FromStr>(args: &[&str], i: usize, name: &str) -> Result<T, String> {
+    let word = args.get(i).ok_or_else(|| format!("missing {}", name))?;
+    word.parse().map_err(|_| format!("invalid {} {}", name, word))
+}
+
+// "none" for nobody
+fn optional_arg<T: FromStr>(args: &[&str], i: usize, name: &str) -> Result<Option<T>, String> {
+    match args.get(i) {
+        Some(&"none") => Ok(None),
+        _ => arg(args, i, name).map(Some),
+    }
+}
+
+// `<command> <room> <args>...` to the admin command for that room
+pub fn parse(line: &str) -> Result<(String, AdminCommand), String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, room) = match (words.get(0), words.get(1)) {
+        (Some(name), Some(room)) => (*name, room.to_string()),
+        (Some(_), None) => return Err(String::from("missing room")),
+        _ => return Err(String::from("empty command")),
+    };
+    let args = &words[2..];
+
+    let command = match name {
+        "transfer" => AdminCommand::TransferAuthority { entity: arg(args, 0, "entity")?, owner: optional_arg(args, 1, "client")? },
+        "collision_layer" => AdminCommand::SetCollisionLayer { entity: arg(args, 0, "entity")?, layer: arg(args, 1, "layer")? },
+        "gravity_scale" => AdminCommand::SetGravityScale { entity: arg(args, 0, "entity")?, scale: arg(args, 1, "scale")? },
+        "team" => AdminCommand::SetTeam { entity: arg(args, 0, "entity")?, team: optional_arg(args, 1, "team")? },
+        "score" => AdminCommand::AddScore { client: arg(args, 0, "client")?, points: arg(args, 1, "points")? },
+        "start_timer" => AdminCommand::StartTimer { name: arg(args, 0, "name")?, ticks: arg(args, 1, "ticks")? },
+        "cancel_timer" => AdminCommand::CancelTimer { name: arg(args, 0, "name")? },
+        "knockback" => AdminCommand::Knockback { entity: arg(args, 0, "entity")?, impulse: Vector2::new(arg(args, 1, "x")?, arg(args, 2, "y")?) },
+        "spawn_vehicle" => AdminCommand::SpawnVehicle { position: Vector2::new(arg(args, 0, "x")?, arg(args, 1, "y")?) },
+        "ragdoll" => AdminCommand::Ragdoll { entity: arg(args, 0, "entity")? },
+        "spawn_soft_body" => AdminCommand::SpawnSoftBody { position: Vector2::new(arg(args, 0, "x")?, arg(args, 1, "y")?), radius: arg(args, 2, "radius")? },
+        "rope" => AdminCommand::AttachRope { entity: arg(args, 0, "entity")?, length: arg(args, 1, "length")?, segments: arg(args, 2, "segments")? },
+        "explode" => AdminCommand::Explode {
+            position: Vector2::new(arg(args, 0, "x")?, arg(args, 1, "y")?),
+            radius: arg(args, 2, "radius")?,
+            impulse: arg(args, 3, "impulse")?,
+            damage: arg(args, 4, "damage")?,
+            cover: optional_arg(args, 5, "cover")?,
+        },
+        _ => return Err(format!("unknown command {}, try help", name)),
+    };
+    Ok((room, command))
+}
+
+// a tenant only sees its own rooms, the operator sees who owns each
+fn status(scope: Option<&str>, watchdog: &Watchdog) -> String {
+    let report: Vec<_> = watchdog.report().into_iter()
+        .filter(|(_, health)| tenant::reaches(scope, health.tenant.as_ref().map(String::as_str)))
+        .collect();
+    if report.is_empty() {
+        return String::from("no room");
+    }
+
+    report.iter().map(|(name, health)| format!(
+        "{}{} tick {}{} bodies {} colliders {} queued {}",
+        name,
+        match (scope, &health.tenant) {
+            (None, Some(tenant)) => format!(" tenant {}", tenant),
+            _ => String::new(),
+        },
+        health.tick,
+        if health.stalled { " stalled" } else { "" },
+        health.usage.bodies,
+        health.usage.colliders,
+        health.usage.queued_messages,
+    )).collect::<Vec<_>>().join("\n")
+}
+
+// the config file read again, the response tells whether it was applied
+fn reload(tx: &Sender<ClientMessage>) -> String {
+    let (reply, result) = mpsc::channel();
+    if tx.send(ClientMessage::Reload { reply }).is_err() {
+        return String::from("the simulation stopped");
+    }
+
+    match result.recv_timeout(RELOAD_TIMEOUT) {
+        Ok(Ok(())) => String::from("config reloaded"),
+        Ok(Err(reason)) => reason,
+        Err(_) => String::from("no answer from the simulation, the reload may still be applied"),
+    }
+}
+
+// a tenant is told its own rooms only exist, the tick loop checks the scope again anyway
+fn reachable(scope: Option<&str>, room: &str, watchdog: &Watchdog) -> bool {
+    scope.is_none() || watchdog.report().iter().any(|(name, health)| name == room && tenant::reaches(scope, health.tenant.as_ref().map(String::as_str)))
+}
+
+// admin commands are fire and forget, the room logs what it couldn't apply, `scope` is the tenant
+// the console logged in as, `None` for the operator
+pub fn execute(line: &str, scope: Option<&str>, tx: &Sender<ClientMessage>, watchdog: &Watchdog) -> String {
+    match line.trim() {
+        "help" => String::from(HELP),
+        "status" => status(scope, watchdog),
+        "reload" if scope.is_some() => String::from("reload is for the operator, it applies to every tenant"),
+        "reload" => reload(tx),
+        line => match parse(line) {
+            Ok((room, _)) if !reachable(scope, &room, watchdog) => format!("unknown room {}", room),
+            Ok((room, command)) => match tx.send(ClientMessage::Admin { room: room.clone(), scope: scope.map(String::from), command }) {
+                Ok(()) => format!("sent to {}", room),
+                Err(_) => String::from("the simulation stopped"),
+            },
+            Err(reason) => reason,
+        },
+    }
+}
+
+// every bit is compared so the time taken doesn't tell how much of the password was right
+fn same_password(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// the operator password reaches every room, a tenant api key only the tenant's, `None` when neither matched
+fn authenticate<'t>(body: &str, password: &str, tenants: &'t [Tenant]) -> Option<Option<&'t Tenant>> {
+    // an empty password would let in anyone who sends nothing
+    if body.is_empty() {
+        return None;
+    }
+    if same_password(body, password) {
+        return Some(None);
+    }
+    tenants.iter().find(|tenant| tenant.accepts(body)).map(Some)
+}
+
+fn serve(mut stream: TcpStream, password: &str, tenants: &[Tenant], tx: Sender<ClientMessage>, watchdog: &Watchdog) -> io::Result<()> {
+    let auth = read_packet(&mut stream)?;
+    let scope = match authenticate(&auth.body, password, tenants) {
+        Some(tenant) if auth.kind == AUTH => tenant.map(|tenant| tenant.name.as_str()),
+        _ => {
+            write_packet(&mut stream, &Packet { id: -1, kind: AUTH_RESPONSE, body: String::new() })?;
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "wrong rcon password"));
+        },
+    };
+    // clients expect an empty response value before the auth response
+    write_packet(&mut stream, &Packet { id: auth.id, kind: RESPONSE_VALUE, body: String::new() })?;
+    write_packet(&mut stream, &Packet { id: auth.id, kind: AUTH_RESPONSE, body: String::new() })?;
+
+    loop {
+        let packet = match read_packet(&mut stream) {
+            Ok(packet) => packet,
+            Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        if packet.kind != EXEC_COMMAND {
+            continue;
+        }
+
+        info!(target: "rcon", tenant = ?scope, command = %packet.body, "command");
+        let body = execute(&packet.body, scope, &tx, watchdog);
+        write_packet(&mut stream, &Packet { id: packet.id, kind: RESPONSE_VALUE, body })?;
+    }
+}
+
+// one thread per console, they're few and mostly idle
+pub fn listen(address: &str, password: String, tenants: Vec<Tenant>, tx: Sender<ClientMessage>, watchdog: Arc<Watchdog>) -> io::Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(address)?;
+    info!(target: "rcon", address, "listening");
+
+    Ok(thread::spawn(move || {
+        let (password, tenants) = (Arc::new(password), Arc::new(tenants));
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    warn!(target: "rcon", %error, "can't accept a console");
+                    continue;
+                },
+            };
+            let (password, tenants, tx, watchdog) = (password.clone(), tenants.clone(), tx.clone(), watchdog.clone());
+            let peer = stream.peer_addr().map(|peer| peer.to_string()).unwrap_or_default();
+
+            thread::spawn(move || {
+                if let Err(error) = serve(stream, &password, &tenants, tx, &watchdog) {
+                    warn!(target: "rcon", peer = %peer, %error, "console dropped");
+                }
+            });
+        }
+    }))
+}
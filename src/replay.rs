@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use na::Vector2;
+use tracing::info;
+
+use crate::codec::{self, DecodeError, Reader, Writer};
+use crate::collision;
+use crate::config::{Config, ConfigError};
+use crate::level::Level;
+use crate::protocol::{AdminCommand, ClientId, ClientMessage, Command, EntityState, Frame, PROTOCOL_VERSION, Role};
+use crate::room::Room;
+
+const MAGIC: &[u8; 4] = b"SPIL";
+const VERSION: u8 = 2;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Decode(DecodeError),
+    Config(ConfigError),
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "can't access replay: {}", error),
+            ReplayError::Decode(error) => write!(f, "can't decode replay: {}", error),
+            ReplayError::Config(error) => write!(f, "can't rebuild the room of the replay: {}", error),
+            ReplayError::Invalid(reason) => write!(f, "invalid replay: {}", reason),
+        }
+    }
+}
+
+// what reached the room from outside, everything else follows from the simulation
+#[derive(Debug, Clone)]
+pub enum Input {
+    Join { client: ClientId, player: String, role: Role },
+    Leave { client: ClientId },
+    Resume { client: ClientId },
+    Command { client: ClientId, sequence: u32, command: Command },
+    Admin(AdminCommand),
+    // a player coming through a portal from another room
+    Arrive { client: ClientId, player: String, spawn_point: String, velocity: Vector2<f32> },
+    // the connection failed while flushing, it's applied after the tick it's recorded with
+    Dropped { client: ClientId },
+}
+
+// the config and level the room was built from, then every input with the tick it was applied
+// before, and a checksum of the room state at each keyframe to tell when a replay diverges
+pub struct InputLog {
+    pub room: String,
+    pub arena_seed: Option<u64>,
+    pub loot_seed: u64,
+    pub config: String,
+    pub level: String,
+    pub ticks: u64,
+    pub inputs: Vec<(u64, Input)>,
+    pub checksums: Vec<(u64, u64)>,
+}
+
+impl InputLog {
+    pub fn new(room: &str, arena_seed: Option<u64>, loot_seed: u64, config: &str, level: &str) -> InputLog {
+        InputLog {
+            room: room.to_string(),
+            arena_seed,
+            loot_seed,
+            config: config.to_string(),
+            level: level.to_string(),
+            ticks: 0,
+            inputs: vec![],
+            checksums: vec![],
+        }
+    }
+
+    pub fn push(&mut self, tick: u64, input: Input) {
+        self.inputs.push((tick, input));
+    }
+
+    // writes `<room>-<unix time>.replay` in `directory`, returns its path
+    pub fn save(&self, directory: &str) -> Result<String, ReplayError> {
+        fs::create_dir_all(directory).map_err(ReplayError::Io)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+        let path = Path::new(directory).join(format!("{}-{}.replay", self.room, now));
+
+        fs::write(&path, self.encode()).map_err(ReplayError::Io)?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    pub fn load(path: &str) -> Result<InputLog, ReplayError> {
+        let bytes = fs::read(path).map_err(ReplayError::Io)?;
+        InputLog::decode(&bytes)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut writer = Writer::new(&mut bytes);
+
+        for byte in MAGIC {
+            writer.u8(*byte);
+        }
+        writer.u8(VERSION);
+        writer.string(&self.room);
+        writer.option(self.arena_seed, Writer::u64);
+        writer.u64(self.loot_seed);
+        writer.long_string(&self.config);
+        writer.long_string(&self.level);
+        writer.u64(self.ticks);
+
+        writer.u32(self.inputs.len() as u32);
+        for (tick, input) in self.inputs.iter() {
+            writer.u64(*tick);
+            write_input(&mut writer, input);
+        }
+
+        writer.u32(self.checksums.len() as u32);
+        for (tick, checksum) in self.checksums.iter() {
+            writer.u64(*tick);
+            writer.u64(*checksum);
+        }
+
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<InputLog, ReplayError> {
+        let mut reader = Reader::new(bytes);
+        let decode = |error| ReplayError::Decode(error);
+
+        for byte in MAGIC {
+            if reader.u8().map_err(decode)? != *byte {
+                return Err(ReplayError::Invalid(String::from("not an input log")));
+            }
+        }
+        let version = reader.u8().map_err(decode)?;
+        if version != VERSION {
+            return Err(ReplayError::Invalid(format!("version {} isn't supported, expected {}", version, VERSION)));
+        }
+
+        let room = reader.string().map_err(decode)?;
+        let arena_seed = reader.option(Reader::u64).map_err(decode)?;
+        let loot_seed = reader.u64().map_err(decode)?;
+        let config = reader.long_string().map_err(decode)?;
+        let level = reader.long_string().map_err(decode)?;
+        let ticks = reader.u64().map_err(decode)?;
+
+        let len = reader.u32().map_err(decode)?;
+        let mut inputs = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            inputs.push((reader.u64().map_err(decode)?, read_input(&mut reader).map_err(decode)?));
+        }
+
+        let len = reader.u32().map_err(decode)?;
+        let mut checksums = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            checksums.push((reader.u64().map_err(decode)?, reader.u64().map_err(decode)?));
+        }
+
+        Ok(InputLog { room, arena_seed, loot_seed, config, level, ticks, inputs, checksums })
+    }
+}
+
+// fnv-1a over the exact bits of every transform and velocity, in replication order
+pub fn checksum(states: &[EntityState]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut add = |value: u32| {
+        for byte in value.to_le_bytes().iter() {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+
+    for state in states {
+        add(state.id as u32);
+        add(state.position.translation.vector.x.to_bits());
+        add(state.position.translation.vector.y.to_bits());
+        add(state.position.rotation.angle().to_bits());
+        add(state.velocity.linear.x.to_bits());
+        add(state.velocity.linear.y.to_bits());
+        add(state.velocity.angular.to_bits());
+    }
+    hash
+}
+
+// client ids are handed out again while replaying, the recorded ones are mapped to them
+fn map_admin(command: AdminCommand, clients: &HashMap<ClientId, ClientId>) -> AdminCommand {
+    let map = |client: ClientId| clients.get(&client).cloned().unwrap_or(client);
+
+    match command {
+        AdminCommand::TransferAuthority { entity, owner } => AdminCommand::TransferAuthority { entity, owner: owner.map(map) },
+        AdminCommand::AddScore { client, points } => AdminCommand::AddScore { client: map(client), points },
+        command => command,
+    }
+}
+
+// a room rebuilt from an input log, fed the recorded inputs one tick at a time
+pub struct Replayer {
+    pub room: Room,
+    // recorded client ids to the ones handed out while replaying
+    clients: HashMap<ClientId, ClientId>,
+    receivers: Vec<Receiver<Frame>>,
+}
+
+impl Replayer {
+    pub fn new(log: &InputLog) -> Result<Replayer, ReplayError> {
+        let config = Config::parse(&log.config).map_err(ReplayError::Config)?;
+        if !config.deterministic {
+            return Err(ReplayError::Invalid(String::from("the match wasn't recorded in deterministic mode")));
+        }
+        let mut level = Level::parse(&log.level).map_err(ReplayError::Config)?;
+        if let Some(ref mut arena) = level.arena {
+            arena.seed = log.arena_seed;
+        }
+
+        let mut room = Room::new(&log.room, Arc::new(config), Arc::new(level), None);
+        room.stop_recording();
+        room.reseed(log.loot_seed);
+
+        Ok(Replayer {
+            room,
+            clients: HashMap::new(),
+            receivers: vec![],
+        })
+    }
+
+    fn client(&self, recorded: ClientId) -> Option<ClientId> {
+        self.clients.get(&recorded).cloned()
+    }
+
+    // `inputs` are the ones recorded with `tick`, drops are applied after the tick like they happened
+    pub fn step(&mut self, tick: u64, inputs: &[(u64, Input)]) {
+        for (_, input) in inputs.iter().filter(|(_, input)| !is_dropped(input)) {
+            self.apply(input.clone());
+        }
+
+        self.room.tick();
+
+        for (_, input) in inputs.iter() {
+            if let Input::Dropped { client: recorded } = *input {
+                if let Some(client) = self.client(recorded) {
+                    self.room.disconnect(client, tick);
+                }
+            }
+        }
+
+        // nobody reads what the room sends, it's only drained so it doesn't pile up
+        for rx in self.receivers.iter() {
+            while rx.try_recv().is_ok() {}
+        }
+    }
+
+    fn apply(&mut self, input: Input) {
+        match input {
+            Input::Join { client: recorded, player, role } => {
+                let (tx, rx) = mpsc::channel();
+                self.receivers.push(rx);
+                let client = self.room.join(player, role, PROTOCOL_VERSION, &[], tx);
+                self.clients.insert(recorded, client);
+            },
+            Input::Leave { client: recorded } => {
+                if let Some(client) = self.client(recorded) {
+                    self.room.handle(ClientMessage::Leave { client });
+                }
+            },
+            Input::Resume { client: recorded } => {
+                if let Some(token) = self.client(recorded).and_then(|client| self.room.session_token(client)) {
+                    let (tx, rx) = mpsc::channel();
+                    self.receivers.push(rx);
+                    self.room.handle(ClientMessage::Resume { token, tx });
+                }
+            },
+            Input::Command { client: recorded, sequence, command } => {
+                if let Some(client) = self.client(recorded) {
+                    self.room.handle(ClientMessage::Command { client, sequence, command });
+                }
+            },
+            Input::Admin(command) => {
+                let command = map_admin(command, &self.clients);
+                let room = self.room.name.clone();
+                self.room.handle(ClientMessage::Admin { room, scope: None, command });
+            },
+            Input::Arrive { client: recorded, player, spawn_point, velocity } => {
+                let (tx, rx) = mpsc::channel();
+                self.receivers.push(rx);
+                let client = self.room.arrive_replayed(player, &spawn_point, velocity, tx);
+                self.clients.insert(recorded, client);
+            },
+            Input::Dropped { .. } => {},
+        }
+    }
+}
+
+// the inputs of each tick in turn, the log keeps them in tick order
+pub fn ticks<'a>(log: &'a InputLog) -> impl Iterator<Item = (u64, &'a [(u64, Input)])> + 'a {
+    let mut start = 0;
+
+    (0..log.ticks).map(move |tick| {
+        let len = log.inputs[start..].iter().take_while(|(at, _)| *at == tick).count();
+        let inputs = &log.inputs[start..start + len];
+        start += len;
+        (tick, inputs)
+    })
+}
+
+// rebuilds the room from the log and feeds it the same inputs at the same ticks, stops at the
+// first keyframe whose checksum differs from the recorded one
+pub fn play(path: &str) -> Result<(), ReplayError> {
+    let log = InputLog::load(path)?;
+    let mut replayer = Replayer::new(&log)?;
+    let mut checksums = log.checksums.iter().peekable();
+
+    info!(target: "replay", room = %log.room, ticks = log.ticks, path, "replaying the input log");
+    for (tick, inputs) in ticks(&log) {
+        replayer.step(tick, inputs);
+
+        if let Some((_, expected)) = checksums.peek().filter(|(at, _)| *at == tick) {
+            let actual = replayer.room.checksum();
+            if actual != *expected {
+                return Err(ReplayError::Invalid(format!("diverged at tick {}, checksum {:016x} instead of {:016x}", tick, actual, expected)));
+            }
+            checksums.next();
+        }
+    }
+
+    info!(target: "replay", room = %log.room, ticks = log.ticks, "matched the recording bit for bit");
+    Ok(())
+}
+
+fn is_dropped(input: &Input) -> bool {
+    match input {
+        Input::Dropped { .. } => true,
+        _ => false,
+    }
+}
+
+fn write_input(writer: &mut Writer, input: &Input) {
+    match input {
+        Input::Join { client, player, role } => {
+            writer.u8(0);
+            writer.id(*client);
+            writer.string(player);
+            writer.u8(*role as u8);
+        },
+        Input::Leave { client } => {
+            writer.u8(1);
+            writer.id(*client);
+        },
+        Input::Resume { client } => {
+            writer.u8(2);
+            writer.id(*client);
+        },
+        Input::Command { client, sequence, command } => {
+            writer.u8(3);
+            writer.id(*client);
+            writer.u32(*sequence);
+            codec::write_command(writer, command);
+        },
+        Input::Admin(command) => {
+            writer.u8(4);
+            write_admin(writer, command);
+        },
+        Input::Arrive { client, player, spawn_point, velocity } => {
+            writer.u8(5);
+            writer.id(*client);
+            writer.string(player);
+            writer.string(spawn_point);
+            writer.f32(velocity.x);
+            writer.f32(velocity.y);
+        },
+        Input::Dropped { client } => {
+            writer.u8(6);
+            writer.id(*client);
+        },
+    }
+}
+
+fn read_input(reader: &mut Reader) -> Result<Input, DecodeError> {
+    let input = match reader.u8()? {
+        0 => Input::Join {
+            client: reader.id()?,
+            player: reader.string()?,
+            role: match reader.u8()? {
+                0 => Role::Player,
+                1 => Role::Spectator,
+                tag => return Err(DecodeError::UnknownTag(tag)),
+            },
+        },
+        1 => Input::Leave { client: reader.id()? },
+        2 => Input::Resume { client: reader.id()? },
+        3 => Input::Command {
+            client: reader.id()?,
+            sequence: reader.u32()?,
+            command: codec::read_command(reader, PROTOCOL_VERSION)?,
+        },
+        4 => Input::Admin(read_admin(reader)?),
+        5 => Input::Arrive {
+            client: reader.id()?,
+            player: reader.string()?,
+            spawn_point: reader.string()?,
+            velocity: Vector2::new(reader.f32()?, reader.f32()?),
+        },
+        6 => Input::Dropped { client: reader.id()? },
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(input)
+}
+
+
+// tags follow the declaration order of `AdminCommand`
+fn write_admin(writer: &mut Writer, command: &AdminCommand) {
+    match command {
+        AdminCommand::TransferAuthority { entity, owner } => {
+            writer.u8(0);
+            writer.id(*entity);
+            writer.option(*owner, Writer::id);
+        },
+        AdminCommand::SetMetadata { entity, metadata } => {
+            writer.u8(1);
+            writer.id(*entity);
+            codec::write_metadata(writer, metadata);
+        },
+        AdminCommand::SetCollisionGroups { entity, groups } => {
+            writer.u8(2);
+            writer.id(*entity);
+            for mask in collision::masks(groups).iter() {
+                writer.u32(*mask);
+            }
+        },
+        AdminCommand::SetCollisionLayer { entity, layer } => {
+            writer.u8(3);
+            writer.id(*entity);
+            writer.string(layer);
+        },
+        AdminCommand::SetGravityScale { entity, scale } => {
+            writer.u8(4);
+            writer.id(*entity);
+            writer.f32(*scale);
+        },
+        AdminCommand::SetTeam { entity, team } => {
+            writer.u8(5);
+            writer.id(*entity);
+            writer.option(*team, Writer::u8);
+        },
+        AdminCommand::AddScore { client, points } => {
+            writer.u8(6);
+            writer.id(*client);
+            writer.u32(*points);
+        },
+        AdminCommand::StartTimer { name, ticks } => {
+            writer.u8(7);
+            writer.string(name);
+            writer.u64(*ticks);
+        },
+        AdminCommand::CancelTimer { name } => {
+            writer.u8(8);
+            writer.string(name);
+        },
+        AdminCommand::Knockback { entity, impulse } => {
+            writer.u8(9);
+            writer.id(*entity);
+            writer.f32(impulse.x);
+            writer.f32(impulse.y);
+        },
+        AdminCommand::SpawnVehicle { position } => {
+            writer.u8(10);
+            writer.f32(position.x);
+            writer.f32(position.y);
+        },
+        AdminCommand::Ragdoll { entity } => {
+            writer.u8(11);
+            writer.id(*entity);
+        },
+        AdminCommand::SpawnSoftBody { position, radius } => {
+            writer.u8(12);
+            writer.f32(position.x);
+            writer.f32(position.y);
+            writer.f32(*radius);
+        },
+        AdminCommand::AttachRope { entity, length, segments } => {
+            writer.u8(13);
+            writer.id(*entity);
+            writer.f32(*length);
+            writer.u8(*segments);
+        },
+        AdminCommand::Explode { position, radius, impulse, damage, cover } => {
+            writer.u8(14);
+            writer.f32(position.x);
+            writer.f32(position.y);
+            writer.f32(*radius);
+            writer.f32(*impulse);
+            writer.f32(*damage);
+            writer.option(*cover, Writer::f32);
+        },
+    }
+}
+
+fn read_admin(reader: &mut Reader) -> Result<AdminCommand, DecodeError> {
+    let command = match reader.u8()? {
+        0 => AdminCommand::TransferAuthority { entity: reader.id()?, owner: reader.option(Reader::id)? },
+        1 => AdminCommand::SetMetadata { entity: reader.id()?, metadata: codec::read_metadata(reader)? },
+        2 => AdminCommand::SetCollisionGroups {
+            entity: reader.id()?,
+            groups: collision::from_masks([reader.u32()?, reader.u32()?, reader.u32()?]),
+        },
+        3 => AdminCommand::SetCollisionLayer { entity: reader.id()?, layer: reader.string()? },
+        4 => AdminCommand::SetGravityScale { entity: reader.id()?, scale: reader.f32()? },
+        5 => AdminCommand::SetTeam { entity: reader.id()?, team: reader.option(Reader::u8)? },
+        6 => AdminCommand::AddScore { client: reader.id()?, points: reader.u32()? },
+        7 => AdminCommand::StartTimer { name: reader.string()?, ticks: reader.u64()? },
+        8 => AdminCommand::CancelTimer { name: reader.string()? },
+        9 => AdminCommand::Knockback { entity: reader.id()?, impulse: Vector2::new(reader.f32()?, reader.f32()?) },
+        10 => AdminCommand::SpawnVehicle { position: Vector2::new(reader.f32()?, reader.f32()?) },
+        11 => AdminCommand::Ragdoll { entity: reader.id()? },
+        12 => AdminCommand::SpawnSoftBody { position: Vector2::new(reader.f32()?, reader.f32()?), radius: reader.f32()? },
+        13 => AdminCommand::AttachRope { entity: reader.id()?, length: reader.f32()?, segments: reader.u8()? },
+        14 => AdminCommand::Explode {
+            position: Vector2::new(reader.f32()?, reader.f32()?),
+            radius: reader.f32()?,
+            impulse: reader.f32()?,
+            damage: reader.f32()?,
+            cover: reader.option(Reader::f32)?,
+        },
+        tag => return Err(DecodeError::UnknownTag(tag)),
+    };
+
+    Ok(command)
+}
@@ -0,0 +1,63 @@
+use na::Vector2;
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+use crate::level::SpawnPointDefinition;
+use crate::protocol::{ClientId, EntityKind};
+
+#[derive(Debug, Clone)]
+pub struct SpawnPoint {
+    pub name: String,
+    pub position: Vector2<f32>,
+}
+
+impl SpawnPoint {
+    pub fn new(definition: &SpawnPointDefinition) -> SpawnPoint {
+        SpawnPoint {
+            name: definition.name.clone(),
+            position: Vector2::new(definition.position[0], definition.position[1]),
+        }
+    }
+}
+
+// the spawn point whose closest player is the farthest away, `None` without spawn points
+pub fn least_contested<'a>(spawn_points: &'a [SpawnPoint], world: &World<f32>, entities: &hecs::World) -> Option<&'a SpawnPoint> {
+    let players: Vec<Vector2<f32>> = entities.query::<(&PhysicsBody, &EntityKind)>()
+        .iter()
+        .filter(|(_, (_, kind))| **kind == EntityKind::Player)
+        .filter_map(|(_, (physics_body, _))| world.rigid_body(physics_body.body))
+        .map(|rigid_body| rigid_body.position().translation.vector)
+        .collect();
+
+    let contest = |spawn_point: &SpawnPoint| {
+        players.iter()
+            .map(|player| (player - spawn_point.position).norm())
+            .fold(std::f32::INFINITY, f32::min)
+    };
+
+    spawn_points.iter().fold(None, |best: Option<(&SpawnPoint, f32)>, spawn_point| {
+        let distance = contest(spawn_point);
+        match best {
+            Some((_, best_distance)) if best_distance >= distance => best,
+            _ => Some((spawn_point, distance)),
+        }
+    }).map(|(spawn_point, _)| spawn_point)
+}
+
+// clients waiting for a new player entity, in the order they lost theirs
+#[derive(Default)]
+pub struct RespawnQueue {
+    pending: Vec<(u64, ClientId)>,
+}
+
+impl RespawnQueue {
+    pub fn schedule(&mut self, tick: u64, client: ClientId) {
+        self.pending.push((tick, client));
+    }
+
+    pub fn due(&mut self, tick: u64) -> Vec<ClientId> {
+        let due = self.pending.iter().filter(|(at, _)| *at <= tick).map(|(_, client)| *client).collect();
+        self.pending.retain(|(at, _)| *at > tick);
+        due
+    }
+}
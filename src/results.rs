@@ -0,0 +1,78 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::ClientId;
+
+#[derive(Debug)]
+pub enum ResultsError {
+    Database(sled::Error),
+    Encoding(String),
+}
+
+impl fmt::Display for ResultsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResultsError::Database(error) => write!(f, "results database error: {}", error),
+            ResultsError::Encoding(reason) => write!(f, "can't encode match results: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerResult {
+    pub client: ClientId,
+    pub player: String,
+    pub score: u32,
+}
+
+// `players` stays last, toml wants plain values before arrays of tables
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub room: String,
+    // seconds since the unix epoch
+    pub finished_at: u64,
+    pub duration_ticks: u64,
+    pub replay: Option<String>,
+    pub players: Vec<PlayerResult>,
+}
+
+// finished matches in an embedded database, keyed by an increasing id so they iterate in order,
+// cheap to clone and shared by every room and the host
+#[derive(Clone)]
+pub struct ResultsStore {
+    db: sled::Db,
+}
+
+impl ResultsStore {
+    pub fn open(path: &str) -> Result<ResultsStore, ResultsError> {
+        let db = sled::open(path).map_err(ResultsError::Database)?;
+        Ok(ResultsStore { db })
+    }
+
+    pub fn record(&self, result: &MatchResult) -> Result<u64, ResultsError> {
+        let encoded = toml::to_string(result).map_err(|error| ResultsError::Encoding(error.to_string()))?;
+        let id = self.db.generate_id().map_err(ResultsError::Database)?;
+        self.db.insert(id.to_be_bytes(), encoded.as_bytes()).map_err(ResultsError::Database)?;
+        Ok(id)
+    }
+
+    // the most recent first, entries that no longer decode are skipped
+    pub fn recent(&self, limit: usize) -> Result<Vec<(u64, MatchResult)>, ResultsError> {
+        let mut results = Vec::with_capacity(limit);
+        for entry in self.db.iter().rev() {
+            if results.len() >= limit {
+                break;
+            }
+
+            let (key, value) = entry.map_err(ResultsError::Database)?;
+            let mut id = [0; 8];
+            id.copy_from_slice(&key[..8]);
+            let decoded = std::str::from_utf8(&value).ok().and_then(|source| toml::from_str(source).ok());
+            if let Some(result) = decoded {
+                results.push((u64::from_be_bytes(id), result));
+            }
+        }
+        Ok(results)
+    }
+}
@@ -0,0 +1,1915 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hecs::Entity;
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::shape::{Cuboid, ShapeHandle};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::object::{BodyHandle, BodyStatus, ColliderHandle, Material};
+use nphysics2d::volumetric::Volumetric;
+use nphysics2d::world::World;
+use nphysics2d::algebra::Inertia2;
+use tracing::{error, info, info_span, warn, Span};
+
+use crate::body_pool::BodyPool;
+use crate::ccd;
+use crate::changes::ChangeTracker;
+use crate::collision;
+use crate::components::{Continuous, Damping, GravityScale, Health, Invulnerable, Joints, Owner, Payload, PhysicsBody, Pooled, Proximity, Replicated, Surface, Team};
+use crate::compression::{self, Compression, Compressor};
+use crate::chain;
+use crate::chunks::Chunks;
+use crate::config::Config;
+use crate::entity_cap;
+use crate::level::{ChainDefinition, Level, MaterialDefinition, TimelineAction};
+use crate::levelgen::{self, ArenaParams};
+use crate::controller::{self, CharacterController};
+use crate::motion::{self, ScriptedMotion};
+use crate::grapple::{self, Grapple};
+use crate::haptics;
+use crate::hitscan::{self, History};
+use crate::impact_fx::ImpactLimiter;
+use crate::inspect::{self, Inspection};
+use crate::invariants::{self, Limits};
+use crate::entity_watch::EntityWatches;
+use crate::expiry::DespawnSchedule;
+use crate::gameplay::{BouncePad, CommandQueue, Context, GameplayCommand, GameplayHandler, KillZone, Portal};
+use crate::matchmaking::RoomSummary;
+use crate::match_state::MatchState;
+use crate::melee;
+use crate::net_ids::NetIds;
+use crate::replay::{self, Input, InputLog};
+use crate::ragdoll::{self, Ragdoll, RagdollDescriptor};
+use crate::random::Random;
+use crate::protocol::{AdminCommand, CameraHint, ClientId, ClientMessage, Command, CommandKind, DespawnReason, EntityKind, EntityState, Event, Frame, MatchPhase, MaterialEntry, Metadata, NetId, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, RejectReason, Role, ServerMessage, Shape, TimerState, Violation};
+use crate::session::{Session, SessionToken, Sessions};
+use crate::results::{MatchResult, PlayerResult, ResultsStore};
+use crate::respawn::{self, RespawnQueue, SpawnPoint};
+use crate::rope::{self, Rope};
+use crate::soft_body::{self, SoftBody};
+use crate::spatial_cache::SpatialCache;
+use crate::spawner::Spawner;
+use crate::systems;
+use crate::tenant;
+use crate::terrain;
+use crate::timeline::Timeline;
+use crate::timers::{self, Timers};
+use crate::timing::{Phase, PhaseTimings};
+use crate::usage::{self, RoomUsage};
+use crate::validation::{self, EntityLimits};
+use crate::vehicle::{self, Vehicle};
+use crate::view::{self, View, ViewRequest};
+use crate::xor_delta;
+
+const GROUND_RADIUS: f32 = 50.0;
+const BALL_RADIUS: f32 = 1.5;
+// how long a dropped client can come back and reclaim its ball
+const RECONNECT_GRACE_TICKS: u64 = 120;
+const PING_INTERVAL_TICKS: u64 = 30;
+// heals deltas lost on the unreliable channel, it's also the spectators update rate
+const KEYFRAME_INTERVAL_TICKS: u64 = 6;
+// ticks the phase percentiles are computed over
+const TIMING_WINDOW_TICKS: usize = 300;
+// simulated time lost beyond this many steps in a tick is dropped rather than caught up
+const MAX_SUBSTEPS: u32 = 4;
+const MAX_RELAY_PAYLOAD: usize = 512;
+// entity payloads go to every client that knows the entity, they're for a few flags or counters
+const MAX_ENTITY_PAYLOAD: usize = 64;
+// soft body outlines are refreshed this often, they cost more to send than the rest of an entity
+const OUTLINE_INTERVAL_TICKS: u64 = 3;
+// a body crosses less than a chunk between two checks, so the chunks around it are always loaded in time
+const CHUNK_INTERVAL_TICKS: u64 = 15;
+// memory only grows with spawns and clients, it doesn't need watching every tick
+const USAGE_INTERVAL_TICKS: u64 = 60;
+const MAX_ROPE_LENGTH: f32 = 50.0;
+const PLAYER_HEALTH: f32 = 100.0;
+// how close two players have to get for a near miss
+const PLAYER_PROXIMITY_MARGIN: f32 = 2.0;
+const PLAYER_SPEED: f32 = 10.0;
+// where players appear when the level has no spawn point
+const DEFAULT_SPAWN: (f32, f32) = (0.0, 10.0);
+const RESPAWN_DELAY_TICKS: u64 = 180;
+const RESPAWN_INVULNERABLE_TICKS: u64 = 120;
+const GRAPPLE_RANGE: f32 = 30.0;
+const HITSCAN_RANGE: f32 = 80.0;
+const HITSCAN_DAMAGE: f32 = 25.0;
+const MELEE_REACH: f32 = 4.0;
+// how far back shots are checked, 200ms at 60 ticks per second, laggier shooters have to lead their target
+const MAX_REWIND_TICKS: usize = 12;
+// impacts softer than this make no sound, and a material pair is heard at most this often
+const MIN_IMPACT_FX_IMPULSE: f32 = 5.0;
+const IMPACT_FX_INTERVAL_TICKS: u64 = 6;
+// hits on a client's entities softer than this don't rumble its gamepad
+const MIN_FEEDBACK_IMPULSE: f32 = 20.0;
+// parked ball bodies kept for reuse, and how many a room starts with
+const MAX_POOLED_BODIES: usize = 256;
+const PREWARMED_BODIES: usize = 32;
+const BALL_DAMPING: Damping = Damping { linear: 0.5, angular: 1.0 };
+
+fn player_limits() -> EntityLimits {
+    EntityLimits {
+        max_impulse: 20.0,
+        max_speed: 40.0,
+        max_dash_distance: 8.0,
+        allowed: vec![CommandKind::Impulse, CommandKind::Move, CommandKind::Jump, CommandKind::Dash, CommandKind::Grapple, CommandKind::Reel, CommandKind::Release, CommandKind::Drive, CommandKind::FireHitscan, CommandKind::Swing],
+    }
+}
+
+pub fn create_ground (world: &mut World<f32>, margin: f32) -> Vec<ColliderHandle> {
+    let material = Material::new(1.0, 0.0);
+
+    let ground_radius = GROUND_RADIUS;
+    let ground_shape = ShapeHandle::new(Cuboid::new(Vector2::new(
+        ground_radius - margin,
+        ground_radius - margin,
+    )));
+
+    let positions = [
+        Isometry2::new(-Vector2::y() * ground_radius, na::zero()),
+        Isometry2::new(Vector2::y() * ground_radius * 2.0, na::zero()),
+        Isometry2::new(Vector2::new(ground_radius * 1.5, ground_radius * 0.5), na::zero()),
+        Isometry2::new(Vector2::new(-ground_radius * 1.5, ground_radius * 0.5), na::zero()),
+    ];
+
+    positions.iter().map(|position| {
+        world.add_collider(
+            margin,
+            ground_shape.clone(),
+            BodyHandle::ground(),
+            *position,
+            material.clone(),
+        )
+    }).collect()
+}
+
+fn create_ball (world: &mut World<f32>, margin: f32, position: Vector2<f32>, lock_rotation: bool) -> PhysicsBody {
+    let material = Material::new(1.0, 0.0);
+    let rad = BALL_RADIUS;
+
+    // let geom = ShapeHandle::new(Ball::new(rad - margin));
+    let geom = ShapeHandle::new(Cuboid::new(Vector2::new(rad - margin, rad - margin)));
+    // nphysics treats a zero angular inertia as infinite, the body stays upright whatever hits it
+    let angular_inertia = if lock_rotation {
+        0.0
+    } else {
+        let unit = geom.inertia(1.0);
+        unit.angular / unit.linear
+    };
+    let inertia = Inertia2::new(1.0, angular_inertia);
+    let center_of_mass = geom.center_of_mass();
+
+    let pos = Isometry2::new(position, 0.0);
+    let body = world.add_rigid_body(pos, inertia, center_of_mass);
+
+    let collider = world.add_collider(
+        margin,
+        geom,
+        body,
+        Isometry2::identity(),
+        material,
+    );
+
+    PhysicsBody { collider, body }
+}
+
+fn apply_impulse (world: &mut World<f32>, physics_body: PhysicsBody, impulse: Vector2<f32>) {
+    if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+        let mass = rigid_body.local_inertia().linear;
+        if mass > 0.0 {
+            let velocity = rigid_body.velocity().linear + impulse / mass;
+            rigid_body.set_linear_velocity(velocity);
+            rigid_body.activate();
+        }
+    }
+}
+
+// how clients see a new entity and which collision layer, by name, it goes on
+pub struct SpawnDescriptor {
+    pub kind: EntityKind,
+    pub layer: String,
+    pub metadata: Metadata,
+    // margin for near-miss events, `None` leaves the entity out of proximity detection
+    pub proximity: Option<f32>,
+    pub gravity_scale: f32,
+    // `None` keeps the velocity until something else changes it
+    pub damping: Option<Damping>,
+    // balls always stayed upright, `false` lets contacts spin the body
+    pub lock_rotation: bool,
+    // makes the body kinematic and moved by the controller rather than the solver
+    pub character: Option<CharacterController>,
+    pub team: Option<u8>,
+    // ticks before the entity despawns on its own, `None` keeps it until something removes it
+    pub ttl: Option<u64>,
+    // continuous collision, costs a sweep per step so leave it to fast movers
+    pub continuous: bool,
+    // level material id, 0 for none
+    pub surface: u8,
+}
+
+impl SpawnDescriptor {
+    pub fn new(kind: EntityKind, layer: &str) -> SpawnDescriptor {
+        SpawnDescriptor {
+            kind,
+            layer: layer.to_string(),
+            metadata: Metadata::default(),
+            proximity: None,
+            gravity_scale: 1.0,
+            damping: None,
+            lock_rotation: true,
+            character: None,
+            team: None,
+            ttl: None,
+            continuous: false,
+            surface: 0,
+        }
+    }
+}
+
+// a player on its way to another room, handed over by the host between ticks
+pub struct Transfer {
+    pub room: String,
+    pub spawn_point: String,
+    pub session: Session,
+    pub velocity: Vector2<f32>,
+}
+
+pub struct Room {
+    pub name: String,
+    // the tenant owning the room, every log of the room is within its span
+    tenant: Option<String>,
+    span: Span,
+    config: Arc<Config>,
+    world: World<f32>,
+    // entities without `EntityLimits` are server-controlled and accept no command
+    entities: hecs::World,
+    net_ids: NetIds,
+    // pairs of entities currently within their proximity margins
+    near: HashSet<(NetId, NetId)>,
+    sessions: Sessions,
+    events: Vec<Event>,
+    handlers: Vec<Box<dyn GameplayHandler>>,
+    spawners: Vec<Spawner>,
+    despawns: DespawnSchedule,
+    spawn_points: Vec<SpawnPoint>,
+    respawns: RespawnQueue,
+    timeline: Timeline,
+    match_state: MatchState,
+    scores: HashMap<ClientId, u32>,
+    timers: Timers,
+    // rebuilt every tick like the snapshot
+    timer_states: Arc<Vec<TimerState>>,
+    results: Option<ResultsStore>,
+    transfers: Vec<Transfer>,
+    chunks: Chunks,
+    // seed and params of the generated arena, if the level asks for one
+    arena: Option<(u64, ArenaParams)>,
+    materials: Vec<MaterialEntry>,
+    // every input of the room, in deterministic mode only
+    recorder: Option<InputLog>,
+    random: Random,
+    // the commands applied since the last tick, in lockstep mode only
+    lockstep: Option<Vec<(ClientId, Command)>>,
+    // something else than client commands changed the simulation this tick, admins, gameplay,
+    // spawns or despawns, lockstep clients get a keyframe instead of the inputs
+    resync: bool,
+    ended: bool,
+    commands: CommandQueue,
+    // refilled every tick, clients only hold it until the end of the tick flush
+    snapshot: Arc<Vec<EntityState>>,
+    changes: ChangeTracker,
+    bodies: BodyPool,
+    spatial: SpatialCache,
+    history: History,
+    impacts: ImpactLimiter,
+    tick: u64,
+    started_at: Instant,
+    timestep: f32,
+    last_tick_at: Option<Instant>,
+    // wall-clock time not simulated yet, in seconds, each step takes a timestep off it so the ticks
+    // the scheduler runs back to back after a hitch don't step the same time again
+    behind: f32,
+    pub last_tick_duration: Duration,
+    timings: PhaseTimings,
+    // soft limits over at the last usage check, only the ones newly crossed are warned about
+    exceeded: Vec<&'static str>,
+    watches: EntityWatches,
+}
+
+impl Room {
+    pub fn new(name: &str, config: Arc<Config>, level: Arc<Level>, results: Option<ResultsStore>) -> Room {
+        let mut world = World::new();
+        world.set_timestep(1.0 / config.tick_rate as f32);
+        world.set_gravity(config.gravity);
+        world.set_prediction(config.world.prediction);
+        {
+            let parameters = world.integration_parameters_mut();
+            parameters.max_velocity_iterations = config.world.velocity_iterations;
+            parameters.max_position_iterations = config.world.position_iterations;
+        }
+        let timestep = world.timestep();
+        let margin = config.world.collider_margin;
+        let tenant = tenant::owner(&config.tenants, name).map(|tenant| tenant.name.clone());
+        let span = match tenant {
+            Some(ref tenant) => info_span!("tenant", tenant = %tenant),
+            None => Span::none(),
+        };
+        let _entered = span.enter();
+        info!(target: "physics", room = name, "room created");
+
+        let (level, arena) = match level.arena {
+            Some(ref definition) => {
+                let seed = definition.seed.unwrap_or_else(rand::random);
+                info!(target: "physics", room = name, seed, "room plays on a generated arena");
+                (Arc::new(levelgen::generate(&level, seed, &definition.params)), Some((seed, definition.params)))
+            },
+            None => (level, None),
+        };
+        let loot_seed = rand::random();
+        let recorder = if config.deterministic {
+            Some(InputLog::new(name, arena.map(|(seed, _)| seed), loot_seed, &config.source, &level.source))
+        } else {
+            None
+        };
+        let lockstep = if config.lockstep { Some(vec![]) } else { None };
+        let sessions = Sessions::new(RECONNECT_GRACE_TICKS, config.command_limit, config.relay_limit);
+
+        let mut room = Room {
+            name: name.to_string(),
+            tenant,
+            span: span.clone(),
+            config,
+            world,
+            entities: hecs::World::new(),
+            net_ids: NetIds::default(),
+            near: HashSet::new(),
+            sessions,
+            events: vec![],
+            handlers: vec![Box::new(BouncePad), Box::new(KillZone), Box::new(Portal)],
+            spawners: level.spawners.iter().cloned().map(Spawner::new).collect(),
+            despawns: DespawnSchedule::default(),
+            spawn_points: level.spawn_points.iter().map(SpawnPoint::new).collect(),
+            respawns: RespawnQueue::default(),
+            timeline: Timeline::new(&level.timeline),
+            match_state: MatchState::new(level.rules.clone()),
+            scores: HashMap::new(),
+            timers: Timers::default(),
+            timer_states: Arc::new(vec![]),
+            results,
+            transfers: vec![],
+            chunks: Chunks::new(&level, margin),
+            arena,
+            materials: level.materials.iter().map(MaterialDefinition::entry).collect(),
+            recorder,
+            random: Random::new(name, loot_seed),
+            lockstep,
+            resync: false,
+            ended: false,
+            commands: CommandQueue::default(),
+            snapshot: Arc::new(vec![]),
+            changes: ChangeTracker::default(),
+            bodies: BodyPool::new(MAX_POOLED_BODIES),
+            spatial: SpatialCache::default(),
+            history: History::new(MAX_REWIND_TICKS),
+            impacts: ImpactLimiter::new(IMPACT_FX_INTERVAL_TICKS),
+            tick: 0,
+            started_at: Instant::now(),
+            timestep,
+            last_tick_at: None,
+            behind: 0.0,
+            last_tick_duration: Duration::default(),
+            timings: PhaseTimings::new(TIMING_WINDOW_TICKS),
+            exceeded: vec![],
+            watches: EntityWatches::default(),
+        };
+
+        let wall = Shape::Cuboid { half_extents: Vector2::repeat(GROUND_RADIUS) };
+        for collider in create_ground(&mut room.world, room.config.world.collider_margin) {
+            room.spawn(PhysicsBody { collider, body: BodyHandle::ground() }, wall.clone(), SpawnDescriptor::new(EntityKind::Wall, "wall"));
+        }
+        for definition in level.polylines.iter() {
+            room.spawn_terrain(&definition.points, &definition.layer, definition.surface);
+        }
+        for definition in level.heightfields.iter() {
+            room.spawn_terrain(&definition.points(), &definition.layer, definition.surface);
+        }
+        for definition in level.chains.iter() {
+            room.spawn_chain(definition);
+        }
+
+        // upright like the spawns that take them
+        for _ in 0..PREWARMED_BODIES {
+            let physics_body = create_ball(&mut room.world, margin, Vector2::zeros(), true);
+            room.bodies.put(&mut room.world, physics_body);
+        }
+
+        let mut last = None;
+        for i in 0..2 {
+            let x = (i as f32 -1.0) * 4.0;
+            last = Some(room.spawn_ball(Vector2::new(x, 3.0), SpawnDescriptor { damping: Some(BALL_DAMPING), ..SpawnDescriptor::new(EntityKind::Prop, "prop") }));
+        }
+
+        let physics_body = *room.entities.get::<PhysicsBody>(last.unwrap()).unwrap();
+        let body = room.world.rigid_body_mut(physics_body.body).unwrap();
+        body.set_linear_velocity(Vector2::new(30.0, 30.0));
+
+        room
+    }
+
+    pub fn has_client(&self, client: ClientId) -> bool {
+        self.sessions.contains(client)
+    }
+
+    pub fn has_session(&self, token: SessionToken) -> bool {
+        self.sessions.find(token).is_some()
+    }
+
+    pub fn has_ended(&self) -> bool {
+        self.ended
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_ref().map(String::as_str)
+    }
+
+    pub fn summary(&self) -> RoomSummary {
+        RoomSummary {
+            name: self.name.clone(),
+            players: self.sessions.players(),
+            entities: self.snapshot.len(),
+            phase: self.match_state.phase,
+        }
+    }
+
+    // checked before anything but a player spawns, evicts an entity when the cap is reached and the
+    // policy allows it, false when the spawn has to be dropped
+    fn make_room(&mut self) -> bool {
+        let cap = match self.config.entity_cap {
+            Some(cap) if self.net_ids.len() >= cap.max => cap,
+            _ => return true,
+        };
+
+        match entity_cap::victim(cap.eviction, &self.world, &self.entities) {
+            Some(victim) => {
+                self.despawn(victim, DespawnReason::Removed);
+                true
+            },
+            None => {
+                warn!(target: "physics", room = %self.name, tick = self.tick, max = cap.max, eviction = ?cap.eviction, "entity cap reached, spawn dropped");
+                false
+            },
+        }
+    }
+
+    // lockstep clients only step the commands, anything else has to reach them as a keyframe
+    fn resync(&mut self) {
+        self.resync |= self.lockstep.is_some();
+    }
+
+    // every replicated entity goes through here so clients hear about it
+    fn spawn(&mut self, physics_body: PhysicsBody, shape: Shape, descriptor: SpawnDescriptor) -> Entity {
+        self.resync();
+        let groups = self.layer_groups(&descriptor.layer);
+        self.world.collision_world_mut().set_collision_groups(physics_body.collider, groups);
+
+        let entity = self.entities.spawn((physics_body, descriptor.kind, shape, descriptor.metadata, groups));
+        let id = self.net_ids.allocate(entity, physics_body.collider);
+        let _ = self.entities.insert_one(entity, Replicated { id });
+        if descriptor.team.is_some() {
+            self.set_team(entity, descriptor.team);
+        }
+        if let Some(ttl) = descriptor.ttl {
+            self.despawns.schedule(self.tick + ttl, id);
+        }
+        if let Some(margin) = descriptor.proximity {
+            let _ = self.entities.insert_one(entity, Proximity { margin });
+        }
+        self.set_gravity_scale(entity, descriptor.gravity_scale);
+        if let Some(damping) = descriptor.damping {
+            let _ = self.entities.insert_one(entity, damping);
+        }
+        if let Some(character) = descriptor.character {
+            if let Some(rigid_body) = self.world.rigid_body_mut(physics_body.body) {
+                rigid_body.set_status(BodyStatus::Kinematic);
+            }
+            let _ = self.entities.insert_one(entity, character);
+        }
+        if let Some(spawned) = systems::spawned(&self.world, &self.entities, entity) {
+            self.events.push(spawned);
+        }
+        if descriptor.continuous {
+            let _ = self.entities.insert_one(entity, Continuous);
+            self.events.push(Event::ContinuousCollision { entity: id });
+        }
+        if descriptor.surface != 0 {
+            let _ = self.entities.insert_one(entity, Surface(descriptor.surface));
+        }
+        entity
+    }
+
+    // damage goes through invulnerability and, with friendly fire off, teams, the hit is reported either way
+    fn fire_hitscan(&mut self, entity: Entity, shooter: NetId, physics_body: PhysicsBody, direction: Vector2<f32>, client_tick: u64) {
+        let origin = match self.world.collider(physics_body.collider) {
+            Some(collider) => Point2::from(collider.position().translation.vector),
+            None => return,
+        };
+        let net_ids = &self.net_ids;
+        let hit = hitscan::fire(&self.world, &self.history, client_tick, shooter, origin, direction, HITSCAN_RANGE, |id| net_ids.entity(id).is_some());
+        let (hit, target) = match hit.and_then(|hit| Some((hit, self.net_ids.entity(hit.target)?))) {
+            Some(hit) => hit,
+            None => return,
+        };
+
+        let team = |entity| self.entities.get::<Team>(entity).ok().map(|team| *team);
+        let friendly = !self.config.friendly_fire && team(entity).is_some() && team(entity) == team(target);
+        let mut damage = 0.0;
+        if !friendly && self.entities.get::<Invulnerable>(target).is_err() {
+            if let Ok(mut health) = self.entities.get_mut::<Health>(target) {
+                health.0 -= HITSCAN_DAMAGE;
+                damage = HITSCAN_DAMAGE;
+            }
+        }
+        self.events.push(Event::Hit { shooter, target: hit.target, point: hit.point, damage });
+    }
+
+    // only sent when it changes, kept on the session so a resumed client gets the last one back
+    pub fn set_camera(&mut self, client: ClientId, hint: CameraHint) {
+        if let Some(session) = self.sessions.get_mut(client) {
+            if session.camera != Some(hint) {
+                session.camera = Some(hint);
+                session.send(ServerMessage::Camera(hint));
+            }
+        }
+    }
+
+    pub fn add_handler(&mut self, handler: Box<dyn GameplayHandler>) {
+        self.handlers.push(handler);
+    }
+
+    // handlers only see the room, what they want changed is applied once they are all done
+    fn run_handlers(&mut self) {
+        self.random.at(self.tick);
+        let context = Context::new(&self.world, &self.entities, &self.net_ids, &self.spatial, &self.random);
+        for handler in self.handlers.iter_mut() {
+            for event in self.events.iter() {
+                handler.on_event(event, &context, &mut self.commands);
+            }
+        }
+
+        let commands: Vec<GameplayCommand> = self.commands.drain().collect();
+        if !commands.is_empty() {
+            self.resync();
+        }
+        for command in commands {
+            match command {
+                GameplayCommand::Spawn { position, descriptor } => {
+                    if self.make_room() {
+                        self.spawn_ball(position, descriptor);
+                    }
+                },
+                GameplayCommand::Despawn(entity) => {
+                    if let Some(entity) = self.net_ids.entity(entity) {
+                        self.despawn(entity, DespawnReason::Removed);
+                    }
+                },
+                GameplayCommand::Impulse { entity, impulse } => {
+                    let physics_body = self.net_ids.entity(entity)
+                        .and_then(|entity| self.entities.get::<PhysicsBody>(entity).ok().map(|physics_body| *physics_body));
+                    if let Some(physics_body) = physics_body {
+                        apply_impulse(&mut self.world, physics_body, impulse);
+                    }
+                },
+                GameplayCommand::Knockback { entity, impulse } => {
+                    if let Some(entity) = self.net_ids.entity(entity) {
+                        self.knockback(entity, impulse);
+                    }
+                },
+                GameplayCommand::Transfer { entity, room, spawn_point } => {
+                    if let Some(entity) = self.net_ids.entity(entity) {
+                        self.transfer(entity, room, spawn_point);
+                    }
+                },
+                GameplayCommand::SetPayload { entity, payload } => {
+                    if let Some(target) = self.net_ids.entity(entity) {
+                        self.set_payload(target, entity, payload);
+                    }
+                },
+                GameplayCommand::Explode { position, radius, impulse, damage, cover } => self.explode(position, radius, impulse, damage, cover),
+                GameplayCommand::SetCamera { client, hint } => self.set_camera(client, hint),
+            }
+        }
+    }
+
+    fn set_payload(&mut self, entity: Entity, id: NetId, payload: Vec<u8>) {
+        if payload.len() > MAX_ENTITY_PAYLOAD {
+            warn!(target: "physics", room = %self.name, tick = self.tick, entity = id, len = payload.len(), "entity payload too large");
+            return;
+        }
+        if self.entities.get::<Payload>(entity).map_or(false, |current| current.0 == payload) {
+            return;
+        }
+
+        let _ = self.entities.insert_one(entity, Payload(payload.clone()));
+        self.events.push(Event::PayloadChanged { entity: id, payload });
+    }
+
+    // replaces the motion in progress, if any
+    fn start_motion(&mut self, entity: Entity, motion: ScriptedMotion) {
+        let id = match self.entities.get::<Replicated>(entity) {
+            Ok(replicated) => replicated.id,
+            Err(_) => return,
+        };
+
+        if let Ok(previous) = self.entities.remove_one::<ScriptedMotion>(entity) {
+            self.events.push(Event::MotionEnded { entity: id, motion: previous.kind });
+        }
+        self.events.push(Event::MotionStarted { entity: id, motion: motion.kind });
+        let _ = self.entities.insert_one(entity, motion);
+    }
+
+    fn knockback(&mut self, entity: Entity, impulse: Vector2<f32>) {
+        let mass = self.entities.get::<PhysicsBody>(entity).ok()
+            .and_then(|physics_body| self.world.rigid_body(physics_body.body))
+            .map(|rigid_body| rigid_body.local_inertia().linear);
+
+        if let Some(mass) = mass.filter(|mass| *mass > 0.0) {
+            self.start_motion(entity, ScriptedMotion::knockback(impulse, mass));
+        }
+    }
+
+    // pushes what's within `radius` away from `center` and hurts what has health, both fading with the
+    // distance, entities static geometry hides from the center only get the `cover` share of it
+    fn explode(&mut self, center: Vector2<f32>, radius: f32, impulse: f32, damage: f32, cover: Option<f32>) {
+        if !(radius.is_finite() && radius > 0.0) || cover.map_or(false, |cover| !(0.0..=1.0).contains(&cover)) {
+            warn!(target: "physics", room = %self.name, tick = self.tick, %radius, cover = ?cover, "can't set off this explosion");
+            return;
+        }
+
+        let origin = Point2::from(center);
+        let reach = Vector2::repeat(radius);
+        let candidates = self.spatial.entities_in(&self.world, &self.net_ids, origin - reach, origin + reach);
+
+        let mut blasted = vec![];
+        for id in candidates.iter() {
+            let entity = match self.net_ids.entity(*id) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            let position = self.entities.get::<PhysicsBody>(entity).ok()
+                .and_then(|physics_body| self.world.collider(physics_body.collider))
+                .map(|collider| collider.position().translation.vector);
+            let offset = match position {
+                Some(position) => position - center,
+                None => continue,
+            };
+
+            let distance = offset.norm();
+            let mut strength = 1.0 - distance / radius;
+            if let Some(cover) = cover {
+                if strength > 0.0 && view::occluded(&self.world, center, center + offset) {
+                    strength *= cover;
+                }
+            }
+            if strength > 0.0 {
+                blasted.push((entity, offset, distance, strength));
+            }
+        }
+
+        for (entity, offset, distance, strength) in blasted {
+            // nothing tells which way to go right at the center, up it is
+            let direction = if distance > 0.0 { offset / distance } else { Vector2::y() };
+            self.knockback(entity, direction * impulse * strength);
+            if self.entities.get::<Invulnerable>(entity).is_err() {
+                if let Ok(mut health) = self.entities.get_mut::<Health>(entity) {
+                    health.0 -= damage * strength;
+                }
+            }
+        }
+    }
+
+    // upright balls reuse a parked body when there is one, rotating ones need their own inertia and
+    // character bodies their own status
+    fn spawn_ball(&mut self, position: Vector2<f32>, descriptor: SpawnDescriptor) -> Entity {
+        let pooled = descriptor.lock_rotation && descriptor.character.is_none();
+        let taken = if pooled { self.bodies.take(&mut self.world, position) } else { None };
+        let physics_body = match taken {
+            Some(physics_body) => physics_body,
+            None => create_ball(&mut self.world, self.config.world.collider_margin, position, descriptor.lock_rotation),
+        };
+        let shape = Shape::Cuboid { half_extents: Vector2::repeat(BALL_RADIUS) };
+        let entity = self.spawn(physics_body, shape, descriptor);
+        if pooled {
+            let _ = self.entities.insert_one(entity, Pooled);
+        }
+        entity
+    }
+
+    // every link is an entity of its own, so clients draw and hear about each of them
+    fn spawn_terrain(&mut self, points: &[[f32; 2]], layer: &str, surface: u8) -> Entity {
+        let (physics_body, points) = terrain::build(&mut self.world, self.config.world.collider_margin, points);
+        self.spawn(physics_body, Shape::Polyline { points }, SpawnDescriptor { surface, ..SpawnDescriptor::new(EntityKind::Wall, layer) })
+    }
+
+    fn spawn_chain(&mut self, definition: &ChainDefinition) -> Vec<Entity> {
+        let start = Point2::new(definition.start[0], definition.start[1]);
+        let end = Point2::new(definition.end[0], definition.end[1]);
+        let (half_extents, links) = chain::build(&mut self.world, self.config.world.collider_margin, start, end, definition.links, definition.thickness);
+
+        links.into_iter().map(|(physics_body, joints)| {
+            let entity = self.spawn(physics_body, Shape::Cuboid { half_extents }, SpawnDescriptor::new(EntityKind::Prop, &definition.layer));
+            let _ = self.entities.insert_one(entity, joints);
+            entity
+        }).collect()
+    }
+
+    fn run_timeline(&mut self) {
+        for action in self.timeline.due(self.tick) {
+            match action {
+                TimelineAction::Spawn { position, template } => {
+                    if self.make_room() {
+                        self.spawn_ball(Vector2::new(position[0], position[1]), template.descriptor());
+                    }
+                },
+                TimelineAction::SetGravity { gravity } => {
+                    self.world.set_gravity(Vector2::new(gravity[0], gravity[1]));
+                },
+                TimelineAction::End => {
+                    self.end();
+                    return;
+                },
+            }
+        }
+    }
+
+    fn run_spawners(&mut self) {
+        for i in 0..self.spawners.len() {
+            if !self.spawners[i].tick(&self.net_ids) || !self.make_room() {
+                continue;
+            }
+
+            let (position, descriptor) = (self.spawners[i].position, self.spawners[i].descriptor());
+            let entity = self.spawn_ball(position, descriptor);
+            if let Ok(replicated) = self.entities.get::<Replicated>(entity).map(|replicated| *replicated) {
+                self.spawners[i].spawned(replicated.id);
+            }
+        }
+    }
+
+    fn spawn_vehicle(&mut self, position: Vector2<f32>, descriptor: SpawnDescriptor) -> Entity {
+        let groups = self.layer_groups(&descriptor.layer);
+        let (physics_body, vehicle) = vehicle::build(&mut self.world, self.config.world.collider_margin, position, groups);
+        let (half_width, half_height) = vehicle::CHASSIS_HALF_EXTENTS;
+        let shape = Shape::Cuboid { half_extents: Vector2::new(half_width, half_height) };
+
+        let entity = self.spawn(physics_body, shape, descriptor);
+        let _ = self.entities.insert_one(entity, vehicle);
+        entity
+    }
+
+    fn spawn_ragdoll(&mut self, position: Vector2<f32>, ragdoll: &RagdollDescriptor, descriptor: SpawnDescriptor) -> Entity {
+        let physics_body = ragdoll::create_part(&mut self.world, self.config.world.collider_margin, Isometry2::new(position, 0.0), ragdoll.torso);
+        let shape = Shape::Cuboid { half_extents: ragdoll.torso };
+        let entity = self.spawn(physics_body, shape, descriptor);
+
+        let groups = self.layer_groups(&ragdoll.limb_layer);
+        let limbs = ragdoll::build(&mut self.world, self.config.world.collider_margin, &physics_body, ragdoll, groups);
+        let _ = self.entities.insert_one(entity, limbs);
+        entity
+    }
+
+    fn spawn_soft_body(&mut self, position: Vector2<f32>, radius: f32, descriptor: SpawnDescriptor) -> Entity {
+        let groups = self.layer_groups(&descriptor.layer);
+        let (physics_body, soft_body) = soft_body::build(&mut self.world, self.config.world.collider_margin, position, radius, groups);
+
+        let entity = self.spawn(physics_body, Shape::Ball { radius }, descriptor);
+        let _ = self.entities.insert_one(entity, soft_body);
+        entity
+    }
+
+    // the body becomes the torso of a humanoid ragdoll, the owner keeps watching it but can't command it anymore
+    fn ragdoll(&mut self, entity: Entity) {
+        let physics_body = match self.entities.get::<PhysicsBody>(entity) {
+            Ok(physics_body) if self.entities.get::<Ragdoll>(entity).is_err() => *physics_body,
+            _ => return,
+        };
+        let half_extents = match self.entities.get::<Shape>(entity).map(|shape| shape.clone()) {
+            Ok(Shape::Cuboid { half_extents }) => half_extents,
+            Ok(Shape::Ball { radius }) => Vector2::repeat(radius),
+            Ok(Shape::Polyline { .. }) | Err(_) => return,
+        };
+
+        if let Ok(motion) = self.entities.remove_one::<ScriptedMotion>(entity) {
+            let id = self.entities.get::<Replicated>(entity).map(|replicated| replicated.id);
+            if let Ok(id) = id {
+                self.events.push(Event::MotionEnded { entity: id, motion: motion.kind });
+            }
+        }
+        let _ = self.entities.remove_one::<Grapple>(entity);
+        let _ = self.entities.remove_one::<EntityLimits>(entity);
+        if self.entities.remove_one::<CharacterController>(entity).is_ok() {
+            if let Some(rigid_body) = self.world.rigid_body_mut(physics_body.body) {
+                rigid_body.set_status(BodyStatus::Dynamic);
+            }
+        }
+        ragdoll::unlock_rotation(&mut self.world, physics_body.body, half_extents);
+
+        let descriptor = RagdollDescriptor::humanoid(half_extents);
+        let groups = self.layer_groups(&descriptor.limb_layer);
+        let limbs = ragdoll::build(&mut self.world, self.config.world.collider_margin, &physics_body, &descriptor, groups);
+        let _ = self.entities.insert_one(entity, limbs);
+    }
+
+    // an unknown layer is a config mistake, the entity still spawns but collides with everything
+    fn layer_groups(&self, layer: &str) -> CollisionGroups {
+        self.config.layers.groups(layer).unwrap_or_else(|| {
+            warn!(target: "physics", room = %self.name, tick = self.tick, layer, "unknown collision layer");
+            CollisionGroups::new()
+        })
+    }
+
+    fn despawn(&mut self, entity: Entity, reason: DespawnReason) {
+        self.resync();
+        // a player lost to the game comes back, one removed on purpose doesn't
+        if reason == DespawnReason::Destroyed || reason == DespawnReason::OutOfBounds {
+            let is_player = self.entities.get::<EntityKind>(entity).map_or(false, |kind| *kind == EntityKind::Player);
+            if let Some(client) = self.owner(entity).filter(|_| is_player) {
+                self.respawns.schedule(self.tick + RESPAWN_DELAY_TICKS, client);
+            }
+        }
+        if let Ok(removed) = self.entities.remove_one::<Joints>(entity) {
+            for joint in removed.0.iter() {
+                self.world.remove_constraint(*joint);
+            }
+            for (_, joints) in self.entities.query::<&mut Joints>().iter() {
+                joints.0.retain(|joint| !removed.0.contains(joint));
+            }
+        }
+        if let Ok((wheels, joints)) = self.entities.get::<Vehicle>(entity).map(|vehicle| vehicle.parts()) {
+            for joint in joints {
+                self.world.remove_constraint(joint);
+            }
+            self.world.remove_bodies(&wheels);
+        }
+        if let Ok((limbs, joints)) = self.entities.get::<Ragdoll>(entity).map(|ragdoll| ragdoll.parts()) {
+            for joint in joints {
+                self.world.remove_constraint(joint);
+            }
+            self.world.remove_bodies(&limbs);
+        }
+        if let Ok(surface) = self.entities.get::<SoftBody>(entity).map(|soft_body| soft_body.surface.clone()) {
+            self.world.remove_bodies(&surface);
+        }
+        // turned into a ragdoll, a vehicle or a soft body it has more to it than its own body
+        let poolable = self.entities.get::<Pooled>(entity).is_ok()
+            && self.entities.get::<Ragdoll>(entity).is_err()
+            && self.entities.get::<Vehicle>(entity).is_err()
+            && self.entities.get::<SoftBody>(entity).is_err();
+        if let Ok(physics_body) = self.entities.get::<PhysicsBody>(entity).map(|physics_body| *physics_body) {
+            if physics_body.body.is_ground() {
+                self.world.remove_colliders(&[physics_body.collider]);
+            } else if !(poolable && self.bodies.put(&mut self.world, physics_body)) {
+                self.world.remove_bodies(&[physics_body.body]);
+            }
+        }
+        if let Ok(id) = self.entities.get::<Replicated>(entity).map(|replicated| replicated.id) {
+            self.net_ids.release(id);
+            self.changes.forget(id);
+            self.near.retain(|&(a, b)| a != id && b != id);
+            self.events.push(Event::Despawned { entity: id, reason });
+        }
+
+        let _ = self.entities.despawn(entity);
+    }
+
+    // the team of the entity, if any, is kept on top of the new groups
+    fn set_collision_groups(&mut self, entity: Entity, groups: CollisionGroups) {
+        let team = self.entities.get::<Team>(entity).ok().map(|team| team.0);
+        let is_projectile = self.entities.get::<EntityKind>(entity).map_or(false, |kind| *kind == EntityKind::Projectile);
+        let groups = collision::with_team(groups, team, is_projectile && !self.config.friendly_fire);
+
+        if let Ok(physics_body) = self.entities.get::<PhysicsBody>(entity).map(|physics_body| *physics_body) {
+            self.world.collision_world_mut().set_collision_groups(physics_body.collider, groups);
+            if let Ok(vehicle) = self.entities.get::<Vehicle>(entity) {
+                for wheel in vehicle.wheels.iter() {
+                    self.world.collision_world_mut().set_collision_groups(wheel.collider, groups);
+                }
+            }
+            let _ = self.entities.insert_one(entity, groups);
+        }
+    }
+
+    fn set_team(&mut self, entity: Entity, team: Option<u8>) {
+        match team {
+            Some(team) => {
+                let _ = self.entities.insert_one(entity, Team(team));
+            },
+            None => {
+                let _ = self.entities.remove_one::<Team>(entity);
+            },
+        }
+
+        if let Ok(groups) = self.entities.get::<CollisionGroups>(entity).map(|groups| *groups) {
+            self.set_collision_groups(entity, groups);
+        }
+    }
+
+    // the default scale needs no compensation, the component is left out
+    fn set_gravity_scale(&mut self, entity: Entity, scale: f32) {
+        if scale == 1.0 {
+            let _ = self.entities.remove_one::<GravityScale>(entity);
+        } else {
+            let _ = self.entities.insert_one(entity, GravityScale(scale));
+        }
+    }
+
+    fn owner(&self, entity: Entity) -> Option<ClientId> {
+        self.entities.get::<Owner>(entity).ok().map(|owner| owner.0)
+    }
+
+    // an owned entity takes commands within the player limits, the server drives the others
+    // walls and terrain never get an owner, they're on the ground body and can't take commands
+    fn set_owner(&mut self, entity: Entity, owner: Option<ClientId>) {
+        self.resync();
+        match owner {
+            Some(_) if self.is_ground(entity) => {},
+            Some(owner) => {
+                let _ = self.entities.insert(entity, (Owner(owner), player_limits()));
+            },
+            None => {
+                let _ = self.entities.remove_one::<Owner>(entity);
+                let _ = self.entities.remove_one::<EntityLimits>(entity);
+            },
+        }
+    }
+
+    fn is_ground(&self, entity: Entity) -> bool {
+        self.entities.get::<PhysicsBody>(entity).map_or(false, |physics_body| physics_body.body.is_ground())
+    }
+
+    // a fresh keyframe for a single client, outside of the tick broadcast
+    fn keyframe(&self) -> Arc<Vec<EntityState>> {
+        let mut states = Vec::with_capacity(self.net_ids.len());
+        systems::replicate(&self.world, &self.entities, &mut states);
+        Arc::new(states)
+    }
+
+    // players still in the room and anyone who scored, even if they left since
+    fn record_results(&self, tick: u64, replay: Option<String>) {
+        let results = match self.results {
+            Some(ref results) => results,
+            None => return,
+        };
+
+        let mut players: Vec<PlayerResult> = self.sessions.iter()
+            .filter(|session| session.role == Role::Player)
+            .map(|session| PlayerResult {
+                client: session.client,
+                player: session.player.clone(),
+                score: self.scores.get(&session.client).cloned().unwrap_or(0),
+            })
+            .collect();
+        for (client, score) in self.scores.iter() {
+            if !players.iter().any(|player| player.client == *client) {
+                players.push(PlayerResult { client: *client, player: String::new(), score: *score });
+            }
+        }
+        players.sort_by(|a, b| b.score.cmp(&a.score));
+
+        let result = MatchResult {
+            room: self.name.clone(),
+            finished_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0),
+            duration_ticks: self.match_state.played(tick),
+            replay,
+            players,
+        };
+        match results.record(&result) {
+            Ok(id) => info!(target: "physics", room = %self.name, tick = self.tick, id, "match results recorded"),
+            Err(error) => error!(target: "physics", room = %self.name, tick = self.tick, %error, "can't record match results"),
+        }
+    }
+
+    // the log is written once, when the match finishes or the room ends before that
+    fn save_replay(&mut self) -> Option<String> {
+        let recorder = self.recorder.take()?;
+
+        match recorder.save(&self.config.replay_directory) {
+            Ok(path) => {
+                info!(target: "physics", room = %self.name, tick = self.tick, path = %path, "inputs saved");
+                Some(path)
+            },
+            Err(error) => {
+                error!(target: "physics", room = %self.name, tick = self.tick, %error, "can't save inputs");
+                None
+            },
+        }
+    }
+
+    fn record(&mut self, input: Input) {
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.push(self.tick, input);
+        }
+    }
+
+    // replays create their own room, it mustn't log over the one being replayed
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+    }
+
+    // replays draw from the recorded seed, before the first tick
+    pub fn reseed(&mut self, seed: u64) {
+        self.random = Random::new(&self.name, seed);
+    }
+
+    pub fn client_entity(&self, client: ClientId) -> Option<NetId> {
+        self.sessions.iter().find(|session| session.client == client).and_then(|session| session.entity)
+    }
+
+    pub fn session_token(&self, client: ClientId) -> Option<SessionToken> {
+        self.sessions.token(client)
+    }
+
+    // replays drop connections at the tick they were dropped in the recording
+    pub fn disconnect(&mut self, client: ClientId, tick: u64) {
+        self.sessions.disconnect(client, tick);
+    }
+
+    // every replicated entity as it is right now, not only the ones that changed
+    pub fn states(&self) -> Arc<Vec<EntityState>> {
+        self.keyframe()
+    }
+
+    pub fn inspect(&self) -> Inspection {
+        Inspection {
+            room: self.name.clone(),
+            tick: self.tick,
+            entities: inspect::entities(&self.world, &self.entities),
+            colliders: inspect::static_colliders(&self.world, &self.net_ids),
+        }
+    }
+
+    // of the last keyframe, replays compare it with the recorded one
+    pub fn checksum(&self) -> u64 {
+        replay::checksum(&self.snapshot)
+    }
+
+    // the match timer of the current phase first, then the host ones by name
+    fn timer_states(&self, tick: u64) -> Vec<TimerState> {
+        let phase_timer = self.match_state.remaining(tick).map(|remaining| {
+            let name = if self.match_state.phase == MatchPhase::Countdown { timers::COUNTDOWN } else { timers::MATCH };
+            TimerState { name: name.to_string(), remaining_ticks: remaining as u32 }
+        });
+
+        phase_timer.into_iter()
+            .chain(self.timers.iter().map(|(name, remaining)| TimerState { name: name.clone(), remaining_ticks: *remaining as u32 }))
+            .collect()
+    }
+
+    // milliseconds since the room was created
+    fn server_time(&self) -> u64 {
+        let elapsed = self.started_at.elapsed();
+        elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+    }
+
+    // at the named spawn point when there is one, the least contested one otherwise, ownerless
+    // until the caller hands it over
+    fn spawn_player(&mut self, player: &str, named: Option<&str>) -> Entity {
+        let mut descriptor = SpawnDescriptor::new(EntityKind::Player, "player");
+        descriptor.metadata.values.insert(String::from("name"), player.to_string());
+        descriptor.proximity = Some(PLAYER_PROXIMITY_MARGIN);
+        descriptor.lock_rotation = true;
+        if self.config.kinematic_players {
+            descriptor.character = Some(CharacterController::new(PLAYER_SPEED));
+        } else {
+            descriptor.damping = Some(BALL_DAMPING);
+        }
+
+        let spawn_point = named.and_then(|name| self.spawn_points.iter().find(|spawn_point| spawn_point.name == name))
+            .or_else(|| respawn::least_contested(&self.spawn_points, &self.world, &self.entities));
+        let position = match spawn_point {
+            Some(spawn_point) => {
+                info!(target: "physics", room = %self.name, tick = self.tick, player, spawn_point = %spawn_point.name, "player spawns");
+                spawn_point.position
+            },
+            None => Vector2::new(DEFAULT_SPAWN.0, DEFAULT_SPAWN.1),
+        };
+        let entity = self.spawn_ball(position, descriptor);
+        let _ = self.entities.insert_one(entity, Health(PLAYER_HEALTH));
+        entity
+    }
+
+    // a client that left in the meantime doesn't come back
+    fn respawn(&mut self, client: ClientId) {
+        let player = match self.sessions.get_mut(client) {
+            Some(session) if session.role == Role::Player => session.player.clone(),
+            _ => return,
+        };
+
+        let entity = self.spawn_player(&player, None);
+        let _ = self.entities.insert_one(entity, Invulnerable(RESPAWN_INVULNERABLE_TICKS));
+        self.set_owner(entity, Some(client));
+
+        let id = match self.entities.get::<Replicated>(entity) {
+            Ok(replicated) => replicated.id,
+            Err(_) => return,
+        };
+        if let Some(session) = self.sessions.get_mut(client) {
+            session.entity = Some(id);
+        }
+        self.events.push(Event::Respawned { client, entity: id });
+    }
+
+    // what the client asked to see, within the server caps
+    pub fn set_view(&mut self, client: ClientId, request: ViewRequest) {
+        let view = View::resolve(request, self.config.max_view_radius, self.config.line_of_sight, self.config.tick_rate, KEYFRAME_INTERVAL_TICKS);
+        if let Some(session) = self.sessions.get_mut(client) {
+            session.view = view;
+            session.view_request = request;
+        }
+    }
+
+    // a reloaded config, `Config::check_reload` made sure it only changes what a running room can
+    // take: the step follows the tick rate, the budgets and views are applied to every session
+    pub fn reconfigure(&mut self, config: Arc<Config>) {
+        self.world.set_timestep(1.0 / config.tick_rate as f32);
+        self.timestep = self.world.timestep();
+        self.sessions.set_limits(config.command_limit, config.relay_limit);
+        self.sessions.resolve_views(|request| View::resolve(request, config.max_view_radius, config.line_of_sight, config.tick_rate, KEYFRAME_INTERVAL_TICKS));
+        self.config = config;
+        info!(target: "physics", room = %self.name, tick_rate = self.config.tick_rate, "config reloaded");
+    }
+
+    // the client is already authenticated and allowed in this room
+    pub fn join(&mut self, player: String, role: Role, version: u16, supported: &[Compression], tx: Sender<Frame>) -> ClientId {
+        let entity = match role {
+            Role::Player => Some(self.spawn_player(&player, None)),
+            Role::Spectator => None,
+        };
+        let id = entity.and_then(|entity| self.entities.get::<Replicated>(entity).ok().map(|replicated| replicated.id));
+
+        let compressor = Compressor::new(compression::negotiate(supported));
+        let client = self.sessions.open(player.clone(), role, version, id, compressor, tx).client;
+        if let Some(entity) = entity {
+            self.set_owner(entity, Some(client));
+        }
+        self.record(Input::Join { client, player, role });
+        self.welcome(client);
+        client
+    }
+
+    // the player keeps its session and momentum, the rest of its entity stays behind
+    fn transfer(&mut self, entity: Entity, room: String, spawn_point: String) {
+        let client = match self.owner(entity) {
+            Some(client) => client,
+            None => return,
+        };
+        let velocity = self.entities.get::<PhysicsBody>(entity).ok()
+            .and_then(|physics_body| self.world.rigid_body(physics_body.body))
+            .map_or(Vector2::zeros(), |rigid_body| rigid_body.velocity().linear);
+        let session = match self.sessions.take(client) {
+            Some(session) => session,
+            None => return,
+        };
+
+        info!(target: "physics", room = %self.name, tick = self.tick, client, to = %room, "client leaves for another room");
+        self.despawn(entity, DespawnReason::Transferred);
+        self.events.push(Event::Left { client, entity: session.entity });
+        self.transfers.push(Transfer { room, spawn_point, session, velocity });
+    }
+
+    pub fn take_transfers(&mut self) -> Vec<Transfer> {
+        self.transfers.drain(..).collect()
+    }
+
+    // the client is welcomed again, as if it had just joined
+    pub fn accept(&mut self, transfer: Transfer) {
+        let Transfer { spawn_point, session, velocity, .. } = transfer;
+        let player = session.player.clone();
+        let client = self.sessions.adopt(session).client;
+        self.arrive(client, player, spawn_point, velocity);
+    }
+
+    // the session of a room that crashed, the client starts over here as if it had just joined
+    pub fn readmit(&mut self, session: Session) {
+        let (player, role) = (session.player.clone(), session.role);
+        let client = self.sessions.adopt(session).client;
+        let entity = match role {
+            Role::Player => Some(self.spawn_player(&player, None)),
+            Role::Spectator => None,
+        };
+        let id = entity.and_then(|entity| self.entities.get::<Replicated>(entity).ok().map(|replicated| replicated.id));
+
+        if let Some(entity) = entity {
+            self.set_owner(entity, Some(client));
+        }
+        if let Some(session) = self.sessions.get_mut(client) {
+            session.entity = id;
+            session.last_sequence = None;
+        }
+        self.record(Input::Join { client, player, role });
+        self.welcome(client);
+    }
+
+    // replays have no session to hand over, a new one stands in for it
+    pub fn arrive_replayed(&mut self, player: String, spawn_point: &str, velocity: Vector2<f32>, tx: Sender<Frame>) -> ClientId {
+        let compressor = Compressor::new(compression::negotiate(&[]));
+        let client = self.sessions.open(player.clone(), Role::Player, PROTOCOL_VERSION, None, compressor, tx).client;
+        self.arrive(client, player, spawn_point.to_string(), velocity);
+        client
+    }
+
+    fn arrive(&mut self, client: ClientId, player: String, spawn_point: String, velocity: Vector2<f32>) {
+        let entity = self.spawn_player(&player, Some(&spawn_point));
+        if let Ok(physics_body) = self.entities.get::<PhysicsBody>(entity).map(|physics_body| *physics_body) {
+            if let Some(rigid_body) = self.world.rigid_body_mut(physics_body.body) {
+                rigid_body.set_linear_velocity(velocity);
+            }
+        }
+        let id = self.entities.get::<Replicated>(entity).ok().map(|replicated| replicated.id);
+
+        self.set_owner(entity, Some(client));
+        if let Some(session) = self.sessions.get_mut(client) {
+            session.entity = id;
+        }
+        info!(target: "physics", room = %self.name, tick = self.tick, client, "client enters");
+        self.record(Input::Arrive { client, player, spawn_point, velocity });
+        self.welcome(client);
+    }
+
+    fn welcome(&mut self, client: ClientId) {
+        let mut spawns = systems::spawns(&self.world, &self.entities);
+        spawns.push(Event::MatchPhaseChanged { phase: self.match_state.phase });
+        let keyframe = self.keyframe();
+        let manifest = self.chunks.manifest();
+        let session = match self.sessions.get_mut(client) {
+            Some(session) => session,
+            None => return,
+        };
+        let welcome = ServerMessage::Welcome {
+            client: session.client,
+            token: session.token,
+            entity: session.entity,
+            compression: session.compressor.compression(),
+        };
+        session.send(welcome);
+        if let Some((seed, params)) = self.arena {
+            session.send(ServerMessage::Arena { seed, params });
+        }
+        session.send(ServerMessage::ChunkManifest(manifest));
+        session.send(ServerMessage::Materials(self.materials.clone()));
+        // clients too old to decode it only get the warning in the server logs
+        if session.version < PROTOCOL_VERSION {
+            spawns.push(Event::ProtocolDeprecated { current: PROTOCOL_VERSION, minimum: MIN_PROTOCOL_VERSION });
+        }
+        session.send(ServerMessage::Events(spawns));
+        session.send_snapshot(keyframe, self.timer_states.clone(), true, self.tick);
+        self.events.push(Event::Joined { client: session.client, entity: session.entity });
+    }
+
+    pub fn usage(&self) -> RoomUsage {
+        RoomUsage {
+            entities: self.entities.iter().count(),
+            bodies: self.entities.query::<&PhysicsBody>().iter().filter(|(_, physics_body)| !physics_body.body.is_ground()).count(),
+            colliders: self.world.colliders().count(),
+            queued_messages: self.sessions.queued(),
+            snapshot_bytes: usage::snapshot_bytes(&self.snapshot),
+        }
+    }
+
+    fn check_usage(&mut self) {
+        let usage = self.usage();
+        let exceeded = self.config.soft_limits.exceeded(&usage);
+
+        for (name, value, limit) in exceeded.iter().filter(|(name, _, _)| !self.exceeded.contains(name)) {
+            warn!(target: "physics", room = %self.name, tick = self.tick, resource = name, value, limit, "room went over a soft limit");
+        }
+        self.exceeded = exceeded.into_iter().map(|(name, _, _)| name).collect();
+    }
+
+    pub fn timings(&self) -> &PhaseTimings {
+        &self.timings
+    }
+
+    // joins are authenticated and routed by the host, everything else lands here
+    pub fn handle(&mut self, message: ClientMessage) {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        let started_at = Instant::now();
+        self.dispatch(message);
+        self.timings.add(Phase::Commands, started_at.elapsed());
+    }
+
+    fn dispatch(&mut self, message: ClientMessage) {
+        let tick = self.tick;
+        let server_time = self.server_time();
+
+        // anything that can change the simulation goes in the input log
+        if self.recorder.is_some() {
+            let input = match message {
+                ClientMessage::Leave { client } => Some(Input::Leave { client }),
+                ClientMessage::Resume { token, .. } => self.sessions.find(token).map(|session| Input::Resume { client: session.client }),
+                ClientMessage::Command { client, sequence, ref command } => Some(Input::Command { client, sequence, command: command.clone() }),
+                ClientMessage::Admin { ref command, .. } => Some(Input::Admin(command.clone())),
+                _ => None,
+            };
+            if let Some(input) = input {
+                self.record(input);
+            }
+        }
+        // admin commands aren't in the lockstep inputs
+        if let ClientMessage::Admin { .. } = message {
+            self.resync();
+        }
+
+        match message {
+            ClientMessage::Join { tx, .. } => {
+                let _ = tx.send(Frame::uncompressed(&ServerMessage::Rejected(String::from("join must go through the host"))));
+            },
+            ClientMessage::Reload { reply } => {
+                let _ = reply.send(Err(String::from("reload must go through the host")));
+            },
+            ClientMessage::Resume { token, tx } => {
+                let mut spawns = systems::spawns(&self.world, &self.entities);
+                spawns.push(Event::MatchPhaseChanged { phase: self.match_state.phase });
+                let keyframe = self.keyframe();
+                let timer_states = self.timer_states.clone();
+                match self.sessions.resume(token, tx) {
+                    Ok(session) => {
+                        info!(target: "physics", room = %self.name, tick, client = session.client, "client resumed its session");
+
+                        // the backlog may have overflowed, the spawns are sent again in full
+                        let backlog = session.take_backlog();
+                        session.send(ServerMessage::Resumed { client: session.client, entity: session.entity });
+                        session.send(ServerMessage::Events(spawns));
+                        session.send_snapshot(keyframe, timer_states, true, tick);
+                        session.send(ServerMessage::Events(backlog));
+                        if let Some(hint) = session.camera {
+                            session.send(ServerMessage::Camera(hint));
+                        }
+                    },
+                    Err(tx) => {
+                        let _ = tx.send(Frame::uncompressed(&ServerMessage::Rejected(String::from("unknown or expired session"))));
+                    },
+                }
+            },
+            ClientMessage::Leave { client } => {
+                self.sessions.disconnect(client, tick);
+            },
+            ClientMessage::Command { client, sequence, command } => {
+                let session = match self.sessions.get_mut(client) {
+                    Some(session) => session,
+                    None => return,
+                };
+                if !session.allow_command() {
+                    return;
+                }
+                session.last_sequence = Some(sequence);
+                self.watches.record_command(&command);
+
+                if session.role == Role::Spectator {
+                    session.send(ServerMessage::CommandRejected { command: command.kind(), reason: RejectReason::Spectator });
+                    return;
+                }
+                if !self.match_state.accepts(command.kind()) {
+                    session.send(ServerMessage::CommandRejected { command: command.kind(), reason: RejectReason::MatchNotRunning });
+                    return;
+                }
+
+                let entities = &self.entities;
+                let checked = match self.net_ids.entity(command.entity()) {
+                    Some(entity) if entities.get::<Owner>(entity).ok().map(|owner| owner.0) != Some(client) => Err(RejectReason::NotOwner),
+                    Some(entity) => match (entities.get::<PhysicsBody>(entity), entities.get::<EntityLimits>(entity)) {
+                        (Ok(physics_body), Ok(entity_limits)) => match self.world.rigid_body(physics_body.body) {
+                            Some(rigid_body) => {
+                                let velocity = rigid_body.velocity().linear;
+                                let mass = rigid_body.local_inertia().linear;
+
+                                validation::validate(&command, &entity_limits, velocity, mass).and_then(|_| match command {
+                                    Command::Move { .. } | Command::Jump { .. } if entities.get::<CharacterController>(entity).is_err() => Err(RejectReason::CommandNotAllowed),
+                                    Command::Drive { .. } if entities.get::<Vehicle>(entity).is_err() => Err(RejectReason::CommandNotAllowed),
+                                    _ => Ok((entity, *physics_body)),
+                                })
+                            },
+                            // walls and terrain are on the ground body, there's nothing to move
+                            None => Err(RejectReason::CommandNotAllowed),
+                        },
+                        _ => Err(RejectReason::CommandNotAllowed),
+                    },
+                    None => Err(RejectReason::UnknownEntity),
+                };
+
+                if let (Ok(_), Some(inputs)) = (&checked, self.lockstep.as_mut()) {
+                    inputs.push((client, command.clone()));
+                }
+                match checked {
+                    Ok((entity, physics_body)) => match command {
+                        Command::Impulse { impulse, .. } => apply_impulse(&mut self.world, physics_body, impulse),
+                        Command::Move { direction, .. } => {
+                            if let Ok(mut controller) = self.entities.get_mut::<CharacterController>(entity) {
+                                controller.input = direction;
+                            }
+                        },
+                        Command::Jump { pressed, .. } => {
+                            if let Ok(mut controller) = self.entities.get_mut::<CharacterController>(entity) {
+                                controller.set_jump(pressed);
+                            }
+                        },
+                        Command::Dash { direction, distance, duration, .. } => {
+                            self.start_motion(entity, ScriptedMotion::dash(direction, distance, duration));
+                        },
+                        Command::Grapple { direction, .. } => {
+                            match grapple::attach(&self.world, &physics_body, direction, GRAPPLE_RANGE) {
+                                Some(hook) => {
+                                    let _ = self.entities.insert_one(entity, hook);
+                                },
+                                None => {
+                                    let _ = self.entities.remove_one::<Grapple>(entity);
+                                },
+                            }
+                        },
+                        Command::Reel { speed, .. } => {
+                            if let Ok(mut hook) = self.entities.get_mut::<Grapple>(entity) {
+                                hook.reel_speed = speed;
+                            }
+                        },
+                        Command::Release { .. } => {
+                            let _ = self.entities.remove_one::<Grapple>(entity);
+                        },
+                        Command::Drive { throttle, steer, .. } => {
+                            if let Ok(mut vehicle) = self.entities.get_mut::<Vehicle>(entity) {
+                                vehicle.throttle = throttle;
+                                vehicle.steer = steer;
+                            }
+                        },
+                        Command::FireHitscan { shooter, dir, client_tick } => self.fire_hitscan(entity, shooter, physics_body, dir, client_tick),
+                        Command::Swing { entity: id, direction, radius, angle } => {
+                            if let Some(collider) = self.world.collider(physics_body.collider) {
+                                let origin = Point2::from(collider.position().translation.vector);
+                                let mut targets = melee::in_arc(&self.world, &self.entities, &self.net_ids, &self.spatial, origin, direction, radius.min(MELEE_REACH), angle);
+                                targets.retain(|target| *target != id);
+                                self.events.push(Event::Swung { entity: id, targets });
+                            }
+                        },
+                    },
+                    Err(reason) => {
+                        session.send(ServerMessage::CommandRejected { command: command.kind(), reason });
+                    },
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::TransferAuthority { entity, owner }, .. } => {
+                let target = match self.net_ids.entity(entity) {
+                    Some(target) => target,
+                    None => {
+                        warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't transfer authority of unknown entity");
+                        return;
+                    },
+                };
+
+                if owner.is_some() && self.is_ground(target) {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't transfer authority of a static entity");
+                    return;
+                }
+                let previous = self.owner(target);
+                if previous == owner {
+                    return;
+                }
+                self.set_owner(target, owner);
+
+                // both clients restart their prediction from a fresh keyframe
+                let keyframe = self.keyframe();
+                for client in previous.iter().chain(owner.iter()) {
+                    if let Some(session) = self.sessions.get_mut(*client) {
+                        session.send_snapshot(keyframe.clone(), self.timer_states.clone(), true, tick);
+                    }
+                }
+                self.events.push(Event::AuthorityChanged { entity, previous, owner });
+            },
+            ClientMessage::Admin { command: AdminCommand::SetMetadata { entity, metadata }, .. } => {
+                let target = match self.net_ids.entity(entity) {
+                    Some(target) => target,
+                    None => {
+                        warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't set metadata of unknown entity");
+                        return;
+                    },
+                };
+
+                if self.entities.get::<Metadata>(target).map_or(false, |current| *current == metadata) {
+                    return;
+                }
+                let _ = self.entities.insert_one(target, metadata.clone());
+                self.events.push(Event::MetadataChanged { entity, metadata });
+            },
+            ClientMessage::Admin { command: AdminCommand::SetCollisionGroups { entity, groups }, .. } => {
+                match self.net_ids.entity(entity) {
+                    Some(target) => self.set_collision_groups(target, groups),
+                    None => warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't set collision groups of unknown entity"),
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::SetCollisionLayer { entity, layer }, .. } => {
+                match self.net_ids.entity(entity) {
+                    Some(target) => {
+                        let groups = self.layer_groups(&layer);
+                        self.set_collision_groups(target, groups);
+                    },
+                    None => warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't set collision layer of unknown entity"),
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::SetTeam { entity, team }, .. } => {
+                if team.map_or(false, |team| team as usize >= collision::MAX_TEAMS) {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, team = ?team, "team is out of range");
+                    return;
+                }
+                match self.net_ids.entity(entity) {
+                    Some(target) => self.set_team(target, team),
+                    None => warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't set team of unknown entity"),
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::AddScore { client, points }, .. } => {
+                let score = self.scores.entry(client).or_insert(0);
+                *score = score.saturating_add(points);
+                let score = *score;
+                self.events.push(Event::Scored { client, points, score });
+            },
+            ClientMessage::Admin { command: AdminCommand::StartTimer { name, ticks }, .. } => {
+                if name == timers::COUNTDOWN || name == timers::MATCH {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, timer = %name, "timer is reserved for the match");
+                    return;
+                }
+                self.timers.start(name, ticks);
+            },
+            ClientMessage::Admin { command: AdminCommand::CancelTimer { name }, .. } => {
+                if !self.timers.cancel(&name) {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, timer = %name, "can't cancel unknown timer");
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::Knockback { entity, impulse }, .. } => {
+                match self.net_ids.entity(entity) {
+                    Some(target) => self.knockback(target, impulse),
+                    None => warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't knock back unknown entity"),
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::SetGravityScale { entity, scale }, .. } => {
+                match self.net_ids.entity(entity) {
+                    Some(target) => self.set_gravity_scale(target, scale),
+                    None => warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't set gravity scale of unknown entity"),
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::SpawnVehicle { position }, .. } => {
+                if self.make_room() {
+                    self.spawn_vehicle(position, SpawnDescriptor::new(EntityKind::Vehicle, "prop"));
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::Ragdoll { entity }, .. } => {
+                match self.net_ids.entity(entity) {
+                    Some(target) => self.ragdoll(target),
+                    None => warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't ragdoll unknown entity"),
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::SpawnSoftBody { position, radius }, .. } => {
+                if !(radius.is_finite() && radius > 0.0) {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, %radius, "can't spawn a soft body this size");
+                } else if self.make_room() {
+                    self.spawn_soft_body(position, radius, SpawnDescriptor::new(EntityKind::SoftBody, "prop"));
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::AttachRope { entity, length, segments }, .. } => {
+                let target = match self.net_ids.entity(entity) {
+                    Some(target) => target,
+                    None => {
+                        warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't attach a rope to unknown entity");
+                        return;
+                    },
+                };
+                if !length.is_finite() || length <= 0.0 || length > MAX_ROPE_LENGTH || segments == 0 {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, %length, segments, "can't attach a rope this size");
+                    return;
+                }
+
+                let anchor = self.entities.get::<PhysicsBody>(target).ok()
+                    .and_then(|physics_body| self.world.collider(physics_body.collider))
+                    .map(|collider| Point2::from(collider.position().translation.vector));
+                if let Some(anchor) = anchor {
+                    let _ = self.entities.insert_one(target, Rope::new(anchor, length, segments as usize));
+                }
+            },
+            ClientMessage::Admin { command: AdminCommand::Explode { position, radius, impulse, damage, cover }, .. } => {
+                self.explode(position, radius, impulse, damage, cover);
+            },
+            ClientMessage::WatchEntity { entity, tx, .. } => {
+                if self.net_ids.entity(entity).is_none() {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, entity, "can't watch unknown entity");
+                    return;
+                }
+                info!(target: "physics", room = %self.name, tick = self.tick, entity, "entity watched");
+                self.watches.add(entity, tx);
+            },
+            ClientMessage::Ping { client, client_time } => {
+                if let Some(session) = self.sessions.get_mut(client) {
+                    session.send(ServerMessage::Pong { client_time, tick, server_time });
+                }
+            },
+            ClientMessage::Pong { client, sent_at, client_time } => {
+                if let Some(session) = self.sessions.get_mut(client) {
+                    session.clock.sample(sent_at, client_time, server_time);
+                }
+            },
+            ClientMessage::AckSnapshot { client, tick } => {
+                if let Some(session) = self.sessions.get_mut(client) {
+                    session.ack_snapshot(tick);
+                }
+            },
+            ClientMessage::Desynced { client } => {
+                warn!(target: "physics", room = %self.name, tick = self.tick, client, "lockstep client desynced");
+                self.sessions.resync(client);
+            },
+            ClientMessage::FetchChunk { client, chunk } => {
+                let blocks = match self.chunks.blocks(chunk) {
+                    Some(blocks) => blocks,
+                    None => {
+                        warn!(target: "physics", room = %self.name, tick = self.tick, client, chunk = ?chunk, "client fetched unknown chunk");
+                        return;
+                    },
+                };
+                if let Some(session) = self.sessions.get_mut(client) {
+                    session.send(ServerMessage::Chunk { id: chunk, blocks });
+                }
+            },
+            ClientMessage::Relay { client, to, channel, payload } => {
+                let session = match self.sessions.get_mut(client) {
+                    Some(session) => session,
+                    None => return,
+                };
+                if payload.len() > MAX_RELAY_PAYLOAD || !session.allow_relay() {
+                    return;
+                }
+                if to.map_or(false, |to| !self.sessions.contains(to)) {
+                    warn!(target: "physics", room = %self.name, tick = self.tick, client, to = ?to, "relay to a client out of the room");
+                    return;
+                }
+                self.sessions.relay(client, to, channel, &payload);
+            },
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        let tick_started_at = Instant::now();
+        let tick = self.tick;
+
+        self.run_timeline();
+        if self.ended {
+            return;
+        }
+
+        let top_score = self.scores.values().cloned().max().unwrap_or(0);
+        if let Some(phase) = self.match_state.update(tick, self.sessions.players(), top_score) {
+            info!(target: "physics", room = %self.name, tick, phase = ?phase, "match phase changed");
+            self.events.push(Event::MatchPhaseChanged { phase });
+            if phase == MatchPhase::Finished {
+                let replay = self.save_replay();
+                self.record_results(tick, replay);
+            }
+        }
+        for name in self.timers.tick() {
+            self.events.push(Event::TimerExpired { name });
+        }
+
+        self.sessions.report_throttled();
+
+        // a late tick catches up with several full steps of the fixed timestep, one big step would
+        // let bodies tunnel, and the ticks run back to back after it take none
+        let elapsed = match self.last_tick_at {
+            Some(last_tick_at) => {
+                let elapsed = tick_started_at - last_tick_at;
+                elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9
+            },
+            None => self.timestep,
+        };
+        self.last_tick_at = Some(tick_started_at);
+
+        // a deterministic room always takes the same step, being late only slows it down
+        let substeps = if self.config.deterministic {
+            1
+        } else {
+            self.behind += elapsed;
+            let substeps = ((self.behind / self.timestep).round().max(0.0) as u32).min(MAX_SUBSTEPS);
+            self.behind -= substeps as f32 * self.timestep;
+            // past the cap the time is dropped, not carried to the next ticks
+            self.behind = self.behind.min(self.timestep);
+            substeps
+        };
+
+        let phase_started_at = Instant::now();
+        for _ in 0..substeps {
+            systems::scale_gravity(&mut self.world, &self.entities);
+            systems::damp(&mut self.world, &self.entities);
+            controller::move_characters(&mut self.world, &self.entities);
+            vehicle::run_motors(&mut self.world, &self.entities);
+            soft_body::apply_springs(&mut self.world, &self.entities);
+            for entity in motion::drive(&mut self.world, &self.entities, &mut self.events) {
+                let _ = self.entities.remove_one::<ScriptedMotion>(entity);
+            }
+            let velocities = systems::velocities(&self.world, &self.entities);
+            let positions = ccd::positions(&self.world, &self.entities);
+            self.world.step();
+            ccd::sweep(&mut self.world, &positions);
+            for entity in grapple::constrain(&mut self.world, &self.entities) {
+                let _ = self.entities.remove_one::<Grapple>(entity);
+            }
+            ragdoll::limit_joints(&mut self.world, &self.entities);
+            rope::simulate(&self.world, &self.entities);
+            systems::clamp_velocities(&mut self.world, &self.entities, self.config.max_linear_speed, self.config.max_angular_speed, &mut self.events);
+            systems::collect_contacts(&self.world, &self.entities, &self.net_ids, &velocities, &mut self.events);
+            let (entities, net_ids, chunks) = (&self.entities, &self.net_ids, &self.chunks);
+            let surface = |collider| match net_ids.by_collider(collider).and_then(|id| net_ids.entity(id)) {
+                Some(entity) => entities.get::<Surface>(entity).map_or(0, |surface| surface.0),
+                None => chunks.surface(collider),
+            };
+            systems::impacts(&self.world, entities, net_ids, &velocities, surface, MIN_IMPACT_FX_IMPULSE, &mut self.impacts, tick, &mut self.events);
+            for (client, impulse) in systems::jolts(&self.world, &self.entities, &self.net_ids, &velocities) {
+                if let (Some(feedback), Some(session)) = (haptics::feedback(impulse, MIN_FEEDBACK_IMPULSE), self.sessions.get_mut(client)) {
+                    session.send(ServerMessage::Feedback { magnitude: feedback.magnitude, duration_ms: feedback.duration_ms });
+                }
+            }
+        }
+        self.spatial.clear();
+        self.history.record(tick, &self.world, &self.entities);
+
+        let events_started_at = Instant::now();
+        self.timings.add(Phase::Step, events_started_at - phase_started_at);
+
+        systems::proximity(&self.world, &self.entities, &mut self.near, &mut self.events);
+        self.run_handlers();
+
+        for entity in systems::dead(&self.entities) {
+            self.despawn(entity, DespawnReason::Destroyed);
+        }
+        for id in self.despawns.expired(tick) {
+            if let Some(entity) = self.net_ids.entity(id) {
+                self.despawn(entity, DespawnReason::Expired);
+            }
+        }
+        for entity in systems::out_of_bounds(&self.world, &self.entities, &self.config.bounds_min, &self.config.bounds_max) {
+            self.despawn(entity, DespawnReason::OutOfBounds);
+        }
+        for entity in systems::wear_off_invulnerability(&self.entities) {
+            let _ = self.entities.remove_one::<Invulnerable>(entity);
+        }
+        for client in self.respawns.due(tick) {
+            self.respawn(client);
+        }
+        self.run_spawners();
+
+        for session in self.sessions.expire(tick) {
+            info!(target: "physics", room = %self.name, tick, client = session.client, "session expired");
+
+            if let Some(entity) = session.entity.and_then(|id| self.net_ids.entity(id)) {
+                self.despawn(entity, DespawnReason::Removed);
+            }
+
+            // anything else it was given goes back to the server
+            let owned: Vec<Entity> = self.entities.query::<&Owner>()
+                .iter()
+                .filter(|(_, owner)| owner.0 == session.client)
+                .map(|(entity, _)| entity)
+                .collect();
+            for entity in owned {
+                self.set_owner(entity, None);
+            }
+            self.events.push(Event::Left { client: session.client, entity: session.entity });
+        }
+
+        if self.config.check_invariants {
+            self.check_invariants(tick);
+        }
+
+        if !self.watches.is_empty() {
+            self.watches.report(tick, &self.world, &self.entities, &self.net_ids);
+        }
+
+        if tick % PING_INTERVAL_TICKS == 0 {
+            let sent_at = self.server_time();
+            self.sessions.broadcast(&ServerMessage::Ping { sent_at });
+        }
+        if tick % USAGE_INTERVAL_TICKS == 0 {
+            self.check_usage();
+        }
+        if tick % CHUNK_INTERVAL_TICKS == 0 && self.chunks.update(&mut self.world, &self.entities, &self.config.layers) {
+            self.sessions.broadcast(&ServerMessage::ChunkManifest(self.chunks.manifest()));
+        }
+        let replication_started_at = Instant::now();
+        self.timings.add(Phase::Events, replication_started_at - events_started_at);
+
+        // every clone of the previous tick was dropped by the flush, so this reuses the same buffer
+        if Arc::get_mut(&mut self.snapshot).is_none() {
+            self.snapshot = Arc::new(Vec::with_capacity(self.net_ids.len()));
+        }
+        let keyframe = tick % KEYFRAME_INTERVAL_TICKS == 0 || self.resync;
+        if tick % OUTLINE_INTERVAL_TICKS == 0 {
+            soft_body::refresh_outlines(&self.world, &self.entities);
+        }
+        if tick % self.config.rope_interval_ticks == 0 {
+            rope::refresh_polylines(&self.entities);
+        }
+        let wants_deltas = self.sessions.wants_deltas();
+        let mut quantized = Arc::new(vec![]);
+        if let Some(states) = Arc::get_mut(&mut self.snapshot) {
+            systems::replicate(&self.world, &self.entities, states);
+            // deltas cover every entity, before the unchanged ones are left out
+            if wants_deltas {
+                quantized = Arc::new(xor_delta::quantize(states));
+            }
+
+            let changes = &mut self.changes;
+            if !keyframe {
+                states.retain(|state| changes.has_changed(state));
+            }
+            for state in states.iter() {
+                changes.mark_sent(state);
+            }
+        }
+        self.timer_states = Arc::new(self.timer_states(tick));
+        match self.lockstep {
+            Some(ref mut inputs) => {
+                if self.resync {
+                    self.sessions.resync_all();
+                    self.resync = false;
+                }
+                let checksum = if keyframe { Some(replay::checksum(&self.snapshot)) } else { None };
+                let inputs = Arc::new(mem::replace(inputs, vec![]));
+                self.sessions.broadcast_inputs(&inputs, checksum, &self.snapshot, &self.timer_states, keyframe, tick);
+            },
+            None => {
+                let (world, entities, net_ids) = (&self.world, &self.entities, &self.net_ids);
+                let focus = |id| {
+                    let physics_body = entities.get::<PhysicsBody>(net_ids.entity(id)?).ok()?;
+                    world.rigid_body(physics_body.body).map(|rigid_body| rigid_body.position().translation.vector)
+                };
+                let occluded = |from, to| view::occluded(world, from, to);
+                self.sessions.broadcast_snapshot(&self.snapshot, &quantized, &self.timer_states, keyframe, tick, focus, occluded);
+            },
+        }
+        self.sessions.publish(&self.events);
+        self.events.clear();
+        let flush_started_at = Instant::now();
+        self.timings.add(Phase::Replication, flush_started_at - replication_started_at);
+
+        for client in self.sessions.flush(tick) {
+            self.record(Input::Dropped { client });
+        }
+        let encoding = self.sessions.take_encoding();
+        self.timings.add(Phase::Encode, encoding);
+        self.timings.add(Phase::Send, flush_started_at.elapsed().checked_sub(encoding).unwrap_or_default());
+        if let Some(ref mut recorder) = self.recorder {
+            if keyframe {
+                recorder.checksums.push((tick, replay::checksum(&self.snapshot)));
+            }
+            recorder.ticks = tick + 1;
+        }
+
+        self.sessions.refill();
+        self.tick += 1;
+        self.last_tick_duration = tick_started_at.elapsed();
+        self.timings.end_tick();
+    }
+
+    fn check_invariants(&mut self, tick: u64) {
+        let limits = Limits {
+            bounds_min: self.config.bounds_min,
+            bounds_max: self.config.bounds_max,
+            max_linear_speed: self.config.max_linear_speed,
+            max_angular_speed: self.config.max_angular_speed,
+        };
+
+        for (id, violation) in invariants::check(&self.world, &self.entities, &self.net_ids, &limits) {
+            error!(target: "physics", room = %self.name, tick, entity = id, violation = ?violation, "entity breaks an invariant");
+            self.events.push(Event::InvariantViolated { entity: id, violation });
+
+            if violation == Violation::NonFinite {
+                if let Some(entity) = self.net_ids.entity(id) {
+                    self.despawn(entity, DespawnReason::Removed);
+                }
+            }
+        }
+    }
+
+    // after a panic nothing in the room can be trusted but its sessions, they are told and handed back,
+    // the input log is kept so the crash can be replayed
+    pub fn crash(&mut self, restarting: bool) -> Vec<Session> {
+        self.ended = true;
+        self.sessions.broadcast(&ServerMessage::Crashed { restarting });
+        self.sessions.flush(self.tick);
+        self.save_replay();
+        self.sessions.drain()
+    }
+
+    pub fn end(&mut self) {
+        self.ended = true;
+        info!(target: "physics", room = %self.name, tick = self.tick, "room ends");
+        self.sessions.broadcast(&ServerMessage::End);
+        self.sessions.flush(self.tick);
+        self.save_replay();
+    }
+}
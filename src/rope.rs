@@ -0,0 +1,109 @@
+use na::{Point2, Vector2};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+
+// more iterations make the rope stiffer, past a few it's no longer visible
+const ITERATIONS: usize = 8;
+// fraction of the velocity a point keeps each step, air drag without a damping model
+const DRAG: f32 = 0.99;
+
+// a verlet rope hanging from the body of its entity, the points aren't bodies so the rope
+// collides with the world but doesn't push anything
+#[derive(Debug, Clone)]
+pub struct Rope {
+    pub points: Vec<Point2<f32>>,
+    previous: Vec<Point2<f32>>,
+    pub segment_length: f32,
+    // the points as last replicated, refreshed at the configured rate
+    pub polyline: Vec<Point2<f32>>,
+}
+
+impl Rope {
+    // starts straight down from `anchor`
+    pub fn new(anchor: Point2<f32>, length: f32, segments: usize) -> Rope {
+        let segments = segments.max(1);
+        let segment_length = length / segments as f32;
+        let points: Vec<Point2<f32>> = (0..=segments)
+            .map(|i| anchor - Vector2::y() * (segment_length * i as f32))
+            .collect();
+
+        Rope {
+            previous: points.clone(),
+            polyline: points.clone(),
+            points,
+            segment_length,
+        }
+    }
+}
+
+// pushes the point out of every collider it ended up in, the anchor body is left out
+fn collide(world: &World<f32>, physics_body: &PhysicsBody, groups: &CollisionGroups, point: Point2<f32>) -> Point2<f32> {
+    let mut point = point;
+
+    for object in world.collision_world().collision_objects() {
+        if object.handle() == physics_body.collider || !groups.can_interact_with_groups(object.collision_groups()) {
+            continue;
+        }
+
+        if let Some(query) = object.shape().as_point_query() {
+            let projection = query.project_point(object.position(), &point, false);
+            if projection.is_inside {
+                point = projection.point;
+            }
+        }
+    }
+
+    point
+}
+
+// runs after each step so the first point follows where its body ended up
+pub fn simulate(world: &World<f32>, entities: &hecs::World) {
+    let timestep = world.timestep();
+    let gravity = *world.gravity() * timestep * timestep;
+
+    for (_, (physics_body, groups, rope)) in entities.query::<(&PhysicsBody, &CollisionGroups, &mut Rope)>().iter() {
+        let anchor = match world.collider(physics_body.collider) {
+            Some(collider) => Point2::from(collider.position().translation.vector),
+            None => continue,
+        };
+
+        for i in 1..rope.points.len() {
+            let current = rope.points[i];
+            rope.points[i] = current + (current - rope.previous[i]) * DRAG + gravity;
+            rope.previous[i] = current;
+        }
+        rope.points[0] = anchor;
+        rope.previous[0] = anchor;
+
+        for _ in 0..ITERATIONS {
+            for i in 0..rope.points.len() - 1 {
+                let offset = rope.points[i + 1] - rope.points[i];
+                let distance = offset.norm();
+                if distance == 0.0 {
+                    continue;
+                }
+                let correction = offset * ((distance - rope.segment_length) / distance);
+
+                // the anchor doesn't move, its neighbour takes the whole correction
+                if i == 0 {
+                    rope.points[i + 1] -= correction;
+                } else {
+                    rope.points[i] += correction * 0.5;
+                    rope.points[i + 1] -= correction * 0.5;
+                }
+            }
+
+            for i in 1..rope.points.len() {
+                rope.points[i] = collide(world, physics_body, groups, rope.points[i]);
+            }
+        }
+    }
+}
+
+pub fn refresh_polylines(entities: &hecs::World) {
+    for (_, rope) in entities.query::<&mut Rope>().iter() {
+        rope.polyline.clone_from(&rope.points);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use tracing::{debug, info};
+
+use crate::auth::{self, Claims};
+use crate::matchmaking::{Matchmaker, RoomSummary};
+use crate::protocol::Role;
+
+// churn is logged once a minute at 60 ticks per second
+const REPORT_TICKS: u64 = 3600;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScalingPolicy {
+    pub max_players: usize,
+    pub max_entities: usize,
+    pub empty_grace_ticks: u64,
+}
+
+impl ScalingPolicy {
+    fn is_full(&self, room: &RoomSummary) -> bool {
+        room.players >= self.max_players || room.entities >= self.max_entities
+    }
+}
+
+// players sent to a full room go to one of its overflow rooms instead, a new one when they're all
+// full too, spectators still go where they asked
+pub struct Scaling<M: Matchmaker> {
+    inner: M,
+    policy: ScalingPolicy,
+}
+
+impl<M: Matchmaker> Scaling<M> {
+    pub fn new(inner: M, policy: ScalingPolicy) -> Scaling<M> {
+        Scaling { inner, policy }
+    }
+}
+
+impl<M: Matchmaker> Matchmaker for Scaling<M> {
+    fn assign(&mut self, claims: &Claims, requested: &str, role: Role, rooms: &[RoomSummary]) -> Option<String> {
+        let assigned = self.inner.assign(claims, requested, role, rooms)?;
+        if role != Role::Player {
+            return Some(assigned);
+        }
+
+        let base = auth::base_room(&assigned);
+        let siblings: Vec<&RoomSummary> = rooms.iter().filter(|room| auth::base_room(&room.name) == base).collect();
+        if siblings.is_empty() {
+            return Some(assigned);
+        }
+        if let Some(room) = siblings.iter().find(|room| !self.policy.is_full(room)) {
+            return Some(room.name.clone());
+        }
+
+        let next = (2..).map(|index| auth::overflow_room(base, index))
+            .find(|name| siblings.iter().all(|room| room.name != *name))?;
+        Some(next)
+    }
+}
+
+// retires rooms left empty for longer than the grace period and keeps count of the churn
+pub struct RoomChurn {
+    policy: ScalingPolicy,
+    empty_since: HashMap<String, u64>,
+    created: u64,
+    retired: u64,
+}
+
+impl RoomChurn {
+    pub fn new(policy: ScalingPolicy) -> RoomChurn {
+        RoomChurn {
+            policy,
+            empty_since: HashMap::new(),
+            created: 0,
+            retired: 0,
+        }
+    }
+
+    pub fn created(&mut self, room: &str) {
+        self.created += 1;
+        debug!(target: "physics", room, "room counted for churn");
+    }
+
+    // the rooms to retire this tick, the last room is always kept so the server keeps running
+    pub fn update(&mut self, tick: u64, rooms: &[RoomSummary]) -> Vec<String> {
+        self.empty_since.retain(|name, _| rooms.iter().any(|room| room.name == *name && room.players == 0));
+
+        let mut retiring = vec![];
+        for room in rooms.iter().filter(|room| room.players == 0) {
+            let since = *self.empty_since.entry(room.name.clone()).or_insert(tick);
+            if tick - since >= self.policy.empty_grace_ticks && retiring.len() + 1 < rooms.len() {
+                retiring.push(room.name.clone());
+            }
+        }
+        for name in retiring.iter() {
+            self.empty_since.remove(name);
+            self.retired += 1;
+            info!(target: "physics", room = %name, tick, "room retired after staying empty");
+        }
+
+        if tick > 0 && tick % REPORT_TICKS == 0 {
+            info!(target: "physics", tick, rooms = rooms.len(), created = self.created, retired = self.retired, "room churn");
+        }
+
+        retiring
+    }
+}
@@ -0,0 +1,52 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+// sleeping is only precise to a millisecond or so, the end of the wait is spun
+const SPIN_THRESHOLD: Duration = Duration::from_micros(1500);
+// past this many late ticks we stop trying to catch up and start from now
+const MAX_LATE_TICKS: u32 = 5;
+
+// waits for absolute deadlines so sleep overshoot doesn't add up tick after tick
+pub struct TickScheduler {
+    period: Duration,
+    deadline: Instant,
+}
+
+impl TickScheduler {
+    pub fn new(period: Duration) -> TickScheduler {
+        TickScheduler {
+            period,
+            deadline: Instant::now() + period,
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    // from the next tick on, the one already waited for keeps its deadline
+    pub fn set_period(&mut self, period: Duration) {
+        self.period = period;
+    }
+
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+
+        if now > self.deadline + self.period * MAX_LATE_TICKS {
+            warn!(target: "physics", late = ?(now - self.deadline), "tick loop is late, skipping ahead");
+            self.deadline = now + self.period;
+            return;
+        }
+
+        if self.deadline > now + SPIN_THRESHOLD {
+            thread::sleep(self.deadline - now - SPIN_THRESHOLD);
+        }
+        while Instant::now() < self.deadline {
+            thread::yield_now();
+        }
+
+        self.deadline += self.period;
+    }
+}
@@ -0,0 +1,339 @@
+use std::fs;
+use std::io;
+
+use serde::Serialize;
+
+use crate::protocol::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+// the wire layout of the codec as data, exported with SERVER_PHYSIC_SCHEMA=<file> so clients
+// generate their decoders from the build they talk to, keep it in the order the codec writes
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Type {
+    U8,
+    U16,
+    U32,
+    U64,
+    I32,
+    F32,
+    // a byte, 0 or 1
+    Bool,
+    // a u32
+    Id,
+    // utf-8 prefixed with its u16 length
+    String,
+    // opaque, prefixed with its u16 length
+    Bytes,
+    // a byte, bit i is set when flag i is
+    Flags { flags: &'static [&'static str] },
+    Option { of: &'static Type },
+    // `len` items prefixed with their count
+    List { len: &'static Type, of: &'static Type },
+    // an enum, a struct or a union of the schema
+    Named { name: &'static str },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub ty: Type,
+}
+
+// values are encoded as a u8, their index in `values`
+#[derive(Debug, Serialize)]
+pub struct Enum {
+    pub name: &'static str,
+    pub values: &'static [&'static str],
+}
+
+#[derive(Debug, Serialize)]
+pub struct Struct {
+    pub name: &'static str,
+    pub fields: &'static [Field],
+}
+
+#[derive(Debug, Serialize)]
+pub struct Variant {
+    pub name: &'static str,
+    pub tag: u8,
+    // the first protocol version with it, older clients never get it
+    pub since: u16,
+    pub fields: &'static [Field],
+}
+
+// a u8 tag followed by the fields of its variant
+#[derive(Debug, Serialize)]
+pub struct Union {
+    pub name: &'static str,
+    pub variants: &'static [Variant],
+}
+
+#[derive(Debug, Serialize)]
+pub struct Schema {
+    pub version: u16,
+    pub min_version: u16,
+    pub enums: &'static [Enum],
+    pub structs: &'static [Struct],
+    pub unions: &'static [Union],
+}
+
+const fn field(name: &'static str, ty: Type) -> Field {
+    Field { name, ty }
+}
+
+const fn variant(name: &'static str, tag: u8, fields: &'static [Field]) -> Variant {
+    Variant { name, tag, since: 1, fields }
+}
+
+const fn named(name: &'static str) -> Type {
+    Type::Named { name }
+}
+
+static ENUMS: &[Enum] = &[
+    Enum { name: "Compression", values: &["None", "Lz4", "Zstd"] },
+    Enum { name: "CommandKind", values: &["Impulse", "Move", "Jump", "Dash", "Grapple", "Reel", "Release", "Drive", "FireHitscan", "Swing"] },
+    Enum {
+        name: "RejectReason",
+        values: &["UnknownEntity", "NotOwner", "Spectator", "CommandNotAllowed", "Malformed", "ImpulseTooLarge", "SpeedTooHigh", "DashTooLong", "MatchNotRunning"],
+    },
+    Enum { name: "EntityKind", values: &["Wall", "Player", "Projectile", "Pickup", "Prop", "Vehicle", "SoftBody"] },
+    Enum { name: "DespawnReason", values: &["Removed", "Destroyed", "Expired", "OutOfBounds", "Transferred"] },
+    Enum { name: "MotionKind", values: &["Dash", "Knockback"] },
+    Enum { name: "MatchPhase", values: &["Lobby", "Countdown", "Playing", "Finished"] },
+    Enum { name: "Violation", values: &["NonFinite", "OutOfBounds", "TooFast", "Unmapped"] },
+];
+
+static STRUCTS: &[Struct] = &[
+    Struct { name: "Vector", fields: &[field("x", Type::F32), field("y", Type::F32)] },
+    Struct { name: "Transform", fields: &[field("x", Type::F32), field("y", Type::F32), field("angle", Type::F32)] },
+    Struct { name: "Velocity", fields: &[field("x", Type::F32), field("y", Type::F32), field("angular", Type::F32)] },
+    Struct { name: "ChunkId", fields: &[field("x", Type::I32), field("y", Type::I32)] },
+    Struct { name: "CameraHint", fields: &[field("target", Type::Option { of: &Type::Id }), field("zoom", Type::F32)] },
+    Struct { name: "ChunkEntry", fields: &[field("id", named("ChunkId")), field("blocks", Type::U16), field("loaded", Type::Bool)] },
+    Struct { name: "Block", fields: &[field("position", named("Vector")), field("shape", named("Shape"))] },
+    Struct {
+        name: "MaterialEntry",
+        fields: &[
+            field("name", Type::String),
+            field("sound_set", Type::Option { of: &Type::String }),
+            field("particle_set", Type::Option { of: &Type::String }),
+            field("footstep", Type::Option { of: &Type::String }),
+        ],
+    },
+    Struct { name: "MetadataValue", fields: &[field("key", Type::String), field("value", Type::String)] },
+    Struct {
+        name: "Metadata",
+        fields: &[
+            field("tags", Type::List { len: &Type::U16, of: &Type::String }),
+            field("values", Type::List { len: &Type::U16, of: &named("MetadataValue") }),
+        ],
+    },
+    Struct {
+        name: "ArenaParams",
+        fields: &[
+            field("width", Type::F32),
+            field("height", Type::F32),
+            field("obstacles", Type::U16),
+            field("min_size", Type::F32),
+            field("max_size", Type::F32),
+            field("spawn_points", Type::U16),
+        ],
+    },
+    Struct {
+        name: "EntityState",
+        fields: &[
+            field("id", Type::Id),
+            field("owner", Type::Option { of: &Type::Id }),
+            field("position", named("Transform")),
+            field("velocity", named("Velocity")),
+            field("character", Type::Option { of: &Type::Flags { flags: &["grounded", "jumping"] } }),
+            field("grapple", Type::Option { of: &named("Vector") }),
+            field("wheels", Type::List { len: &Type::U8, of: &Type::F32 }),
+            field("limbs", Type::List { len: &Type::U8, of: &named("Transform") }),
+            field("outline", Type::List { len: &Type::U8, of: &named("Vector") }),
+            field("invulnerable", Type::Option { of: &Type::U32 }),
+            field("team", Type::Option { of: &Type::U8 }),
+            field("rope", Type::List { len: &Type::U8, of: &named("Vector") }),
+        ],
+    },
+    Struct { name: "TimerState", fields: &[field("name", Type::String), field("remaining_ticks", Type::U32)] },
+    Struct { name: "RelayedCommand", fields: &[field("client", Type::Id), field("command", named("Command"))] },
+];
+
+static UNIONS: &[Union] = &[
+    Union {
+        name: "ServerMessage",
+        variants: &[
+            variant("Welcome", 0, &[
+                field("client", Type::Id),
+                field("token", Type::U64),
+                field("entity", Type::Option { of: &Type::Id }),
+                field("compression", named("Compression")),
+            ]),
+            variant("Resumed", 1, &[field("client", Type::Id), field("entity", Type::Option { of: &Type::Id })]),
+            variant("Rejected", 2, &[field("reason", Type::String)]),
+            variant("Throttled", 3, &[field("dropped", Type::U32)]),
+            variant("CommandRejected", 4, &[field("command", named("CommandKind")), field("reason", named("RejectReason"))]),
+            // the header, up to `offset`, differs per client, the rest is shared by the room
+            variant("Snapshot", 5, &[
+                field("ack", Type::Option { of: &Type::U32 }),
+                field("rtt", Type::Option { of: &Type::F32 }),
+                field("jitter", Type::F32),
+                field("offset", Type::F32),
+                field("tick", Type::U64),
+                field("keyframe", Type::Bool),
+                field("entities", Type::List { len: &Type::U16, of: &named("EntityState") }),
+                field("timers", Type::List { len: &Type::U8, of: &named("TimerState") }),
+            ]),
+            variant("Events", 6, &[field("events", Type::List { len: &Type::U16, of: &named("Event") })]),
+            variant("Ping", 7, &[field("sent_at", Type::U64)]),
+            variant("Pong", 8, &[field("client_time", Type::U64), field("tick", Type::U64), field("server_time", Type::U64)]),
+            variant("End", 9, &[]),
+            variant("ChunkManifest", 10, &[field("chunks", Type::List { len: &Type::U16, of: &named("ChunkEntry") })]),
+            variant("Chunk", 11, &[field("id", named("ChunkId")), field("blocks", Type::List { len: &Type::U16, of: &named("Block") })]),
+            variant("Arena", 12, &[field("seed", Type::U64), field("params", named("ArenaParams"))]),
+            Variant { name: "Crashed", tag: 13, since: 2, fields: &[field("restarting", Type::Bool)] },
+            Variant { name: "Relayed", tag: 14, since: 3, fields: &[field("from", Type::Id), field("channel", Type::U8), field("payload", Type::Bytes)] },
+            Variant {
+                name: "Inputs",
+                tag: 15,
+                since: 5,
+                fields: &[
+                    field("tick", Type::U64),
+                    field("commands", Type::List { len: &Type::U16, of: &named("RelayedCommand") }),
+                    field("checksum", Type::Option { of: &Type::U64 }),
+                ],
+            },
+            // xored with the quantized entities of `baseline` and run length encoded, see xor_delta.rs
+            Variant {
+                name: "SnapshotDelta",
+                tag: 16,
+                since: 7,
+                fields: &[
+                    field("tick", Type::U64),
+                    field("baseline", Type::U64),
+                    field("ack", Type::Option { of: &Type::U32 }),
+                    field("len", Type::U32),
+                    field("bytes", Type::Bytes),
+                ],
+            },
+            Variant { name: "Materials", tag: 17, since: 11, fields: &[field("materials", Type::List { len: &Type::U16, of: &named("MaterialEntry") })] },
+            Variant { name: "Feedback", tag: 18, since: 12, fields: &[field("magnitude", Type::F32), field("duration_ms", Type::U16)] },
+            Variant { name: "Camera", tag: 19, since: 13, fields: &[field("hint", named("CameraHint"))] },
+        ],
+    },
+    Union {
+        name: "Event",
+        variants: &[
+            variant("Joined", 0, &[field("client", Type::Id), field("entity", Type::Option { of: &Type::Id })]),
+            variant("Left", 1, &[field("client", Type::Id), field("entity", Type::Option { of: &Type::Id })]),
+            variant("ContactStarted", 2, &[
+                field("a", Type::Id),
+                field("b", Type::Id),
+                field("impulse", Type::F32),
+                field("point", named("Vector")),
+                field("normal", named("Vector")),
+            ]),
+            variant("ContactStopped", 3, &[field("a", Type::Id), field("b", Type::Id)]),
+            variant("AuthorityChanged", 4, &[
+                field("entity", Type::Id),
+                field("previous", Type::Option { of: &Type::Id }),
+                field("owner", Type::Option { of: &Type::Id }),
+            ]),
+            variant("Despawned", 5, &[field("entity", Type::Id), field("reason", named("DespawnReason"))]),
+            variant("Spawned", 6, &[
+                field("entity", Type::Id),
+                field("kind", named("EntityKind")),
+                field("shape", named("Shape")),
+                field("position", named("Transform")),
+                field("metadata", named("Metadata")),
+            ]),
+            variant("MetadataChanged", 7, &[field("entity", Type::Id), field("metadata", named("Metadata"))]),
+            variant("Approached", 8, &[field("a", Type::Id), field("b", Type::Id)]),
+            variant("Separated", 9, &[field("a", Type::Id), field("b", Type::Id)]),
+            variant("VelocityClamped", 10, &[field("entity", Type::Id)]),
+            variant("MotionStarted", 11, &[field("entity", Type::Id), field("motion", named("MotionKind"))]),
+            variant("MotionEnded", 12, &[field("entity", Type::Id), field("motion", named("MotionKind"))]),
+            variant("Respawned", 13, &[field("client", Type::Id), field("entity", Type::Id)]),
+            variant("MatchPhaseChanged", 14, &[field("phase", named("MatchPhase"))]),
+            variant("Scored", 15, &[field("client", Type::Id), field("points", Type::U32), field("score", Type::U32)]),
+            variant("TimerExpired", 16, &[field("name", Type::String)]),
+            Variant { name: "InvariantViolated", tag: 17, since: 2, fields: &[field("entity", Type::Id), field("violation", named("Violation"))] },
+            Variant { name: "PayloadChanged", tag: 18, since: 4, fields: &[field("entity", Type::Id), field("payload", Type::Bytes)] },
+            Variant { name: "ContinuousCollision", tag: 19, since: 6, fields: &[field("entity", Type::Id)] },
+            Variant {
+                name: "Hit",
+                tag: 20,
+                since: 8,
+                fields: &[field("shooter", Type::Id), field("target", Type::Id), field("point", named("Vector")), field("damage", Type::F32)],
+            },
+            Variant { name: "Swung", tag: 21, since: 9, fields: &[field("entity", Type::Id), field("targets", Type::List { len: &Type::U16, of: &Type::Id })] },
+            Variant {
+                name: "ImpactFx",
+                tag: 22,
+                since: 10,
+                fields: &[field("material_a", Type::U8), field("material_b", Type::U8), field("impulse", Type::F32), field("point", named("Vector"))],
+            },
+            Variant { name: "ProtocolDeprecated", tag: 23, since: 14, fields: &[field("current", Type::U16), field("minimum", Type::U16)] },
+        ],
+    },
+    Union {
+        name: "Shape",
+        variants: &[
+            variant("Cuboid", 0, &[field("half_extents", named("Vector"))]),
+            variant("Ball", 1, &[field("radius", Type::F32)]),
+            variant("Polyline", 2, &[field("points", Type::List { len: &Type::U16, of: &named("Vector") })]),
+        ],
+    },
+    // as written in input logs and lockstep inputs, the tag is the `CommandKind`
+    Union {
+        name: "Command",
+        variants: &[
+            variant("Impulse", 0, &[field("entity", Type::Id), field("impulse", named("Vector"))]),
+            variant("Move", 1, &[field("entity", Type::Id), field("direction", Type::F32)]),
+            variant("Jump", 2, &[field("entity", Type::Id), field("pressed", Type::Bool)]),
+            variant("Dash", 3, &[
+                field("entity", Type::Id),
+                field("direction", named("Vector")),
+                field("distance", Type::F32),
+                field("duration", Type::F32),
+            ]),
+            variant("Grapple", 4, &[field("entity", Type::Id), field("direction", named("Vector"))]),
+            variant("Reel", 5, &[field("entity", Type::Id), field("speed", Type::F32)]),
+            variant("Release", 6, &[field("entity", Type::Id)]),
+            variant("Drive", 7, &[field("entity", Type::Id), field("throttle", Type::F32), field("steer", Type::F32)]),
+            Variant {
+                name: "FireHitscan",
+                tag: 8,
+                since: 8,
+                fields: &[field("shooter", Type::Id), field("dir", named("Vector")), field("client_tick", Type::U64)],
+            },
+            Variant {
+                name: "Swing",
+                tag: 9,
+                since: 9,
+                fields: &[field("entity", Type::Id), field("direction", named("Vector")), field("radius", Type::F32), field("angle", Type::F32)],
+            },
+        ],
+    },
+];
+
+pub fn schema() -> Schema {
+    Schema {
+        version: PROTOCOL_VERSION,
+        min_version: MIN_PROTOCOL_VERSION,
+        enums: ENUMS,
+        structs: STRUCTS,
+        unions: UNIONS,
+    }
+}
+
+pub fn to_json() -> String {
+    serde_json::to_string_pretty(&schema()).expect("the schema serializes")
+}
+
+pub fn export(path: &str) -> io::Result<()> {
+    fs::write(path, to_json())
+}
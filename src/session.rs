@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use na::Vector2;
+use rayon::prelude::*;
+use tracing::warn;
+
+use crate::channel::Outbox;
+use crate::clock::ClockSync;
+use crate::compression::Compressor;
+use crate::protocol::{CameraHint, ClientId, Command, EntityState, Event, Frame, NetId, PROTOCOL_VERSION, Role, ServerMessage, SharedPayloads, Snapshot, TimerState};
+use crate::view::{View, ViewRequest};
+use crate::xor_delta::{self, Baselines};
+
+// events kept for a disconnected client, older ones are dropped first
+const MAX_BACKLOG: usize = 256;
+// below this many clients encoding on the room's thread is faster than handing it out
+const PARALLEL_ENCODING_SESSIONS: usize = 16;
+
+// client ids are unique across rooms so the host can route messages by client
+static NEXT_CLIENT: AtomicUsize = AtomicUsize::new(0);
+
+// token bucket: `per_tick` commands are granted every tick, up to `burst` saved for later
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub per_tick: f32,
+    pub burst: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionToken(pub u64);
+
+impl SessionToken {
+    fn generate() -> SessionToken {
+        SessionToken(rand::random())
+    }
+}
+
+pub struct Session {
+    pub client: ClientId,
+    pub token: SessionToken,
+    pub player: String,
+    pub role: Role,
+    // the protocol the client decodes, messages are downgraded to it
+    pub version: u16,
+    pub entity: Option<NetId>,
+    pub last_sequence: Option<u32>,
+    pub clock: ClockSync,
+    pub compressor: Compressor,
+    tx: Option<Sender<Frame>>,
+    outbox: Outbox,
+    scratch: Vec<u8>,
+    disconnected_at: Option<u64>,
+    backlog: Vec<Event>,
+    allowance: f32,
+    throttled: u32,
+    // relayed messages have their own budget so chat can't eat into commands
+    relay_allowance: f32,
+    // in lockstep rooms, whether the client got a keyframe to apply the inputs to
+    synced: bool,
+    // set once the client acks a snapshot, it gets xor deltas between keyframes from then on
+    baselines: Option<Baselines>,
+    pub view: View,
+    // what the client asked for, resolved again when the server caps change
+    pub view_request: ViewRequest,
+    // the last camera hint gameplay gave, kept to send again on resume
+    pub camera: Option<CameraHint>,
+}
+
+impl Session {
+    pub fn is_connected(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    // queues the message on its channel, it leaves on the next flush
+    pub fn send(&mut self, message: ServerMessage) {
+        if self.is_connected() {
+            if let Some(message) = message.downgrade(self.version) {
+                self.outbox.push(message);
+            }
+        }
+    }
+
+    // the outbox as frames, nothing is sent yet
+    fn encode(&mut self, shared: &Mutex<SharedPayloads>) -> Vec<Frame> {
+        if !self.is_connected() {
+            return vec![];
+        }
+
+        let compressor = &mut self.compressor;
+        let scratch = &mut self.scratch;
+        let version = self.version;
+        self.outbox.drain().map(|message| Frame::new(&message, version, compressor, scratch, shared)).collect()
+    }
+
+    // returns false when the connection was found dropped
+    fn send_frames(&mut self, tick: u64, frames: Vec<Frame>) -> bool {
+        let sent = match self.tx {
+            Some(ref tx) => frames.into_iter().all(|frame| tx.send(frame).is_ok()),
+            None => return true,
+        };
+
+        if !sent {
+            self.disconnect(tick);
+        }
+        sent
+    }
+
+    // returns false when the relayed message must be dropped, they're dropped silently
+    pub fn allow_relay(&mut self) -> bool {
+        if self.relay_allowance < 1.0 {
+            return false;
+        }
+
+        self.relay_allowance -= 1.0;
+        true
+    }
+
+    // returns false when the command must be dropped
+    pub fn allow_command(&mut self) -> bool {
+        if self.allowance < 1.0 {
+            self.throttled += 1;
+            return false;
+        }
+
+        self.allowance -= 1.0;
+        true
+    }
+
+    pub fn send_snapshot(&mut self, entities: Arc<Vec<EntityState>>, timers: Arc<Vec<TimerState>>, keyframe: bool, tick: u64) {
+        let ack = self.last_sequence;
+        let clock = self.clock;
+        self.send(ServerMessage::Snapshot(Snapshot { tick, keyframe, ack, clock, entities, timers }));
+    }
+
+    pub fn ack_snapshot(&mut self, tick: u64) {
+        self.baselines.get_or_insert_with(Baselines::default).ack(tick);
+    }
+
+    // keyframes stay whole, they carry what deltas leave out, and so do deltas that would not fit a frame
+    fn delta(&mut self, quantized: &Arc<Vec<u8>>, keyframe: bool, tick: u64) -> Option<ServerMessage> {
+        let baselines = self.baselines.as_mut()?;
+        baselines.sent(tick, quantized.clone());
+        if keyframe {
+            return None;
+        }
+
+        let (baseline, base) = baselines.acked()?;
+        let bytes = xor_delta::encode(base, quantized);
+        if bytes.len() > u16::max_value() as usize {
+            return None;
+        }
+        Some(ServerMessage::SnapshotDelta { tick, baseline, ack: self.last_sequence, len: quantized.len() as u32, bytes })
+    }
+
+    pub fn take_backlog(&mut self) -> Vec<Event> {
+        self.backlog.drain(..).collect()
+    }
+
+    fn disconnect(&mut self, tick: u64) {
+        if self.tx.take().is_some() {
+            self.outbox.clear();
+            self.synced = false;
+            self.disconnected_at = Some(tick);
+        }
+    }
+
+    fn push_backlog(&mut self, events: &[Event]) {
+        self.backlog.extend_from_slice(events);
+
+        if self.backlog.len() > MAX_BACKLOG {
+            let overflow = self.backlog.len() - MAX_BACKLOG;
+            self.backlog.drain(..overflow);
+        }
+    }
+}
+
+pub struct Sessions {
+    grace_ticks: u64,
+    rate_limit: RateLimit,
+    relay_limit: RateLimit,
+    sessions: HashMap<ClientId, Session>,
+    // spent encoding frames in the flushes since the last take
+    encoding: Duration,
+}
+
+impl Sessions {
+    pub fn new(grace_ticks: u64, rate_limit: RateLimit, relay_limit: RateLimit) -> Sessions {
+        Sessions {
+            grace_ticks,
+            rate_limit,
+            relay_limit,
+            sessions: HashMap::new(),
+            encoding: Duration::default(),
+        }
+    }
+
+    pub fn open(&mut self, player: String, role: Role, version: u16, entity: Option<NetId>, compressor: Compressor, tx: Sender<Frame>) -> &mut Session {
+        if version < PROTOCOL_VERSION {
+            warn!(target: "protocol", player = %player, version, current = PROTOCOL_VERSION, "client joined with a deprecated protocol version");
+        }
+        let client = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+
+        self.sessions.entry(client).or_insert(Session {
+            client,
+            token: SessionToken::generate(),
+            player,
+            role,
+            version,
+            entity,
+            last_sequence: None,
+            clock: ClockSync::default(),
+            compressor,
+            tx: Some(tx),
+            outbox: Outbox::default(),
+            scratch: vec![],
+            disconnected_at: None,
+            backlog: vec![],
+            allowance: self.rate_limit.burst,
+            throttled: 0,
+            relay_allowance: self.relay_limit.burst,
+            synced: false,
+            baselines: None,
+            view: View::default(),
+            view_request: ViewRequest::default(),
+            camera: None,
+        })
+    }
+
+    // a client can only resume a session that is still within its grace window,
+    // the sender is given back when the token is unknown so the caller can reject it
+    pub fn resume(&mut self, token: SessionToken, tx: Sender<Frame>) -> Result<&mut Session, Sender<Frame>> {
+        match self.sessions.values_mut().find(|session| session.token == token) {
+            Some(session) => {
+                session.tx = Some(tx);
+                session.disconnected_at = None;
+                Ok(session)
+            },
+            None => Err(tx),
+        }
+    }
+
+    // the session leaves with its client and carries on in another room
+    pub fn take(&mut self, client: ClientId) -> Option<Session> {
+        self.sessions.remove(&client)
+    }
+
+    pub fn adopt(&mut self, session: Session) -> &mut Session {
+        let client = session.client;
+        self.sessions.entry(client).or_insert(session)
+    }
+
+    // every session, in client order, when the room goes away under them
+    pub fn drain(&mut self) -> Vec<Session> {
+        let mut sessions: Vec<Session> = self.sessions.drain().map(|(_, session)| session).collect();
+        sessions.sort_by_key(|session| session.client);
+        sessions
+    }
+
+    // connected players, spectators and dropped clients don't count
+    pub fn players(&self) -> usize {
+        self.sessions.values().filter(|session| session.role == Role::Player && session.is_connected()).count()
+    }
+
+    // messages held for clients, sent or not yet
+    pub fn queued(&self) -> usize {
+        self.sessions.values().map(|session| session.outbox.len() + session.backlog.len()).sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.values()
+    }
+
+    pub fn contains(&self, client: ClientId) -> bool {
+        self.sessions.contains_key(&client)
+    }
+
+    pub fn token(&self, client: ClientId) -> Option<SessionToken> {
+        self.sessions.get(&client).map(|session| session.token)
+    }
+
+    pub fn find(&self, token: SessionToken) -> Option<&Session> {
+        self.sessions.values().find(|session| session.token == token)
+    }
+
+    pub fn get_mut(&mut self, client: ClientId) -> Option<&mut Session> {
+        self.sessions.get_mut(&client).filter(|session| session.is_connected())
+    }
+
+    // a config reload, the allowances saved up are kept within the new bursts
+    pub fn set_limits(&mut self, rate_limit: RateLimit, relay_limit: RateLimit) {
+        self.rate_limit = rate_limit;
+        self.relay_limit = relay_limit;
+
+        for session in self.sessions.values_mut() {
+            session.allowance = session.allowance.min(rate_limit.burst);
+            session.relay_allowance = session.relay_allowance.min(relay_limit.burst);
+        }
+    }
+
+    // the views of every session, dropped clients too, from what they asked for
+    pub fn resolve_views(&mut self, resolve: impl Fn(ViewRequest) -> View) {
+        for session in self.sessions.values_mut() {
+            session.view = resolve(session.view_request);
+        }
+    }
+
+    pub fn refill(&mut self) {
+        let (rate_limit, relay_limit) = (self.rate_limit, self.relay_limit);
+
+        for session in self.sessions.values_mut() {
+            session.allowance = (session.allowance + rate_limit.per_tick).min(rate_limit.burst);
+            session.relay_allowance = (session.relay_allowance + relay_limit.per_tick).min(relay_limit.burst);
+        }
+    }
+
+    // tells clients how many of their commands were dropped this tick
+    pub fn report_throttled(&mut self) {
+        for session in self.sessions.values_mut() {
+            if session.throttled == 0 {
+                continue;
+            }
+
+            warn!(target: "physics", client = session.client, dropped = session.throttled, "client exceeded its command rate");
+            let dropped = session.throttled;
+            session.throttled = 0;
+            session.send(ServerMessage::Throttled { dropped });
+        }
+    }
+
+    pub fn disconnect(&mut self, client: ClientId, tick: u64) {
+        if let Some(session) = self.sessions.get_mut(&client) {
+            session.disconnect(tick);
+        }
+    }
+
+    // removes and returns sessions whose grace window is over
+    pub fn expire(&mut self, tick: u64) -> Vec<Session> {
+        let grace_ticks = self.grace_ticks;
+        let mut expired: Vec<ClientId> = self.sessions.values()
+            .filter(|session| match session.disconnected_at {
+                Some(at) => tick - at >= grace_ticks,
+                None => false,
+            })
+            .map(|session| session.client)
+            .collect();
+        // in client order so despawns, and the net ids they free, don't depend on the map
+        expired.sort();
+
+        expired.iter()
+            .filter_map(|client| self.sessions.remove(client))
+            .collect()
+    }
+
+    // to `to` or every other client of the room, the ones on a protocol without relaying are skipped
+    pub fn relay(&mut self, from: ClientId, to: Option<ClientId>, channel: u8, payload: &[u8]) {
+        for session in self.sessions.values_mut().filter(|session| session.client != from && to.map_or(true, |to| to == session.client)) {
+            session.send(ServerMessage::Relayed { from, channel, payload: payload.to_vec() });
+        }
+    }
+
+    pub fn broadcast(&mut self, message: &ServerMessage) {
+        for session in self.sessions.values_mut() {
+            session.send(message.clone());
+        }
+    }
+
+    // returns the clients whose connection dropped, every frame is encoded before the first is sent
+    // so the time spent encoding can be told apart, on rayon's threads once there are enough clients
+    pub fn flush(&mut self, tick: u64) -> Vec<ClientId> {
+        let shared = Mutex::new(SharedPayloads::default());
+
+        let started_at = Instant::now();
+        let encoded: Vec<(&mut Session, Vec<Frame>)> = if self.sessions.len() >= PARALLEL_ENCODING_SESSIONS {
+            self.sessions.par_iter_mut().map(|(_, session)| {
+                let frames = session.encode(&shared);
+                (session, frames)
+            }).collect()
+        } else {
+            self.sessions.values_mut().map(|session| {
+                let frames = session.encode(&shared);
+                (session, frames)
+            }).collect()
+        };
+        self.encoding += started_at.elapsed();
+
+        let mut dropped: Vec<ClientId> = encoded.into_iter()
+            .filter_map(|(session, frames)| if session.send_frames(tick, frames) { None } else { Some(session.client) })
+            .collect();
+        dropped.sort();
+        dropped
+    }
+
+    pub fn take_encoding(&mut self) -> Duration {
+        mem::replace(&mut self.encoding, Duration::default())
+    }
+
+    // whether the room has to quantize its snapshots for `broadcast_snapshot`
+    pub fn wants_deltas(&self) -> bool {
+        self.sessions.values().any(|session| session.baselines.is_some())
+    }
+
+    // spectators only follow keyframes, they don't need the full rate, `quantized` is every entity
+    // of the room and only read for the clients on deltas, clients with a focused view only get what
+    // they see from the position `focus` gives for their entity and never get deltas, they'd cover everything
+    pub fn broadcast_snapshot(
+        &mut self,
+        entities: &Arc<Vec<EntityState>>,
+        quantized: &Arc<Vec<u8>>,
+        timers: &Arc<Vec<TimerState>>,
+        keyframe: bool,
+        tick: u64,
+        focus: impl Fn(NetId) -> Option<Vector2<f32>>,
+        occluded: impl Fn(Vector2<f32>, Vector2<f32>) -> bool,
+    ) {
+        for session in self.sessions.values_mut() {
+            if (session.role == Role::Spectator && !keyframe) || !session.view.wants(tick, keyframe) {
+                continue;
+            }
+
+            let center = session.entity.filter(|_| session.view.is_focused()).and_then(&focus);
+            match center {
+                Some(center) => {
+                    let view = session.view;
+                    let seen = entities.iter().filter(|state| view.sees(center, state, &occluded)).cloned().collect();
+                    session.send_snapshot(Arc::new(seen), timers.clone(), keyframe, tick);
+                },
+                None => {
+                    let delta = if !session.view.is_focused() { session.delta(quantized, keyframe, tick) } else { None };
+                    match delta {
+                        Some(delta) => session.send(delta),
+                        None => session.send_snapshot(entities.clone(), timers.clone(), keyframe, tick),
+                    }
+                },
+            }
+        }
+    }
+
+    // the client's checksum didn't match, it waits for the next keyframe like a joining one
+    pub fn resync(&mut self, client: ClientId) {
+        if let Some(session) = self.sessions.get_mut(&client) {
+            session.synced = false;
+        }
+    }
+
+    pub fn resync_all(&mut self) {
+        for session in self.sessions.values_mut() {
+            session.synced = false;
+        }
+    }
+
+    // clients that aren't synced wait for the next keyframe, the inputs of its tick are already in it
+    pub fn broadcast_inputs(&mut self, inputs: &Arc<Vec<(ClientId, Command)>>, checksum: Option<u64>, entities: &Arc<Vec<EntityState>>, timers: &Arc<Vec<TimerState>>, keyframe: bool, tick: u64) {
+        for session in self.sessions.values_mut().filter(|session| session.is_connected()) {
+            if !session.synced {
+                if keyframe {
+                    session.send_snapshot(entities.clone(), timers.clone(), true, tick);
+                    session.synced = true;
+                }
+                continue;
+            }
+
+            session.send(ServerMessage::Inputs { tick, commands: inputs.clone(), checksum });
+        }
+    }
+
+    // connected clients get the events right away, the others keep them for when they resume
+    pub fn publish(&mut self, events: &[Event]) {
+        if events.is_empty() {
+            return;
+        }
+
+        for session in self.sessions.values_mut() {
+            if session.is_connected() {
+                session.send(ServerMessage::Events(events.to_vec()));
+            } else {
+                session.push_backlog(events);
+            }
+        }
+    }
+}
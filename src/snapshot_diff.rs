@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use na::{Isometry2, Vector2};
+use nphysics2d::algebra::Velocity2;
+
+use crate::protocol::{EntityState, NetId};
+use crate::replay::{self, InputLog, ReplayError, Replayer};
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    Added(EntityState),
+    Removed(EntityState),
+    // how far the entity went between the two states
+    Moved { translation: f32, rotation: f32, velocity: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Delta {
+    pub entity: NetId,
+    pub change: Change,
+}
+
+// entities are matched by id, the ones that moved by `threshold` or less in every way are left out
+pub fn diff(before: &[EntityState], after: &[EntityState], threshold: f32) -> Vec<Delta> {
+    let mut entities: BTreeMap<NetId, (Option<&EntityState>, Option<&EntityState>)> = BTreeMap::new();
+    for state in before {
+        entities.entry(state.id).or_insert((None, None)).0 = Some(state);
+    }
+    for state in after {
+        entities.entry(state.id).or_insert((None, None)).1 = Some(state);
+    }
+
+    entities.into_iter().filter_map(|(entity, states)| {
+        let change = match states {
+            (Some(before), None) => Change::Removed(before.clone()),
+            (None, Some(after)) => Change::Added(after.clone()),
+            (Some(before), Some(after)) => {
+                let translation = (after.position.translation.vector - before.position.translation.vector).norm();
+                let rotation = before.position.rotation.angle_to(&after.position.rotation).abs();
+                let velocity = (after.velocity.linear - before.velocity.linear).norm();
+                // NaN never compares greater, it always makes the cut
+                if !(translation <= threshold && rotation <= threshold && velocity <= threshold) {
+                    Change::Moved { translation, rotation, velocity }
+                } else {
+                    return None;
+                }
+            },
+            (None, None) => return None,
+        };
+        Some(Delta { entity, change })
+    }).collect()
+}
+
+fn describe(state: &EntityState) -> String {
+    format!("at ({}, {}) angle {}", state.position.translation.vector.x, state.position.translation.vector.y, state.position.rotation.angle())
+}
+
+pub fn report(deltas: &[Delta]) -> String {
+    let mut lines: Vec<String> = deltas.iter().map(|delta| match delta.change {
+        Change::Added(ref state) => format!("+ entity {} {}", delta.entity, describe(state)),
+        Change::Removed(ref state) => format!("- entity {} {}", delta.entity, describe(state)),
+        Change::Moved { translation, rotation, velocity } => format!("~ entity {} moved {} turned {} velocity changed by {}", delta.entity, translation, rotation, velocity),
+    }).collect();
+
+    let (mut added, mut removed, mut moved) = (0, 0, 0);
+    for delta in deltas {
+        match delta.change {
+            Change::Added(_) => added += 1,
+            Change::Removed(_) => removed += 1,
+            Change::Moved { .. } => moved += 1,
+        }
+    }
+    lines.push(format!("{} added, {} removed, {} moved", added, removed, moved));
+    lines.join("\n")
+}
+
+// reads back a golden file, see `golden::summary`
+pub fn parse_summary(summary: &str) -> Result<Vec<EntityState>, String> {
+    summary.lines().enumerate().map(|(i, line)| {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let id = words.get(1).and_then(|id| id.parse::<NetId>().ok());
+        let numbers: Option<Vec<f32>> = [3, 4, 5, 7, 8, 9].iter().map(|at| words.get(*at)?.parse().ok()).collect();
+
+        match (words.get(0), id, words.get(2), words.get(6), numbers) {
+            (Some(&"entity"), Some(id), Some(&"position"), Some(&"velocity"), Some(n)) if words.len() == 10 => Ok(EntityState::new(
+                id,
+                None,
+                Isometry2::new(Vector2::new(n[0], n[1]), n[2]),
+                Velocity2::new(Vector2::new(n[3], n[4]), n[5]),
+            )),
+            _ => Err(format!("line {}: not an entity summary", i + 1)),
+        }
+    }).collect()
+}
+
+// replays the input log and compares the room at `from` with the room at `to`, the last tick when `None`
+pub fn run(log_path: &str, from: u64, to: Option<u64>, threshold: f32) -> Result<(), ReplayError> {
+    let log = InputLog::load(log_path)?;
+    let mut replayer = Replayer::new(&log)?;
+    let last = to.unwrap_or(log.ticks.saturating_sub(1));
+    if from > last {
+        return Err(ReplayError::Invalid(format!("can't diff tick {} against the earlier tick {}", from, last)));
+    }
+
+    let mut before = None;
+    for (tick, inputs) in replay::ticks(&log).take_while(|(tick, _)| *tick <= last) {
+        replayer.step(tick, inputs);
+        if tick == from {
+            before = Some(replayer.room.states());
+        }
+    }
+
+    let before = before.ok_or_else(|| ReplayError::Invalid(format!("the log stops before tick {}", from)))?;
+    println!("{}", report(&diff(&before, &replayer.room.states(), threshold)));
+    Ok(())
+}
+
+// two saved states, written by the golden checks
+pub fn run_files(before_path: &str, after_path: &str, threshold: f32) -> Result<(), ReplayError> {
+    let read = |path: &str| -> Result<Vec<EntityState>, ReplayError> {
+        let summary = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        parse_summary(&summary).map_err(|reason| ReplayError::Invalid(format!("{}: {}", path, reason)))
+    };
+
+    println!("{}", report(&diff(&read(before_path)?, &read(after_path)?, threshold)));
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::shape::{Ball, ShapeHandle};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::object::{BodyHandle, Material};
+use nphysics2d::volumetric::Volumetric;
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+
+// the nphysics version we build against has no deformable bodies, a soft body is a mass-spring
+// system of small rigid particles instead: a ring for the surface around a center particle
+const RING_PARTICLES: usize = 12;
+const PARTICLE_DENSITY: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Spring {
+    pub a: BodyHandle,
+    pub b: BodyHandle,
+    pub rest_length: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoftBody {
+    // the ring particles in order around the center, which is the entity body
+    pub surface: Vec<BodyHandle>,
+    pub springs: Vec<Spring>,
+    pub stiffness: f32,
+    pub damping: f32,
+    // surface points relative to the center as last replicated, refreshed at a reduced rate
+    pub outline: Vec<Point2<f32>>,
+}
+
+fn add_particle(world: &mut World<f32>, margin: f32, position: Vector2<f32>, radius: f32, groups: CollisionGroups) -> PhysicsBody {
+    let shape = ShapeHandle::new(Ball::new(radius - margin));
+    let inertia = shape.inertia(PARTICLE_DENSITY);
+    let center_of_mass = shape.center_of_mass();
+    let body = world.add_rigid_body(Isometry2::new(position, 0.0), inertia, center_of_mass);
+    let collider = world.add_collider(margin, shape, body, Isometry2::identity(), Material::new(0.0, 0.5));
+    world.collision_world_mut().set_collision_groups(collider, groups);
+
+    PhysicsBody { collider, body }
+}
+
+fn spring(world: &World<f32>, a: BodyHandle, b: BodyHandle) -> Option<Spring> {
+    let a_position = world.rigid_body(a)?.position().translation.vector;
+    let b_position = world.rigid_body(b)?.position().translation.vector;
+
+    Some(Spring { a, b, rest_length: (b_position - a_position).norm() })
+}
+
+// a round blob of `radius`, ring particles are linked to their neighbours, to the ones two steps
+// away so the surface resists folding, and to the center so it keeps its volume
+pub fn build(world: &mut World<f32>, margin: f32, position: Vector2<f32>, radius: f32, groups: CollisionGroups) -> (PhysicsBody, SoftBody) {
+    let particle_radius = radius * std::f32::consts::PI / RING_PARTICLES as f32 * 0.8;
+    let center = add_particle(world, margin, position, particle_radius, groups);
+
+    let surface: Vec<BodyHandle> = (0..RING_PARTICLES).map(|i| {
+        let angle = i as f32 / RING_PARTICLES as f32 * std::f32::consts::PI * 2.0;
+        let offset = Vector2::new(angle.cos(), angle.sin()) * (radius - particle_radius);
+        add_particle(world, margin, position + offset, particle_radius, groups).body
+    }).collect();
+
+    let mut springs = vec![];
+    for i in 0..RING_PARTICLES {
+        let pairs = [
+            (center.body, surface[i]),
+            (surface[i], surface[(i + 1) % RING_PARTICLES]),
+            (surface[i], surface[(i + 2) % RING_PARTICLES]),
+        ];
+        springs.extend(pairs.iter().filter_map(|&(a, b)| spring(world, a, b)));
+    }
+
+    let mut soft_body = SoftBody {
+        surface,
+        springs,
+        stiffness: 400.0,
+        damping: 10.0,
+        outline: vec![],
+    };
+    soft_body.outline = outline(world, &center, &soft_body);
+
+    (center, soft_body)
+}
+
+// runs before each step, spring and damping forces are applied as velocity changes
+pub fn apply_springs(world: &mut World<f32>, entities: &hecs::World) {
+    let timestep = world.timestep();
+
+    for (_, soft_body) in entities.query::<&SoftBody>().iter() {
+        for spring in soft_body.springs.iter() {
+            let (a_position, a_velocity, a_mass) = match world.rigid_body(spring.a) {
+                Some(rigid_body) => (rigid_body.position().translation.vector, rigid_body.velocity().linear, rigid_body.local_inertia().linear),
+                None => continue,
+            };
+            let (b_position, b_velocity, b_mass) = match world.rigid_body(spring.b) {
+                Some(rigid_body) => (rigid_body.position().translation.vector, rigid_body.velocity().linear, rigid_body.local_inertia().linear),
+                None => continue,
+            };
+
+            let offset = b_position - a_position;
+            let length = offset.norm();
+            if length == 0.0 || a_mass <= 0.0 || b_mass <= 0.0 {
+                continue;
+            }
+            let axis = offset / length;
+
+            let stretch = length - spring.rest_length;
+            let closing = (b_velocity - a_velocity).dot(&axis);
+            let impulse = axis * ((soft_body.stiffness * stretch + soft_body.damping * closing) * timestep);
+
+            if let Some(rigid_body) = world.rigid_body_mut(spring.a) {
+                rigid_body.set_linear_velocity(a_velocity + impulse / a_mass);
+                rigid_body.activate();
+            }
+            if let Some(rigid_body) = world.rigid_body_mut(spring.b) {
+                rigid_body.set_linear_velocity(b_velocity - impulse / b_mass);
+                rigid_body.activate();
+            }
+        }
+    }
+}
+
+// surface points around the center particle, clients triangulate it as a fan from the center
+pub fn outline(world: &World<f32>, center: &PhysicsBody, soft_body: &SoftBody) -> Vec<Point2<f32>> {
+    let center = match world.rigid_body(center.body) {
+        Some(rigid_body) => rigid_body.position().translation.vector,
+        None => return vec![],
+    };
+
+    soft_body.surface.iter()
+        .filter_map(|body| world.rigid_body(*body))
+        .map(|rigid_body| Point2::from(rigid_body.position().translation.vector - center))
+        .collect()
+}
+
+// the outline is heavier than the rest of the entity state, it's only refreshed every few ticks
+pub fn refresh_outlines(world: &World<f32>, entities: &hecs::World) {
+    for (_, (physics_body, soft_body)) in entities.query::<(&PhysicsBody, &mut SoftBody)>().iter() {
+        soft_body.outline = outline(world, physics_body, soft_body);
+    }
+}
@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use na::Point2;
+use ncollide2d::bounding_volume::AABB;
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::world::World;
+
+use crate::net_ids::NetIds;
+use crate::protocol::NetId;
+
+// regions are grown to this grid so queries that nearly match share an answer
+const CELL: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Region {
+    mins: (i32, i32),
+    maxs: (i32, i32),
+}
+
+impl Region {
+    fn new(mins: Point2<f32>, maxs: Point2<f32>) -> Region {
+        Region {
+            mins: ((mins.x / CELL).floor() as i32, (mins.y / CELL).floor() as i32),
+            maxs: ((maxs.x / CELL).ceil() as i32, (maxs.y / CELL).ceil() as i32),
+        }
+    }
+
+    fn aabb(&self) -> AABB<f32> {
+        AABB::new(
+            Point2::new(self.mins.0 as f32 * CELL, self.mins.1 as f32 * CELL),
+            Point2::new(self.maxs.0 as f32 * CELL, self.maxs.1 as f32 * CELL),
+        )
+    }
+}
+
+// the replicated entities whose collider overlaps a region, looked up in the broad phase once per
+// region and tick, emptied after every step since bodies moved, the region being grown to whole
+// cells the answer can hold entities just outside of what was asked
+#[derive(Default)]
+pub struct SpatialCache {
+    regions: RefCell<HashMap<Region, Arc<Vec<NetId>>>>,
+}
+
+impl SpatialCache {
+    pub fn clear(&mut self) {
+        self.regions.get_mut().clear();
+    }
+
+    pub fn entities_in(&self, world: &World<f32>, net_ids: &NetIds, mins: Point2<f32>, maxs: Point2<f32>) -> Arc<Vec<NetId>> {
+        let region = Region::new(mins, maxs);
+        if let Some(entities) = self.regions.borrow().get(&region) {
+            return entities.clone();
+        }
+
+        let everything = CollisionGroups::new();
+        let mut entities: Vec<NetId> = world.collision_world().interferences_with_aabb(&region.aabb(), &everything)
+            .filter_map(|object| net_ids.by_collider(object.handle()))
+            .collect();
+        entities.sort();
+        entities.dedup();
+
+        let entities = Arc::new(entities);
+        self.regions.borrow_mut().insert(region, entities.clone());
+        entities
+    }
+}
@@ -0,0 +1,50 @@
+use na::Vector2;
+
+use crate::level::SpawnerDefinition;
+use crate::net_ids::NetIds;
+use crate::protocol::NetId;
+use crate::room::SpawnDescriptor;
+
+// emits entities on a fixed interval, it waits while its entities are at the cap and the first
+// one after that comes a full interval after a slot frees up
+pub struct Spawner {
+    pub position: Vector2<f32>,
+    definition: SpawnerDefinition,
+    countdown: u64,
+    alive: Vec<NetId>,
+}
+
+impl Spawner {
+    pub fn new(definition: SpawnerDefinition) -> Spawner {
+        Spawner {
+            position: Vector2::new(definition.position[0], definition.position[1]),
+            countdown: definition.interval_ticks,
+            definition,
+            alive: vec![],
+        }
+    }
+
+    // called once per tick, true when an entity has to be emitted now
+    pub fn tick(&mut self, net_ids: &NetIds) -> bool {
+        self.alive.retain(|id| net_ids.entity(*id).is_some());
+        if self.alive.len() >= self.definition.max_alive {
+            self.countdown = self.definition.interval_ticks;
+            return false;
+        }
+
+        self.countdown = self.countdown.saturating_sub(1);
+        if self.countdown > 0 {
+            return false;
+        }
+        self.countdown = self.definition.interval_ticks;
+        true
+    }
+
+    pub fn spawned(&mut self, id: NetId) {
+        self.alive.push(id);
+    }
+
+    pub fn descriptor(&self) -> SpawnDescriptor {
+        self.definition.template.descriptor()
+    }
+}
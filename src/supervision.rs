@@ -0,0 +1,44 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+
+// runs `f`, a panic comes back as its message instead of unwinding further
+pub fn guard<F: FnOnce()>(f: F) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| message(&*payload))
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+// a room that keeps crashing is left down after `max` restarts
+pub struct Restarts {
+    max: u32,
+    counts: HashMap<String, u32>,
+}
+
+impl Restarts {
+    pub fn new(max: u32) -> Restarts {
+        Restarts {
+            max,
+            counts: HashMap::new(),
+        }
+    }
+
+    // counts the restart when it's allowed
+    pub fn allow(&mut self, room: &str) -> bool {
+        let count = self.counts.entry(room.to_string()).or_insert(0);
+        if *count >= self.max {
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+}
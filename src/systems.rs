@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+
+use hecs::Entity;
+use na::{Point2, Vector2};
+use ncollide2d::bounding_volume::BoundingVolume;
+use ncollide2d::events::ContactEvent;
+use nphysics2d::object::ColliderHandle;
+use nphysics2d::world::World;
+
+use crate::controller::CharacterController;
+use crate::grapple::Grapple;
+use crate::impact_fx::ImpactLimiter;
+use crate::ragdoll::{self, Ragdoll};
+use crate::rope::Rope;
+use crate::soft_body::SoftBody;
+use crate::vehicle::{self, Vehicle};
+use crate::components::{Continuous, Damping, GravityScale, Health, Invulnerable, Owner, Payload, PhysicsBody, Proximity, Replicated, Team};
+use crate::net_ids::NetIds;
+use crate::protocol::{CharacterState, ClientId, EntityKind, EntityState, Event, Metadata, NetId, Shape};
+
+// single read-only pass over the entities, the physics world is never borrowed mutably while extracting,
+// static entities have no rigid body and are only described by their spawn event
+pub fn replicate(world: &World<f32>, entities: &hecs::World, states: &mut Vec<EntityState>) {
+    states.clear();
+
+    let mut query = entities.query::<(&PhysicsBody, &Replicated, Option<&Owner>, Option<&CharacterController>, Option<&Grapple>, Option<&Vehicle>, Option<&Ragdoll>, Option<&SoftBody>, Option<&Rope>, Option<&Invulnerable>, Option<&Team>)>();
+    states.extend(query.iter().filter_map(|(_, (physics_body, replicated, owner, controller, grapple, vehicle, ragdoll, soft_body, rope, invulnerable, team))| {
+        let rigid_body = world.rigid_body(physics_body.body)?;
+
+        let mut state = EntityState::new(replicated.id, owner.map(|owner| owner.0), *rigid_body.position(), *rigid_body.velocity());
+        state.character = controller.map(|controller| CharacterState { grounded: controller.grounded, jumping: controller.jumping });
+        state.grapple = grapple.map(|grapple| grapple.end);
+        state.invulnerable = invulnerable.map(|invulnerable| invulnerable.0 as u32);
+        state.team = team.map(|team| team.0);
+        if let Some(vehicle) = vehicle {
+            state.wheels = vehicle::wheel_angles(world, physics_body, vehicle);
+        }
+        if let Some(ragdoll) = ragdoll {
+            state.limbs = ragdoll::limb_positions(world, ragdoll);
+        }
+        if let Some(soft_body) = soft_body {
+            state.outline = soft_body.outline.clone();
+        }
+        if let Some(rope) = rope {
+            state.rope = rope.polyline.clone();
+        }
+        Some(state)
+    }));
+}
+
+fn spawn_event(world: &World<f32>, physics_body: &PhysicsBody, replicated: &Replicated, kind: EntityKind, shape: Shape, metadata: &Metadata) -> Option<Event> {
+    let collider = world.collider(physics_body.collider)?;
+
+    Some(Event::Spawned { entity: replicated.id, kind, shape, position: *collider.position(), metadata: metadata.clone() })
+}
+
+pub fn spawned(world: &World<f32>, entities: &hecs::World, entity: Entity) -> Option<Event> {
+    let physics_body = entities.get::<PhysicsBody>(entity).ok()?;
+    let replicated = entities.get::<Replicated>(entity).ok()?;
+    let kind = *entities.get::<EntityKind>(entity).ok()?;
+    let shape = entities.get::<Shape>(entity).ok()?.clone();
+    let metadata = entities.get::<Metadata>(entity).ok()?;
+
+    spawn_event(world, &physics_body, &replicated, kind, shape, &metadata)
+}
+
+// everything a client joining now has to instantiate before the first keyframe, payloads and
+// continuous collision flags follow the spawns
+pub fn spawns(world: &World<f32>, entities: &hecs::World) -> Vec<Event> {
+    let mut query = entities.query::<(&PhysicsBody, &Replicated, &EntityKind, &Shape, &Metadata)>();
+    let mut events: Vec<Event> = query.iter()
+        .filter_map(|(_, (physics_body, replicated, kind, shape, metadata))| spawn_event(world, physics_body, replicated, *kind, shape.clone(), metadata))
+        .collect();
+
+    events.extend(entities.query::<(&Replicated, &Payload)>().iter()
+        .map(|(_, (replicated, payload))| Event::PayloadChanged { entity: replicated.id, payload: payload.0.clone() }));
+    events.extend(entities.query::<(&Replicated, &Continuous)>().iter()
+        .map(|(_, (replicated, _))| Event::ContinuousCollision { entity: replicated.id }));
+    events
+}
+
+// nphysics applies the same gravity to every body, the difference is made up before each step
+pub fn scale_gravity(world: &mut World<f32>, entities: &hecs::World) {
+    let gravity = *world.gravity();
+    if gravity == Vector2::zeros() {
+        return;
+    }
+
+    let timestep = world.timestep();
+    for (_, (physics_body, scale)) in entities.query::<(&PhysicsBody, &GravityScale)>().iter() {
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            // sleeping bodies don't get gravity either
+            if rigid_body.is_active() {
+                let velocity = rigid_body.velocity().linear + gravity * (scale.0 - 1.0) * timestep;
+                rigid_body.set_linear_velocity(velocity);
+            }
+        }
+    }
+}
+
+// same formula as most engines, `v / (1 + dt * damping)`, so it stays stable with large dampings
+pub fn damp(world: &mut World<f32>, entities: &hecs::World) {
+    let timestep = world.timestep();
+
+    for (_, (physics_body, damping)) in entities.query::<(&PhysicsBody, &Damping)>().iter() {
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            if rigid_body.is_active() {
+                let velocity = *rigid_body.velocity();
+                rigid_body.set_linear_velocity(velocity.linear / (1.0 + timestep * damping.linear));
+                rigid_body.set_angular_velocity(velocity.angular / (1.0 + timestep * damping.angular));
+            }
+        }
+    }
+}
+
+pub fn clamp_velocities(world: &mut World<f32>, entities: &hecs::World, max_linear: f32, max_angular: f32, events: &mut Vec<Event>) {
+    for (_, (physics_body, replicated)) in entities.query::<(&PhysicsBody, &Replicated)>().iter() {
+        if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+            let velocity = *rigid_body.velocity();
+            let speed = velocity.linear.norm();
+            // a NaN never compares greater, it's caught separately
+            let linear_clamped = speed > max_linear || !speed.is_finite();
+            let angular_clamped = velocity.angular.abs() > max_angular || !velocity.angular.is_finite();
+
+            if linear_clamped {
+                let linear = if speed.is_finite() { velocity.linear * (max_linear / speed) } else { Vector2::zeros() };
+                rigid_body.set_linear_velocity(linear);
+            }
+            if angular_clamped {
+                let angular = if velocity.angular.is_finite() { max_angular.copysign(velocity.angular) } else { 0.0 };
+                rigid_body.set_angular_velocity(angular);
+            }
+            if linear_clamped || angular_clamped {
+                events.push(Event::VelocityClamped { entity: replicated.id });
+            }
+        }
+    }
+}
+
+// taken right before a step, impacts are measured from the velocity change across it
+pub fn velocities(world: &World<f32>, entities: &hecs::World) -> HashMap<NetId, Vector2<f32>> {
+    let mut query = entities.query::<(&PhysicsBody, &Replicated)>();
+    query.iter()
+        .filter_map(|(_, (physics_body, replicated))| {
+            world.rigid_body(physics_body.body).map(|rigid_body| (replicated.id, rigid_body.velocity().linear))
+        })
+        .collect()
+}
+
+// nphysics keeps its solver impulses to itself, so this is the momentum change of the body over the
+// step minus gravity, static bodies have none
+fn impact_impulse(world: &World<f32>, entities: &hecs::World, net_ids: &NetIds, before: &HashMap<NetId, Vector2<f32>>, id: NetId) -> f32 {
+    let physics_body = match net_ids.entity(id).and_then(|entity| entities.get::<PhysicsBody>(entity).ok()) {
+        Some(physics_body) => *physics_body,
+        None => return 0.0,
+    };
+
+    match (world.rigid_body(physics_body.body), before.get(&id)) {
+        (Some(rigid_body), Some(velocity)) => {
+            let change = rigid_body.velocity().linear - velocity - world.gravity() * world.timestep();
+            change.norm() * rigid_body.local_inertia().linear
+        },
+        _ => 0.0,
+    }
+}
+
+// deepest point of contact between the two colliders with the normal pointing from `a` to `b`
+fn contact_point(world: &World<f32>, a: ColliderHandle, b: ColliderHandle) -> Option<(Point2<f32>, Vector2<f32>)> {
+    let mut manifolds = vec![];
+
+    for (object_a, object_b, algorithm) in world.collision_world().contact_pairs() {
+        let flipped = if object_a.handle() == a && object_b.handle() == b {
+            false
+        } else if object_a.handle() == b && object_b.handle() == a {
+            true
+        } else {
+            continue;
+        };
+
+        algorithm.contacts(&mut manifolds);
+        let deepest = manifolds.iter()
+            .filter_map(|manifold| manifold.deepest_contact())
+            .max_by(|x, y| x.contact.depth.partial_cmp(&y.contact.depth).unwrap_or(std::cmp::Ordering::Equal))?;
+
+        let point = na::center(&deepest.contact.world1, &deepest.contact.world2);
+        let normal = deepest.contact.normal.into_inner();
+        return Some((point, if flipped { -normal } else { normal }));
+    }
+
+    None
+}
+
+// the hardest impact each client's entities took in the step, from the contacts that started in it,
+// sorted by client so feedback goes out in the same order on every run
+pub fn jolts(world: &World<f32>, entities: &hecs::World, net_ids: &NetIds, before: &HashMap<NetId, Vector2<f32>>) -> Vec<(ClientId, f32)> {
+    let mut hardest: HashMap<ClientId, f32> = HashMap::new();
+    for contact in world.contact_events() {
+        if let ContactEvent::Started(handle_a, handle_b) = contact {
+            for handle in [*handle_a, *handle_b].iter() {
+                let id = match net_ids.by_collider(*handle) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                let owner = net_ids.entity(id).and_then(|entity| entities.get::<Owner>(entity).ok().map(|owner| owner.0));
+                if let Some(owner) = owner {
+                    let impulse = impact_impulse(world, entities, net_ids, before, id);
+                    let entry = hardest.entry(owner).or_insert(0.0);
+                    *entry = entry.max(impulse);
+                }
+            }
+        }
+    }
+
+    let mut jolts: Vec<(ClientId, f32)> = hardest.into_iter().collect();
+    jolts.sort_by_key(|(client, _)| *client);
+    jolts
+}
+
+// contacts started hard enough for clients to play something, unlike `collect_contacts` the level
+// blocks count, `surface` gives the material of any collider, `limiter` keeps one per material pair
+pub fn impacts(
+    world: &World<f32>,
+    entities: &hecs::World,
+    net_ids: &NetIds,
+    before: &HashMap<NetId, Vector2<f32>>,
+    surface: impl Fn(ColliderHandle) -> u8,
+    min_impulse: f32,
+    limiter: &mut ImpactLimiter,
+    tick: u64,
+    events: &mut Vec<Event>,
+) {
+    for contact in world.contact_events() {
+        if let ContactEvent::Started(handle_a, handle_b) = contact {
+            let impulse = |handle| net_ids.by_collider(handle).map_or(0.0, |id| impact_impulse(world, entities, net_ids, before, id));
+            let impulse = impulse(*handle_a).max(impulse(*handle_b));
+            if impulse < min_impulse {
+                continue;
+            }
+            // solved away during the step, there's no point to play it at
+            let point = match contact_point(world, *handle_a, *handle_b) {
+                Some((point, _)) => point,
+                None => continue,
+            };
+
+            let (material_a, material_b) = (surface(*handle_a), surface(*handle_b));
+            if limiter.allow(tick, material_a, material_b) {
+                events.push(Event::ImpactFx { material_a, material_b, impulse, point });
+            }
+        }
+    }
+}
+
+// contact events only live until the next step, contacts with unreplicated colliders are skipped
+pub fn collect_contacts(world: &World<f32>, entities: &hecs::World, net_ids: &NetIds, before: &HashMap<NetId, Vector2<f32>>, events: &mut Vec<Event>) {
+    for contact in world.contact_events() {
+        match contact {
+            ContactEvent::Started(handle_a, handle_b) => {
+                if let (Some(a), Some(b)) = (net_ids.by_collider(*handle_a), net_ids.by_collider(*handle_b)) {
+                    // a contact that was already solved away during the step has no manifold left
+                    let (point, normal) = contact_point(world, *handle_a, *handle_b).unwrap_or((Point2::origin(), na::zero()));
+                    let impulse = impact_impulse(world, entities, net_ids, before, a)
+                        .max(impact_impulse(world, entities, net_ids, before, b));
+
+                    events.push(Event::ContactStarted { a, b, impulse, point, normal });
+                }
+            },
+            ContactEvent::Stopped(handle_a, handle_b) => {
+                if let (Some(a), Some(b)) = (net_ids.by_collider(*handle_a), net_ids.by_collider(*handle_b)) {
+                    events.push(Event::ContactStopped(a, b));
+                }
+            },
+        }
+    }
+}
+
+// broad-phase only, bounding boxes are enough for near misses and much cheaper than contacts,
+// `near` holds the pairs that were close after the previous pass
+pub fn proximity(world: &World<f32>, entities: &hecs::World, near: &mut HashSet<(NetId, NetId)>, events: &mut Vec<Event>) {
+    let mut query = entities.query::<(&PhysicsBody, &Replicated, &Proximity)>();
+    let mut boxes: Vec<_> = query.iter()
+        .filter_map(|(_, (physics_body, replicated, proximity))| {
+            let collider = world.collider(physics_body.collider)?;
+            Some((replicated.id, collider.shape().aabb(collider.position()).loosened(proximity.margin)))
+        })
+        .collect();
+    boxes.sort_by_key(|(id, _)| *id);
+
+    let mut close = HashSet::new();
+    for (i, (a, box_a)) in boxes.iter().enumerate() {
+        for (b, box_b) in boxes[i + 1..].iter() {
+            if box_a.intersects(box_b) {
+                close.insert((*a, *b));
+            }
+        }
+    }
+
+    // sorted so handlers see the pairs in the same order on every run
+    let mut approached: Vec<(NetId, NetId)> = close.difference(near).cloned().collect();
+    let mut separated: Vec<(NetId, NetId)> = near.difference(&close).cloned().collect();
+    approached.sort();
+    separated.sort();
+    events.extend(approached.into_iter().map(|(a, b)| Event::Approached { a, b }));
+    events.extend(separated.into_iter().map(|(a, b)| Event::Separated { a, b }));
+    *near = close;
+}
+
+// static entities can't leave, only bodies are checked
+pub fn out_of_bounds(world: &World<f32>, entities: &hecs::World, min: &Vector2<f32>, max: &Vector2<f32>) -> Vec<Entity> {
+    entities.query::<&PhysicsBody>()
+        .iter()
+        .filter(|(_, physics_body)| {
+            world.rigid_body(physics_body.body).map_or(false, |rigid_body| {
+                let position = rigid_body.position().translation.vector;
+                position.x < min.x || position.y < min.y || position.x > max.x || position.y > max.y
+            })
+        })
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+// counts down every tick, returns the entities that can be destroyed again
+pub fn wear_off_invulnerability(entities: &hecs::World) -> Vec<Entity> {
+    let mut worn_off = vec![];
+    for (entity, invulnerable) in entities.query::<&mut Invulnerable>().iter() {
+        invulnerable.0 = invulnerable.0.saturating_sub(1);
+        if invulnerable.0 == 0 {
+            worn_off.push(entity);
+        }
+    }
+    worn_off
+}
+
+// entities out of health, the caller despawns them
+pub fn dead(entities: &hecs::World) -> Vec<Entity> {
+    entities.query::<(&Health, Option<&Invulnerable>)>()
+        .iter()
+        .filter(|(_, (health, invulnerable))| health.0 <= 0.0 && invulnerable.is_none())
+        .map(|(entity, _)| entity)
+        .collect()
+}
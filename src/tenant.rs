@@ -0,0 +1,43 @@
+use sha2::{Digest, Sha256};
+
+// a customer hosting its rooms on a shared server, the rooms whose name starts with `prefix` are
+// its own and its api key only reaches those, only a digest of the key is kept since the config
+// ends up in the input logs
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub prefix: String,
+    key_sha256: Vec<u8>,
+}
+
+impl Tenant {
+    pub fn new(name: &str, prefix: &str, key_sha256: &str) -> Result<Tenant, String> {
+        let key_sha256 = hex::decode(key_sha256)
+            .ok()
+            .filter(|digest| digest.len() == 32)
+            .ok_or_else(|| format!("tenant {} key_sha256 must be 64 hex digits", name))?;
+
+        Ok(Tenant { name: name.to_string(), prefix: prefix.to_string(), key_sha256 })
+    }
+
+    pub fn owns(&self, room: &str) -> bool {
+        room.starts_with(&self.prefix)
+    }
+
+    // every byte is compared so the time taken doesn't tell how much of the digest matched
+    pub fn accepts(&self, key: &str) -> bool {
+        let digest = Sha256::digest(key.as_bytes());
+        digest.iter().zip(self.key_sha256.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+// prefixes don't overlap, checked with the config, so a room has one owner at most
+pub fn owner<'t>(tenants: &'t [Tenant], room: &str) -> Option<&'t Tenant> {
+    tenants.iter().find(|tenant| tenant.owns(room))
+}
+
+// `scope` is the tenant an admin operation comes from, `None` for the operator who reaches every
+// room, a tenant only reaches the rooms it owns
+pub fn reaches(scope: Option<&str>, owner: Option<&str>) -> bool {
+    scope.map_or(true, |scope| owner == Some(scope))
+}
@@ -0,0 +1,20 @@
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::shape::{Polyline, ShapeHandle};
+use nphysics2d::object::{BodyHandle, Material};
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+
+// a static polyline through `points`, the collider sits on the first point and the returned
+// points, the ones clients draw, are relative to it
+pub fn build(world: &mut World<f32>, margin: f32, points: &[[f32; 2]]) -> (PhysicsBody, Vec<Point2<f32>>) {
+    let origin = Vector2::new(points[0][0], points[0][1]);
+    let relative: Vec<Point2<f32>> = points.iter()
+        .map(|point| Point2::new(point[0] - origin.x, point[1] - origin.y))
+        .collect();
+
+    let shape = ShapeHandle::new(Polyline::new(relative.clone()));
+    let collider = world.add_collider(margin, shape, BodyHandle::ground(), Isometry2::new(origin, 0.0), Material::new(1.0, 0.5));
+
+    (PhysicsBody { collider, body: BodyHandle::ground() }, relative)
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+
+use crate::config::{Config, ConfigError};
+use crate::golden::{self, GoldenError};
+use crate::level::Level;
+use crate::protocol::{AdminCommand, ClientId, ClientMessage, Command, EntityState, Event, Frame, NetId, PROTOCOL_VERSION, Role, ServerMessage};
+use crate::room::Room;
+
+const ROOM: &str = "test";
+
+// a room driven by hand for integration tests of gameplay: no socket, no thread, no clock, every
+// tick is the same fixed step, events are read the way a spectator would get them
+pub struct TestWorld {
+    room: Room,
+    observer: Receiver<Frame>,
+    // what the room sends to joined clients, drained so it doesn't pile up
+    clients: HashMap<ClientId, Receiver<Frame>>,
+    sequences: HashMap<ClientId, u32>,
+    events: Vec<Event>,
+}
+
+impl TestWorld {
+    pub fn new(config: Config, level: Level) -> TestWorld {
+        let mut config = config;
+        config.deterministic = true;
+        config.check_invariants = true;
+
+        let mut room = Room::new(ROOM, Arc::new(config), Arc::new(level), None);
+        room.stop_recording();
+
+        let (tx, observer) = mpsc::channel();
+        room.join(String::from("observer"), Role::Spectator, PROTOCOL_VERSION, &[], tx);
+
+        let mut world = TestWorld {
+            room,
+            observer,
+            clients: HashMap::new(),
+            sequences: HashMap::new(),
+            events: vec![],
+        };
+        world.collect();
+        world
+    }
+
+    pub fn with_defaults() -> TestWorld {
+        TestWorld::new(Config::defaults(), Level::defaults())
+    }
+
+    pub fn from_sources(config: &str, level: &str) -> Result<TestWorld, ConfigError> {
+        Ok(TestWorld::new(Config::parse(config)?, Level::parse(level)?))
+    }
+
+    // returns the client and, for players, the entity it controls
+    pub fn join(&mut self, player: &str, role: Role) -> (ClientId, Option<NetId>) {
+        let (tx, rx) = mpsc::channel();
+        let client = self.room.join(player.to_string(), role, PROTOCOL_VERSION, &[], tx);
+        self.clients.insert(client, rx);
+        self.collect();
+
+        (client, self.room.client_entity(client))
+    }
+
+    pub fn leave(&mut self, client: ClientId) {
+        self.room.handle(ClientMessage::Leave { client });
+    }
+
+    // applied on the next step, like a command received between two ticks
+    pub fn command(&mut self, client: ClientId, command: Command) {
+        let sequence = self.sequences.entry(client).or_insert(0);
+        *sequence += 1;
+        self.room.handle(ClientMessage::Command { client, sequence: *sequence, command });
+    }
+
+    pub fn admin(&mut self, command: AdminCommand) {
+        self.room.handle(ClientMessage::Admin { room: String::from(ROOM), scope: None, command });
+    }
+
+    pub fn step(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            if self.room.has_ended() {
+                break;
+            }
+            self.room.tick();
+            self.collect();
+        }
+    }
+
+    pub fn entity(&self, id: NetId) -> Option<EntityState> {
+        self.room.states().iter().find(|state| state.id == id).cloned()
+    }
+
+    pub fn entities(&self) -> Arc<Vec<EntityState>> {
+        self.room.states()
+    }
+
+    // compares the current state with a golden file, see `golden::check`
+    pub fn check_golden(&self, path: &str, tolerance: f32) -> Result<(), GoldenError> {
+        golden::check(path, &golden::summary(&self.room.states()), tolerance)
+    }
+
+    // every event since the last drain, in the order the room emitted them
+    pub fn drain_events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+
+    pub fn room(&mut self) -> &mut Room {
+        &mut self.room
+    }
+
+    fn collect(&mut self) {
+        while let Ok(frame) = self.observer.try_recv() {
+            if let Ok(ServerMessage::Events(events)) = frame.decode() {
+                self.events.extend(events);
+            }
+        }
+        for rx in self.clients.values() {
+            while rx.try_recv().is_ok() {}
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::level::{BlockDefinition, TilemapDefinition};
+
+const SOLID: char = '#';
+const EMPTY: char = '.';
+
+pub fn is_tile(tile: char) -> bool {
+    tile == SOLID || tile == EMPTY
+}
+
+// the solid tiles as few blocks as it takes: every rectangle grows right then down as far as
+// the tiles are solid and not taken yet, blocks stay within `max_size` so they fit a chunk
+pub fn bake(tilemap: &TilemapDefinition, max_size: f32) -> Vec<BlockDefinition> {
+    let size = tilemap.tile_size;
+    let max_tiles = ((max_size / size) as usize).max(1);
+    let rows: Vec<Vec<bool>> = tilemap.rows.iter().map(|row| row.chars().map(|tile| tile == SOLID).collect()).collect();
+    let mut taken: Vec<Vec<bool>> = rows.iter().map(|row| vec![false; row.len()]).collect();
+    let free = |taken: &Vec<Vec<bool>>, row: usize, column: usize| rows[row].get(column) == Some(&true) && !taken[row][column];
+
+    let mut blocks = vec![];
+    for row in 0..rows.len() {
+        for column in 0..rows[row].len() {
+            if !free(&taken, row, column) {
+                continue;
+            }
+
+            let mut width = 1;
+            while width < max_tiles && free(&taken, row, column + width) {
+                width += 1;
+            }
+            let mut height = 1;
+            while height < max_tiles && row + height < rows.len() && (column..column + width).all(|at| free(&taken, row + height, at)) {
+                height += 1;
+            }
+
+            for taken_row in taken[row..row + height].iter_mut() {
+                for tile in taken_row[column..column + width].iter_mut() {
+                    *tile = true;
+                }
+            }
+            blocks.push(BlockDefinition {
+                position: [
+                    tilemap.origin[0] + (column as f32 + width as f32 / 2.0) * size,
+                    tilemap.origin[1] - (row as f32 + height as f32 / 2.0) * size,
+                ],
+                half_extents: [width as f32 * size / 2.0, height as f32 * size / 2.0],
+                layer: tilemap.layer.clone(),
+                material: tilemap.material.clone(),
+                surface: 0,
+            });
+        }
+    }
+    blocks
+}
@@ -0,0 +1,29 @@
+use crate::level::{TimelineAction, TimelineEntry};
+
+// the level timeline played back by a room, entries are consumed in tick order
+pub struct Timeline {
+    entries: Vec<TimelineEntry>,
+    next: usize,
+}
+
+impl Timeline {
+    pub fn new(entries: &[TimelineEntry]) -> Timeline {
+        let mut entries = entries.to_vec();
+        // stable, so entries of the same tick keep the order of the level file
+        entries.sort_by_key(|entry| entry.tick);
+
+        Timeline {
+            entries,
+            next: 0,
+        }
+    }
+
+    // everything planned up to `tick` that hasn't run yet
+    pub fn due(&mut self, tick: u64) -> Vec<TimelineAction> {
+        let start = self.next;
+        while self.next < self.entries.len() && self.entries[self.next].tick <= tick {
+            self.next += 1;
+        }
+        self.entries[start..self.next].iter().map(|entry| entry.action.clone()).collect()
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+// timers the match state machine owns, host timers can't use these names
+pub const COUNTDOWN: &str = "countdown";
+pub const MATCH: &str = "match";
+
+// named countdowns started by the host, for cooldowns and anything clients display
+#[derive(Default)]
+pub struct Timers {
+    remaining: BTreeMap<String, u64>,
+}
+
+impl Timers {
+    // restarts the timer if it's already running
+    pub fn start(&mut self, name: String, ticks: u64) {
+        self.remaining.insert(name, ticks);
+    }
+
+    pub fn cancel(&mut self, name: &str) -> bool {
+        self.remaining.remove(name).is_some()
+    }
+
+    // called once per tick, returns the timers that ran out
+    pub fn tick(&mut self) -> Vec<String> {
+        for remaining in self.remaining.values_mut() {
+            *remaining = remaining.saturating_sub(1);
+        }
+
+        let expired: Vec<String> = self.remaining.iter()
+            .filter(|(_, remaining)| **remaining == 0)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in expired.iter() {
+            self.remaining.remove(name);
+        }
+        expired
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &u64)> {
+        self.remaining.iter()
+    }
+}
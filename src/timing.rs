@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// the order is the one a tick runs them in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    // client messages handled since the previous tick
+    Commands,
+    Step,
+    // contacts, gameplay handlers, despawns and everything else that reacts to the step
+    Events,
+    // reading the world into the snapshot and filtering what each client gets
+    Replication,
+    Encode,
+    Send,
+}
+
+pub const PHASES: [Phase; 6] = [Phase::Commands, Phase::Step, Phase::Events, Phase::Replication, Phase::Encode, Phase::Send];
+
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    pub last: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+// what each phase took over the last `window` ticks, percentiles are only sorted out when asked
+pub struct PhaseTimings {
+    window: usize,
+    current: [Duration; 6],
+    history: Vec<VecDeque<Duration>>,
+}
+
+impl PhaseTimings {
+    pub fn new(window: usize) -> PhaseTimings {
+        PhaseTimings {
+            window,
+            current: [Duration::default(); 6],
+            history: PHASES.iter().map(|_| VecDeque::with_capacity(window)).collect(),
+        }
+    }
+
+    // a phase can be timed in several pieces within a tick
+    pub fn add(&mut self, phase: Phase, duration: Duration) {
+        self.current[phase as usize] += duration;
+    }
+
+    pub fn end_tick(&mut self) {
+        for (history, duration) in self.history.iter_mut().zip(self.current.iter_mut()) {
+            if history.len() == self.window {
+                history.pop_front();
+            }
+            history.push_back(*duration);
+            *duration = Duration::default();
+        }
+    }
+
+    pub fn report(&self) -> Vec<PhaseReport> {
+        PHASES.iter().map(|phase| {
+            let history = &self.history[*phase as usize];
+            let mut sorted: Vec<Duration> = history.iter().cloned().collect();
+            sorted.sort();
+            let percentile = |p: f32| if sorted.is_empty() {
+                Duration::default()
+            } else {
+                sorted[((sorted.len() - 1) as f32 * p).round() as usize]
+            };
+
+            PhaseReport {
+                phase: *phase,
+                last: history.back().cloned().unwrap_or_default(),
+                p50: percentile(0.5),
+                p90: percentile(0.9),
+                p99: percentile(0.99),
+            }
+        }).collect()
+    }
+
+    // the phase that took the longest last tick
+    pub fn slowest(&self) -> Option<(Phase, Duration)> {
+        PHASES.iter()
+            .filter_map(|phase| self.history[*phase as usize].back().map(|duration| (*phase, *duration)))
+            .max_by_key(|(_, duration)| *duration)
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+
+use crate::schema::{self, Field, Schema, Type};
+
+// typescript interfaces and a decoder for the messages of the schema, exported with
+// SERVER_PHYSIC_TYPESCRIPT=<file>, u64 become bigints and unions objects with a `type`
+
+const READER: &str = r#"
+export class DecodeError extends Error {}
+
+export class Reader {
+    private view: DataView;
+    private offset = 0;
+
+    constructor(bytes: Uint8Array, readonly version: number) {
+        this.view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+    }
+
+    private take(len: number): number {
+        if (this.offset + len > this.view.byteLength) {
+            throw new DecodeError("unexpected end of frame");
+        }
+        const offset = this.offset;
+        this.offset += len;
+        return offset;
+    }
+
+    u8(): number { return this.view.getUint8(this.take(1)); }
+    u16(): number { return this.view.getUint16(this.take(2), true); }
+    u32(): number { return this.view.getUint32(this.take(4), true); }
+    u64(): bigint { return this.view.getBigUint64(this.take(8), true); }
+    i32(): number { return this.view.getInt32(this.take(4), true); }
+    f32(): number { return this.view.getFloat32(this.take(4), true); }
+
+    string(): string {
+        const len = this.u16();
+        const offset = this.take(len);
+        return new TextDecoder("utf-8", { fatal: true }).decode(new Uint8Array(this.view.buffer, this.view.byteOffset + offset, len));
+    }
+
+    bytes(): Uint8Array {
+        const len = this.u16();
+        const offset = this.take(len);
+        return new Uint8Array(this.view.buffer, this.view.byteOffset + offset, len).slice();
+    }
+
+    option<T>(read: () => T): T | null {
+        const tag = this.u8();
+        if (tag === 0) return null;
+        if (tag === 1) return read();
+        throw new DecodeError(`unknown tag ${tag}`);
+    }
+
+    list<T>(len: number, read: () => T): T[] {
+        const items: T[] = [];
+        for (let i = 0; i < len; i++) {
+            items.push(read());
+        }
+        return items;
+    }
+}
+"#;
+
+fn ts_type(ty: &Type) -> String {
+    match ty {
+        Type::U64 => String::from("bigint"),
+        Type::U8 | Type::U16 | Type::U32 | Type::I32 | Type::F32 | Type::Id => String::from("number"),
+        Type::Bool => String::from("boolean"),
+        Type::String => String::from("string"),
+        Type::Bytes => String::from("Uint8Array"),
+        Type::Flags { flags } => format!("{{ {} }}", flags.iter().map(|flag| format!("{}: boolean;", flag)).collect::<Vec<_>>().join(" ")),
+        Type::Option { of } => format!("{} | null", ts_type(of)),
+        Type::List { of, .. } => match of {
+            Type::Option { .. } | Type::Flags { .. } => format!("({})[]", ts_type(of)),
+            _ => format!("{}[]", ts_type(of)),
+        },
+        Type::Named { name } => name.to_string(),
+    }
+}
+
+// an expression reading `ty` from the reader `r`
+fn read(ty: &Type) -> String {
+    match ty {
+        Type::U8 => String::from("r.u8()"),
+        Type::U16 => String::from("r.u16()"),
+        Type::U32 | Type::Id => String::from("r.u32()"),
+        Type::U64 => String::from("r.u64()"),
+        Type::I32 => String::from("r.i32()"),
+        Type::F32 => String::from("r.f32()"),
+        Type::Bool => String::from("r.u8() !== 0"),
+        Type::String => String::from("r.string()"),
+        Type::Bytes => String::from("r.bytes()"),
+        Type::Flags { flags } => format!(
+            "((flags: number) => ({{ {} }}))(r.u8())",
+            flags.iter().enumerate().map(|(i, flag)| format!("{}: (flags & {}) !== 0", flag, 1 << i)).collect::<Vec<_>>().join(", "),
+        ),
+        Type::Option { of } => format!("r.option(() => {})", read(of)),
+        Type::List { len, of } => format!("r.list({}, () => {})", read(len), read(of)),
+        Type::Named { name } => format!("read{}(r)", name),
+    }
+}
+
+fn fields(fields: &[Field]) -> String {
+    fields.iter().map(|field| format!(" {}: {};", field.name, ts_type(&field.ty))).collect()
+}
+
+fn reads(fields: &[Field]) -> String {
+    fields.iter().map(|field| format!(", {}: {}", field.name, read(&field.ty))).collect()
+}
+
+pub fn generate(schema: &Schema) -> String {
+    let mut out = String::new();
+    writeln!(out, "// generated by the server for protocol version {}, don't edit", schema.version).unwrap();
+    writeln!(out, "// decodes messages once decompressed, see `Frame` on the server").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "export const PROTOCOL_VERSION = {};", schema.version).unwrap();
+    writeln!(out, "export const MIN_PROTOCOL_VERSION = {};", schema.min_version).unwrap();
+    out.push_str(READER);
+
+    for definition in schema.enums {
+        writeln!(out).unwrap();
+        writeln!(out, "export enum {} {{", definition.name).unwrap();
+        for (i, value) in definition.values.iter().enumerate() {
+            writeln!(out, "    {} = {},", value, i).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "function read{}(r: Reader): {} {{", definition.name, definition.name).unwrap();
+        writeln!(out, "    const value = r.u8();").unwrap();
+        writeln!(out, "    if (value >= {}) throw new DecodeError(`unknown tag ${{value}}`);", definition.values.len()).unwrap();
+        writeln!(out, "    return value as {};", definition.name).unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    for definition in schema.structs {
+        writeln!(out).unwrap();
+        writeln!(out, "export interface {} {{{} }}", definition.name, fields(definition.fields)).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "function read{}(r: Reader): {} {{", definition.name, definition.name).unwrap();
+        writeln!(out, "    return {{ {} }};", reads(definition.fields).trim_start_matches(", ")).unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    for definition in schema.unions {
+        writeln!(out).unwrap();
+        writeln!(out, "export type {} =", definition.name).unwrap();
+        for variant in definition.variants {
+            writeln!(out, "    | {{ type: \"{}\";{} }}", variant.name, fields(variant.fields)).unwrap();
+        }
+        writeln!(out, "    ;").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "function read{}(r: Reader): {} {{", definition.name, definition.name).unwrap();
+        writeln!(out, "    const tag = r.u8();").unwrap();
+        writeln!(out, "    switch (tag) {{").unwrap();
+        for variant in definition.variants {
+            writeln!(out, "        case {}:", variant.tag).unwrap();
+            if variant.since > schema.min_version {
+                writeln!(out, "            if (r.version < {}) break;", variant.since).unwrap();
+            }
+            writeln!(out, "            return {{ type: \"{}\"{} }};", variant.name, reads(variant.fields)).unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "    throw new DecodeError(`unknown tag ${{tag}}`);").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "// `version` is the one we joined with, the server never sends anything newer").unwrap();
+    writeln!(out, "export function decode(bytes: Uint8Array, version: number = PROTOCOL_VERSION): ServerMessage {{").unwrap();
+    writeln!(out, "    if (version < MIN_PROTOCOL_VERSION || version > PROTOCOL_VERSION) {{").unwrap();
+    writeln!(out, "        throw new DecodeError(`unsupported protocol version ${{version}}`);").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    return readServerMessage(new Reader(bytes, version));").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    writeln!(out).unwrap();
+    writeln!(out, "// commands are laid out the same in input logs").unwrap();
+    writeln!(out, "export function decodeCommand(bytes: Uint8Array): Command {{").unwrap();
+    writeln!(out, "    return readCommand(new Reader(bytes, PROTOCOL_VERSION));").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+pub fn export(path: &str) -> io::Result<()> {
+    fs::write(path, generate(&schema::schema()))
+}
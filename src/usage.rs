@@ -0,0 +1,51 @@
+use std::mem;
+
+use na::Point2;
+
+use crate::protocol::EntityState;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoomUsage {
+    pub entities: usize,
+    // the bodies of entities, the extra ones of ragdolls, soft bodies and vehicles aren't counted
+    pub bodies: usize,
+    pub colliders: usize,
+    // waiting in outboxes and in the backlogs of dropped clients
+    pub queued_messages: usize,
+    // what the snapshot buffer holds on to, estimated from its capacity
+    pub snapshot_bytes: usize,
+}
+
+// going over one only logs a warning, early enough to act before the process runs out of memory,
+// 0 turns a limit off
+#[derive(Debug, Clone, Copy)]
+pub struct SoftLimits {
+    pub max_bodies: usize,
+    pub max_colliders: usize,
+    pub max_queued_messages: usize,
+    pub max_snapshot_bytes: usize,
+}
+
+impl SoftLimits {
+    // the name, value and limit of everything over its limit
+    pub fn exceeded(&self, usage: &RoomUsage) -> Vec<(&'static str, usize, usize)> {
+        let checks = [
+            ("bodies", usage.bodies, self.max_bodies),
+            ("colliders", usage.colliders, self.max_colliders),
+            ("queued_messages", usage.queued_messages, self.max_queued_messages),
+            ("snapshot_bytes", usage.snapshot_bytes, self.max_snapshot_bytes),
+        ];
+
+        checks.iter().cloned().filter(|(_, value, limit)| *limit > 0 && value > limit).collect()
+    }
+}
+
+pub fn snapshot_bytes(states: &Vec<EntityState>) -> usize {
+    let inner: usize = states.iter().map(|state| {
+        state.wheels.capacity() * mem::size_of::<f32>()
+            + state.limbs.capacity() * mem::size_of_val(&state.position)
+            + (state.outline.capacity() + state.rope.capacity()) * mem::size_of::<Point2<f32>>()
+    }).sum();
+
+    states.capacity() * mem::size_of::<EntityState>() + inner
+}
@@ -0,0 +1,83 @@
+use na::Vector2;
+
+use crate::protocol::{Command, CommandKind, RejectReason};
+
+#[derive(Debug, Clone)]
+pub struct EntityLimits {
+    pub max_impulse: f32,
+    pub max_speed: f32,
+    pub max_dash_distance: f32,
+    pub allowed: Vec<CommandKind>,
+}
+
+// `velocity` and `mass` are the current state of the commanded body
+pub fn validate(command: &Command, limits: &EntityLimits, velocity: Vector2<f32>, mass: f32) -> Result<(), RejectReason> {
+    if !limits.allowed.contains(&command.kind()) {
+        return Err(RejectReason::CommandNotAllowed);
+    }
+
+    match command {
+        Command::Impulse { impulse, .. } => {
+            if !impulse.x.is_finite() || !impulse.y.is_finite() {
+                return Err(RejectReason::Malformed);
+            }
+
+            if impulse.norm() > limits.max_impulse {
+                return Err(RejectReason::ImpulseTooLarge);
+            }
+
+            if mass > 0.0 && (velocity + impulse / mass).norm() > limits.max_speed {
+                return Err(RejectReason::SpeedTooHigh);
+            }
+        },
+        Command::Move { direction, .. } => {
+            if !direction.is_finite() || direction.abs() > 1.0 {
+                return Err(RejectReason::Malformed);
+            }
+        },
+        Command::Jump { .. } => {},
+        Command::Dash { direction, distance, duration, .. } => {
+            let finite = direction.x.is_finite() && direction.y.is_finite() && distance.is_finite() && duration.is_finite();
+            if !finite || direction.norm() == 0.0 || *distance <= 0.0 || *duration <= 0.0 {
+                return Err(RejectReason::Malformed);
+            }
+
+            if *distance > limits.max_dash_distance {
+                return Err(RejectReason::DashTooLong);
+            }
+
+            // a dash is a burst of speed but still bound by the entity speed limit
+            if distance / duration > limits.max_speed {
+                return Err(RejectReason::SpeedTooHigh);
+            }
+        },
+        Command::Grapple { direction, .. } | Command::FireHitscan { dir: direction, .. } => {
+            if !direction.x.is_finite() || !direction.y.is_finite() || direction.norm() == 0.0 {
+                return Err(RejectReason::Malformed);
+            }
+        },
+        Command::Reel { speed, .. } => {
+            if !speed.is_finite() {
+                return Err(RejectReason::Malformed);
+            }
+
+            if speed.abs() > limits.max_speed {
+                return Err(RejectReason::SpeedTooHigh);
+            }
+        },
+        Command::Release { .. } => {},
+        Command::Swing { direction, radius, angle, .. } => {
+            let finite = direction.x.is_finite() && direction.y.is_finite() && radius.is_finite() && angle.is_finite();
+            if !finite || direction.norm() == 0.0 || *radius <= 0.0 || *angle <= 0.0 {
+                return Err(RejectReason::Malformed);
+            }
+        },
+        Command::Drive { throttle, steer, .. } => {
+            if !throttle.is_finite() || !steer.is_finite() || throttle.abs() > 1.0 || steer.abs() > 1.0 {
+                return Err(RejectReason::Malformed);
+            }
+        },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use na::{Isometry2, Point2, Vector2};
+use ncollide2d::shape::{Ball, Cuboid, ShapeHandle};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::joint::{ConstraintHandle, RevoluteConstraint};
+use nphysics2d::object::{BodyHandle, ColliderHandle, Material};
+use nphysics2d::volumetric::Volumetric;
+use nphysics2d::world::World;
+
+use crate::components::PhysicsBody;
+
+pub const CHASSIS_HALF_EXTENTS: (f32, f32) = (2.5, 0.6);
+const WHEEL_RADIUS: f32 = 0.8;
+const CHASSIS_DENSITY: f32 = 1.0;
+const WHEEL_DENSITY: f32 = 0.5;
+
+// a wheel is a body of its own pinned under the chassis, it isn't replicated as an entity
+#[derive(Debug, Clone, Copy)]
+pub struct Wheel {
+    pub body: BodyHandle,
+    pub collider: ColliderHandle,
+    pub joint: ConstraintHandle,
+    // only the driven wheels get the motor torque
+    pub driven: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+    pub wheels: Vec<Wheel>,
+    // both between -1 and 1, set by the owner's `Drive` commands
+    pub throttle: f32,
+    pub steer: f32,
+    // wheel speed at full throttle, in radians per second
+    pub max_wheel_speed: f32,
+    // caps how fast the wheels reach that speed, so a heavy load slows the vehicle down
+    pub motor_torque: f32,
+    // the chassis is tilted by steering, it's how the vehicle is balanced over bumps and in the air
+    pub lean_torque: f32,
+}
+
+impl Vehicle {
+    fn new(wheels: Vec<Wheel>) -> Vehicle {
+        Vehicle {
+            wheels,
+            throttle: 0.0,
+            steer: 0.0,
+            max_wheel_speed: 25.0,
+            motor_torque: 40.0,
+            lean_torque: 30.0,
+        }
+    }
+
+    // every body and joint behind the chassis, for the room to remove along with it
+    pub fn parts(&self) -> (Vec<BodyHandle>, Vec<ConstraintHandle>) {
+        (self.wheels.iter().map(|wheel| wheel.body).collect(), self.wheels.iter().map(|wheel| wheel.joint).collect())
+    }
+}
+
+fn add_body(world: &mut World<f32>, margin: f32, shape: ShapeHandle<f32>, density: f32, position: Isometry2<f32>, groups: CollisionGroups) -> PhysicsBody {
+    let inertia = shape.inertia(density);
+    let center_of_mass = shape.center_of_mass();
+    let body = world.add_rigid_body(position, inertia, center_of_mass);
+    let collider = world.add_collider(margin, shape, body, Isometry2::identity(), Material::new(0.0, 1.0));
+    world.collision_world_mut().set_collision_groups(collider, groups);
+
+    PhysicsBody { collider, body }
+}
+
+// the chassis is what the room spawns as an entity, the wheels hang below it clear of its collider
+// so the two never push each other apart
+pub fn build(world: &mut World<f32>, margin: f32, position: Vector2<f32>, groups: CollisionGroups) -> (PhysicsBody, Vehicle) {
+    let (half_width, half_height) = CHASSIS_HALF_EXTENTS;
+    let chassis_shape = ShapeHandle::new(Cuboid::new(Vector2::new(half_width - margin, half_height - margin)));
+    let chassis = add_body(world, margin, chassis_shape, CHASSIS_DENSITY, Isometry2::new(position, 0.0), groups);
+
+    let wheel_shape = ShapeHandle::new(Ball::new(WHEEL_RADIUS - margin));
+    let axle_y = -half_height - WHEEL_RADIUS - margin * 2.0;
+    let wheels = [(-half_width * 0.7, true), (half_width * 0.7, false)].iter().map(|&(axle_x, driven)| {
+        let axle = Vector2::new(axle_x, axle_y);
+        let wheel = add_body(world, margin, wheel_shape.clone(), WHEEL_DENSITY, Isometry2::new(position + axle, 0.0), groups);
+        let joint = world.add_constraint(RevoluteConstraint::new(chassis.body, wheel.body, Point2::from(axle), Point2::origin()));
+
+        Wheel { body: wheel.body, collider: wheel.collider, joint, driven }
+    }).collect();
+
+    (chassis, Vehicle::new(wheels))
+}
+
+// runs before each step: the motors bring the driven wheels towards the throttle speed, as far as
+// their torque allows in one step, and steering spins the chassis the same way
+pub fn run_motors(world: &mut World<f32>, entities: &hecs::World) {
+    let timestep = world.timestep();
+
+    for (_, (physics_body, vehicle)) in entities.query::<(&PhysicsBody, &Vehicle)>().iter() {
+        // clockwise wheels drive the vehicle to the right
+        let target = -vehicle.throttle * vehicle.max_wheel_speed;
+        for wheel in vehicle.wheels.iter().filter(|wheel| wheel.driven) {
+            if let Some(rigid_body) = world.rigid_body_mut(wheel.body) {
+                let inertia = rigid_body.local_inertia().angular;
+                if inertia <= 0.0 {
+                    continue;
+                }
+
+                let current = rigid_body.velocity().angular;
+                let max_change = vehicle.motor_torque * timestep / inertia;
+                let change = (target - current).max(-max_change).min(max_change);
+                if change != 0.0 {
+                    rigid_body.set_angular_velocity(current + change);
+                    rigid_body.activate();
+                }
+            }
+        }
+
+        if vehicle.steer != 0.0 {
+            if let Some(rigid_body) = world.rigid_body_mut(physics_body.body) {
+                let inertia = rigid_body.local_inertia().angular;
+                if inertia > 0.0 {
+                    let angular = rigid_body.velocity().angular - vehicle.steer * vehicle.lean_torque * timestep / inertia;
+                    rigid_body.set_angular_velocity(angular);
+                    rigid_body.activate();
+                }
+            }
+        }
+    }
+}
+
+// wheel angles relative to the chassis, in the order the wheels were built
+pub fn wheel_angles(world: &World<f32>, physics_body: &PhysicsBody, vehicle: &Vehicle) -> Vec<f32> {
+    let chassis = match world.rigid_body(physics_body.body) {
+        Some(rigid_body) => rigid_body.position().rotation,
+        None => return vec![],
+    };
+
+    vehicle.wheels.iter()
+        .filter_map(|wheel| world.rigid_body(wheel.body))
+        .map(|rigid_body| chassis.angle_to(&rigid_body.position().rotation))
+        .collect()
+}
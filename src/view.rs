@@ -0,0 +1,80 @@
+use na::{Point2, Vector2};
+use ncollide2d::query::Ray;
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::world::World;
+
+use crate::protocol::EntityState;
+
+// what a client asks for when it joins, `None` for as much as the server gives
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ViewRequest {
+    // entities further than this from the client's own entity are left out of its snapshots
+    pub radius: Option<f32>,
+    // snapshots per second
+    pub rate: Option<u32>,
+}
+
+// what the client gets once the request went through the server caps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    pub radius: Option<f32>,
+    // ticks between snapshots, 1 or a whole number of keyframe intervals: skipping a tick would
+    // lose the changes of the snapshots in between, keyframes carry everything
+    pub interval: u64,
+    // entities hidden behind static geometry from the client's entity are left out, set by the
+    // server so a modified client can't see through walls
+    pub line_of_sight: bool,
+}
+
+impl Default for View {
+    fn default() -> View {
+        View { radius: None, interval: 1, line_of_sight: false }
+    }
+}
+
+impl View {
+    pub fn resolve(request: ViewRequest, max_radius: Option<f32>, line_of_sight: bool, tick_rate: u64, keyframe_interval: u64) -> View {
+        let radius = match (request.radius.filter(|radius| *radius > 0.0), max_radius) {
+            (Some(radius), Some(max)) => Some(radius.min(max)),
+            (radius, max) => radius.or(max),
+        };
+        let interval = match request.rate {
+            Some(rate) if u64::from(rate) < tick_rate => {
+                let ticks = tick_rate / u64::from(rate.max(1));
+                (ticks + keyframe_interval - 1) / keyframe_interval * keyframe_interval
+            },
+            _ => 1,
+        };
+
+        View { radius, interval, line_of_sight }
+    }
+
+    // whether snapshots are filtered around the client's entity
+    pub fn is_focused(&self) -> bool {
+        self.radius.is_some() || self.line_of_sight
+    }
+
+    pub fn wants(&self, tick: u64, keyframe: bool) -> bool {
+        self.interval == 1 || (keyframe && tick % self.interval == 0)
+    }
+
+    // `occluded` tells whether static geometry stands between two points, it's only asked about
+    // entities within the radius
+    pub fn sees(&self, focus: Vector2<f32>, state: &EntityState, occluded: impl Fn(Vector2<f32>, Vector2<f32>) -> bool) -> bool {
+        let position = state.position.translation.vector;
+        self.radius.map_or(true, |radius| (position - focus).norm() <= radius)
+            && !(self.line_of_sight && occluded(focus, position))
+    }
+}
+
+// static colliders, walls, blocks and terrain, crossing the segment, bodies never hide anything
+pub fn occluded(world: &World<f32>, from: Vector2<f32>, to: Vector2<f32>) -> bool {
+    let distance = (to - from).norm();
+    if !(distance > 0.0) {
+        return false;
+    }
+
+    let ray = Ray::new(Point2::from(from), (to - from) / distance);
+    world.collision_world().interferences_with_ray(&ray, &CollisionGroups::new())
+        .any(|(object, intersection)| intersection.toi < distance && object.data().body().is_ground())
+}
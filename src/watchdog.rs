@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{error, warn};
+
+use crate::timing::PhaseReport;
+use crate::usage::RoomUsage;
+
+#[derive(Debug, Clone)]
+pub struct RoomHealth {
+    // the tenant owning the room, health is only shown to its key and the operator
+    pub tenant: Option<String>,
+    // the last tick the room completed, and when
+    pub tick: u64,
+    pub beat_at: Instant,
+    pub stalled: bool,
+    // how many times the room was found stalled since it was created
+    pub stalls: u32,
+    // where the ticks and the memory of the room go, refreshed every few seconds
+    pub phases: Vec<PhaseReport>,
+    pub usage: RoomUsage,
+}
+
+// watches the rooms from its own thread, so a stalled tick loop is noticed while it's stuck,
+// the connection layer holds it too to serve the room health to admins
+pub struct Watchdog {
+    // follows the tick rate when the config is reloaded
+    max_stall: Mutex<Duration>,
+    rooms: Mutex<HashMap<String, RoomHealth>>,
+    // stalled rooms the tick loop hasn't picked up yet
+    flagged: Mutex<Vec<String>>,
+    // counts every stall of every room, for metrics
+    stalls: AtomicU64,
+}
+
+impl Watchdog {
+    pub fn new(max_stall: Duration) -> Arc<Watchdog> {
+        Arc::new(Watchdog {
+            max_stall: Mutex::new(max_stall),
+            rooms: Mutex::new(HashMap::new()),
+            flagged: Mutex::new(vec![]),
+            stalls: AtomicU64::new(0),
+        })
+    }
+
+    // called by every room once its tick is done
+    pub fn beat(&self, room: &str, tenant: Option<&str>, tick: u64) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let health = rooms.entry(room.to_string()).or_insert_with(|| RoomHealth {
+            tenant: tenant.map(String::from),
+            tick,
+            beat_at: Instant::now(),
+            stalled: false,
+            stalls: 0,
+            phases: vec![],
+            usage: RoomUsage::default(),
+        });
+
+        if health.stalled {
+            warn!(target: "watchdog", room, tenant = ?health.tenant, tick, stalled_for = ?health.beat_at.elapsed(), "room completed a tick after stalling");
+        }
+        health.tick = tick;
+        health.beat_at = Instant::now();
+        health.stalled = false;
+    }
+
+    pub fn refresh(&self, room: &str, phases: Vec<PhaseReport>, usage: RoomUsage) {
+        if let Some(health) = self.rooms.lock().unwrap().get_mut(room) {
+            health.phases = phases;
+            health.usage = usage;
+        }
+    }
+
+    pub fn forget(&self, room: &str) {
+        self.rooms.lock().unwrap().remove(room);
+    }
+
+    pub fn set_max_stall(&self, max_stall: Duration) {
+        *self.max_stall.lock().unwrap() = max_stall;
+    }
+
+    // flags the rooms that just went over `max_stall` without a beat
+    pub fn check(&self) {
+        let max_stall = *self.max_stall.lock().unwrap();
+        let mut rooms = self.rooms.lock().unwrap();
+
+        for (name, health) in rooms.iter_mut().filter(|(_, health)| !health.stalled && health.beat_at.elapsed() > max_stall) {
+            error!(target: "watchdog", room = %name, tenant = ?health.tenant, tick = health.tick, stalled_for = ?health.beat_at.elapsed(), "room stalled");
+            health.stalled = true;
+            health.stalls += 1;
+            self.stalls.fetch_add(1, Ordering::Relaxed);
+            self.flagged.lock().unwrap().push(name.clone());
+        }
+    }
+
+    // the rooms flagged since the last call, for the tick loop to restart once it's running again
+    pub fn take_flagged(&self) -> Vec<String> {
+        self.flagged.lock().unwrap().drain(..).collect()
+    }
+
+    pub fn report(&self) -> Vec<(String, RoomHealth)> {
+        let mut report: Vec<_> = self.rooms.lock().unwrap().iter().map(|(name, health)| (name.clone(), health.clone())).collect();
+        report.sort_by(|a, b| a.0.cmp(&b.0));
+        report
+    }
+
+    pub fn stalls(&self) -> u64 {
+        self.stalls.load(Ordering::Relaxed)
+    }
+
+    // checks a few times per allowed stall, the thread runs as long as the process
+    pub fn spawn(watchdog: Arc<Watchdog>) -> thread::JoinHandle<()> {
+        thread::spawn(move || loop {
+            let period = *watchdog.max_stall.lock().unwrap() / 4;
+            thread::sleep(period);
+            watchdog.check();
+        })
+    }
+}
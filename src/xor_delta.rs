@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use na::{Isometry2, Vector2};
+use nphysics2d::algebra::Velocity2;
+
+use crate::codec::DecodeError;
+use crate::protocol::{EntityState, NetId};
+
+// the cheapest snapshots we send, for clients on a tight bandwidth budget: every entity is quantized
+// to a fixed size record, the records are xored with the ones of a snapshot the client acked and the
+// zero runs, what didn't change, are run length encoded, only transforms and velocities make it,
+// the rest of the state comes with the regular keyframes
+
+// id, position and angle, linear and angular velocity
+pub const RECORD_SIZE: usize = 4 + 4 + 4 + 2 + 4 + 4 + 4;
+// positions and velocities in thousandths, angles in ten thousandths of a radian
+const UNIT: f32 = 1000.0;
+const ANGLE_UNIT: f32 = 10000.0;
+// a token byte with this bit set is a run of zeros, otherwise literal bytes follow it,
+// the other bits are the length minus one
+const ZERO_RUN: u8 = 0x80;
+const MAX_RUN: usize = 0x80;
+// snapshots sent to a client and kept until it acks one of them
+const MAX_PENDING: usize = 32;
+
+fn quantize_f32(value: f32, unit: f32) -> i32 {
+    (value * unit).round() as i32
+}
+
+// sorted by id so the records line up with the baseline as long as no entity came or went
+pub fn quantize(states: &[EntityState]) -> Vec<u8> {
+    let mut sorted: Vec<&EntityState> = states.iter().collect();
+    sorted.sort_by_key(|state| state.id);
+
+    let mut bytes = Vec::with_capacity(sorted.len() * RECORD_SIZE);
+    for state in sorted {
+        let translation = state.position.translation.vector;
+        bytes.extend_from_slice(&(state.id as u32).to_le_bytes());
+        bytes.extend_from_slice(&quantize_f32(translation.x, UNIT).to_le_bytes());
+        bytes.extend_from_slice(&quantize_f32(translation.y, UNIT).to_le_bytes());
+        bytes.extend_from_slice(&(quantize_f32(state.position.rotation.angle(), ANGLE_UNIT) as i16).to_le_bytes());
+        bytes.extend_from_slice(&quantize_f32(state.velocity.linear.x, UNIT).to_le_bytes());
+        bytes.extend_from_slice(&quantize_f32(state.velocity.linear.y, UNIT).to_le_bytes());
+        bytes.extend_from_slice(&quantize_f32(state.velocity.angular, UNIT).to_le_bytes());
+    }
+    bytes
+}
+
+fn i32_at(record: &[u8], at: usize) -> f32 {
+    i32::from_le_bytes([record[at], record[at + 1], record[at + 2], record[at + 3]]) as f32
+}
+
+pub fn dequantize(bytes: &[u8]) -> Result<Vec<(NetId, Isometry2<f32>, Velocity2<f32>)>, DecodeError> {
+    if bytes.len() % RECORD_SIZE != 0 {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    Ok(bytes.chunks(RECORD_SIZE).map(|record| {
+        let id = u32::from_le_bytes([record[0], record[1], record[2], record[3]]) as NetId;
+        let angle = i16::from_le_bytes([record[12], record[13]]) as f32 / ANGLE_UNIT;
+        let position = Isometry2::new(Vector2::new(i32_at(record, 4), i32_at(record, 8)) / UNIT, angle);
+        let velocity = Velocity2::new(Vector2::new(i32_at(record, 14), i32_at(record, 18)) / UNIT, i32_at(record, 22) / UNIT);
+        (id, position, velocity)
+    }).collect())
+}
+
+// `current` xored with `baseline` byte for byte, the shorter one padded with zeros
+pub fn encode(baseline: &[u8], current: &[u8]) -> Vec<u8> {
+    let xored: Vec<u8> = current.iter().enumerate().map(|(i, byte)| byte ^ baseline.get(i).cloned().unwrap_or(0)).collect();
+
+    let mut out = Vec::with_capacity(xored.len() / 4);
+    let mut i = 0;
+    while i < xored.len() {
+        let zeros = xored[i..].iter().take(MAX_RUN).take_while(|byte| **byte == 0).count();
+        if zeros > 0 {
+            out.push(ZERO_RUN | (zeros - 1) as u8);
+            i += zeros;
+            continue;
+        }
+
+        // literals stop at the first pair of zeros, a single zero is cheaper kept in
+        let mut len = 1;
+        while len < MAX_RUN && i + len < xored.len() && !(xored[i + len] == 0 && xored.get(i + len + 1) == Some(&0)) {
+            len += 1;
+        }
+        out.push((len - 1) as u8);
+        out.extend_from_slice(&xored[i..i + len]);
+        i += len;
+    }
+    out
+}
+
+// the `len` bytes `encode` was given as `current`
+pub fn decode(baseline: &[u8], len: usize, bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut xored = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < bytes.len() {
+        let token = bytes[i];
+        let run = (token & !ZERO_RUN) as usize + 1;
+        i += 1;
+        if token & ZERO_RUN != 0 {
+            xored.extend(std::iter::repeat(0).take(run));
+        } else {
+            let literal = bytes.get(i..i + run).ok_or(DecodeError::UnexpectedEnd)?;
+            xored.extend_from_slice(literal);
+            i += run;
+        }
+    }
+    if xored.len() != len {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    Ok(xored.iter().enumerate().map(|(i, byte)| byte ^ baseline.get(i).cloned().unwrap_or(0)).collect())
+}
+
+// what a client opted into deltas was sent and what it acked, deltas are taken against the
+// latest acked snapshot so a lost one never leaves it unable to decode the next
+#[derive(Default)]
+pub struct Baselines {
+    pending: VecDeque<(u64, Arc<Vec<u8>>)>,
+    acked: Option<(u64, Arc<Vec<u8>>)>,
+}
+
+impl Baselines {
+    pub fn sent(&mut self, tick: u64, quantized: Arc<Vec<u8>>) {
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((tick, quantized));
+    }
+
+    // acks for snapshots we no longer have or older than the baseline are ignored
+    pub fn ack(&mut self, tick: u64) {
+        if self.acked.as_ref().map_or(false, |(acked, _)| *acked >= tick) {
+            return;
+        }
+        if let Some(at) = self.pending.iter().position(|(sent, _)| *sent == tick) {
+            self.acked = self.pending.drain(..=at).last();
+        }
+    }
+
+    pub fn acked(&self) -> Option<(u64, &[u8])> {
+        self.acked.as_ref().map(|(tick, quantized)| (*tick, quantized.as_slice()))
+    }
+}
@@ -0,0 +1,54 @@
+extern crate nalgebra as na;
+extern crate server_physic;
+
+use na::Vector2;
+
+use server_physic::protocol::{Command, EntityKind, Event, MatchPhase, Role};
+use server_physic::testing::TestWorld;
+
+#[test]
+fn joining_player_is_announced_and_spawned() {
+    let mut world = TestWorld::with_defaults();
+    let (client, entity) = world.join("alice", Role::Player);
+    let entity = entity.expect("players get an entity");
+
+    world.step(1);
+    let events = world.drain_events();
+    assert!(events.iter().any(|event| match event {
+        Event::Joined { client: joined, entity: Some(spawned) } => *joined == client && *spawned == entity,
+        _ => false,
+    }));
+    assert!(events.iter().any(|event| match event {
+        Event::Spawned { entity: spawned, kind: EntityKind::Player, .. } => *spawned == entity,
+        _ => false,
+    }));
+
+    let state = world.entity(entity).expect("the player is replicated");
+    assert_eq!(state.owner, Some(client));
+}
+
+#[test]
+fn impulse_moves_the_player_once_the_match_runs() {
+    let mut world = TestWorld::with_defaults();
+    let (client, entity) = world.join("alice", Role::Player);
+    let entity = entity.expect("players get an entity");
+
+    // commands are refused during the countdown
+    world.step(70);
+    let events = world.drain_events();
+    assert!(events.iter().any(|event| match event {
+        Event::MatchPhaseChanged { phase } => *phase == MatchPhase::Playing,
+        _ => false,
+    }));
+
+    let before = world.entity(entity).expect("the player is replicated").position.translation.vector;
+    world.command(client, Command::Impulse { entity, impulse: Vector2::new(20.0, 0.0) });
+    world.step(10);
+    let after = world.entity(entity).expect("the player is still there").position.translation.vector;
+
+    assert!(after.x > before.x + 0.1, "moved from {:?} to {:?}", before, after);
+    assert!(world.drain_events().iter().all(|event| match event {
+        Event::InvariantViolated { .. } => false,
+        _ => true,
+    }));
+}